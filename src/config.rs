@@ -0,0 +1,59 @@
+use crate::logger;
+use crate::constants;
+use crate::vulkan::LeakPolicy;
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+// Startup settings read from a TOML file, missing entries keep their default and an unreadable file keeps them all.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub frames_in_flight: usize,
+    pub leak_policy: LeakPolicy,
+}
+
+impl Config {
+    
+//----
+    pub fn load<P:AsRef<Path>>(path:P) -> Self {
+        let path = path.as_ref();
+        
+        let parsed = fs::read_to_string(path)
+            .map_err(|error|error.to_string())
+            .and_then(|contents|toml::from_str::<Self>(&contents).map_err(|error|error.to_string()));
+        let mut holder = match parsed {
+            Ok(holder) => holder,
+            Err(error) => {
+                logger::various_log!("config",
+                    (logger::Warn, "could not load {:?}, using defaults: {}", path, error)
+                );
+                return Self::default();
+            }
+        };
+        
+        if !(constants::fif::MIN..=constants::fif::MAX).contains(&holder.frames_in_flight) {
+            logger::various_log!("config",
+                (logger::Warn, "frames_in_flight must be between {} and {}, got {}", constants::fif::MIN, constants::fif::MAX, holder.frames_in_flight)
+            );
+            holder.frames_in_flight = constants::fif::DEFAULT;
+        }
+        
+        logger::various_log!("config",
+            (logger::Debug, "{:?}", holder)
+        );
+        holder
+    }
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+        Self{
+            frames_in_flight: constants::fif::DEFAULT,
+            leak_policy: constants::DEFAULT_LEAK_POLICY,
+        }
+    }
+}
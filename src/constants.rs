@@ -15,12 +15,40 @@ use ash::extensions::khr::Synchronization2;
 use ash::extensions::khr::Swapchain;
 use ash::extensions::ext::DebugUtils;
 use ash::vk;
+use crate::vulkan::LeakPolicy;
 
-const FIF:usize = 2;
-#[allow(dead_code)]
+// Frames in flight are chosen at startup from the config file, within these bounds.
 pub mod fif {
-    pub const USIZE:usize = super::FIF as usize;
-    pub const U32:u32 = super::FIF as u32;
+    pub const DEFAULT:usize = 2;
+    pub const MIN:usize = 1;
+    pub const MAX:usize = 4;
+}
+
+pub const CONFIG_PATH:&str = "res/engine.toml";
+
+// Shaders are compiled from source when pipelines are built, the SPIR-V is cached under a hash of the source, its includes and defines.
+pub mod shaders {
+    pub const SOURCE_DIR:&str = "src/ssrc";
+    pub const CACHE_DIR:&str = "res/shaders/cache";
+}
+
+// Shader sources are polled while running, pipelines using a changed source or include are rebuilt.
+pub mod hot_reload {
+    use std::time::Duration;
+    
+    pub const ENABLED:bool = cfg!(debug_assertions);
+    pub const POLL_INTERVAL:Duration = Duration::from_millis(500);
+}
+
+// Sizes and bindings of the global descriptor indexing set, they must match input_structures.glsl.
+pub mod bindless {
+    pub const IMAGE_BINDING:u32 = 0;
+    pub const SAMPLER_BINDING:u32 = 1;
+    pub const BUFFER_BINDING:u32 = 2;
+    
+    pub const IMAGES:u32 = 4096;
+    pub const SAMPLERS:u32 = 64;
+    pub const BUFFERS:u32 = 1024;
 }
 
 const SC_MAX_IMAGES:usize = 8;
@@ -35,7 +63,10 @@ pub const SHADER_START:&CStr = cstr::cstr!(b"main");
 pub const WIDTH:u32 = 1200/16*16;
 pub const HEIGTH:u32 = 800/16*16;
 pub const VALIDATION:bool = true;
+pub const LAYOUT_VALIDATION:bool = cfg!(debug_assertions);
+pub const DESCRIPTOR_VALIDATION:bool = cfg!(debug_assertions);
 pub const LOGGING:bool = true;
+pub const DEFAULT_LEAK_POLICY:LeakPolicy = LeakPolicy::LogAndLeak;
 
 pub const LAYERS:[&'static str; 1] = ["VK_LAYER_KHRONOS_validation"];
 pub const EXTENSIONS:[&'static str; EXTENSIONS_LEN_PLUS_VAL] = extension_logic();
@@ -91,20 +122,68 @@ const fn extension_logic() -> [&'static str; EXTENSIONS_LEN_PLUS_VAL] {
 }
 
 
+// Compute effects are declared in the manifest, these bound what it may ask for.
 pub mod comp {
-    #[allow(dead_code)]
-    pub const COMP_SHADER:&str = "res/shaders/sh.comp.spv";
-    pub const GRADIENT_SHADER:&str = "res/shaders/gradient_color.comp.spv";
-    pub const SKY_SHADER:&str = "res/shaders/sky.comp.spv";
+    pub const EFFECT_MANIFEST:&str = "res/effects.toml";
+    // Saved parameters live in one directory per effect, one `.toml` file per preset.
+    pub const PRESET_DIR:&str = "res/presets";
+    pub const MAX_EFFECT_NAME:usize = 64;
+    pub const MAX_EFFECT_IMAGES:usize = 4;
+}
+
+// The post-processing chain runs the effects of its own manifest over the render image, in the order they are listed.
+pub mod post {
+    pub const MANIFEST:&str = "res/post.toml";
+    // Grading cube of 32 texels a side, unrolled into 32 slices laid left to right, blue picks the slice.
+    pub const LUT_SIZE:u32 = 32;
+    pub const LUT_FILE:&str = "res/textures/grading_lut.png";
+}
+
+// Auto-exposure meters the render image into a log2 luminance histogram, EVs here are log2 of luminance.
+pub mod exposure {
+    pub const HISTOGRAM_SHADER:&str = "src/ssrc/exposure_histogram.comp";
+    pub const AVERAGE_SHADER:&str = "src/ssrc/exposure_average.comp";
+    pub const HISTOGRAM_BINS:usize = 256;
+    pub const MIN_EV:f32 = -10.0;
+    pub const MAX_EV:f32 = 6.0;
+    // Fraction of the way to the target covered per second, as in 1 - e^(-speed).
+    pub const SPEED:f32 = 1.5;
+    // Luminance the metered average is exposed to, mid grey.
+    pub const KEY:f32 = 0.18;
+}
+
+// Bloom blurs the bright part of the image through a chain of levels, each half the size of the previous one.
+pub mod bloom {
+    pub const DOWNSAMPLE_SHADER:&str = "src/ssrc/bloom_downsample.comp";
+    pub const UPSAMPLE_SHADER:&str = "src/ssrc/bloom_upsample.comp";
+    // The first level is half the render image, the chain stops early once a level is a single texel.
+    pub const LEVELS:usize = 6;
+}
+
+// Geometry can be drawn multisampled over a copy of the background and resolved into the render image.
+pub mod msaa {
+    pub const BACKGROUND_VERT:&str = "src/ssrc/msaa_background.vert";
+    pub const BACKGROUND_FRAG:&str = "src/ssrc/msaa_background.frag";
+    // Offered when the device supports them for both color and depth attachments.
+    pub const SAMPLE_COUNTS:[u32; 4] = [1, 2, 4, 8];
+}
+
+// Temporal anti-aliasing, the geometry is jittered inside the pixel and accumulated over a reprojected history.
+pub mod taa {
+    pub const RESOLVE_SHADER:&str = "src/ssrc/taa_resolve.comp";
+    // Length of the Halton (2, 3) sequence the jitter cycles through.
+    pub const JITTER_SAMPLES:usize = 8;
+    // Weight of the current frame against the history once it has settled.
+    pub const BLEND:f32 = 0.1;
 }
 
 
 pub mod graph {
-    pub const MESH_VERT:&str = "res/shaders/mesh.vert.spv";
-    pub const MESH_FRAG:&str = "res/shaders/mesh.frag.spv";
+    pub const MESH_VERT:&str = "src/ssrc/mesh.vert";
+    pub const MESH_FRAG:&str = "src/ssrc/mesh.frag";
     
-    //pub const TRIANGLE_VERT:&str = "res/shaders/triangle.vert.spv";
-    //pub const TRIANGLE_FRAG:&str = "res/shaders/triangle.frag.spv";
+    //pub const TRIANGLE_VERT:&str = "src/ssrc/triangle.vert";
+    //pub const TRIANGLE_FRAG:&str = "src/ssrc/triangle.frag";
 }
 
 
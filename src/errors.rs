@@ -29,6 +29,9 @@ pub enum Error {
     #[error("only simple gltf are supported")]
     ComplexGltf,
     
+    #[error("{0}x MSAA is not supported by the device")]
+    UnsupportedSampleCount(u32),
+    
     #[error("swapchain support is strange")]
     SwapchainImageSize,
     
@@ -38,6 +41,45 @@ pub enum Error {
     #[error("cant create mesh from empty arrays")]
     EmptyMesh,
     
+    #[error("cant create empty buffers")]
+    EmptyBuffer,
+    #[error("buffer memory is not mapped")]
+    UnmappedBuffer,
+    #[error("buffer in {0:?} memory can't be read back")]
+    UnreadableBuffer(gpu::MemoryLocation),
+    #[error("buffer is missing usage {0:?}")]
+    InvalidBufferUsage(vk::BufferUsageFlags),
+    #[error("access of {count} elements at {index} out of bounds for buffer of {len}")]
+    BufferOutOfBounds{
+        index: usize,
+        count: usize,
+        len: usize,
+    },
+    
+    #[error("descriptor set does not fit in a new pool: {0:?}")]
+    DescriptorPoolTooSmall(vk::Result),
+    #[error("descriptor writes do not match the set layout")]
+    InvalidDescriptorWrites,
+    #[error("bindless {0} array is full")]
+    BindlessFull(&'static str),
+    #[error("bindless {0} need {1} descriptors, the device allows {2}")]
+    BindlessLimit(&'static str, u32, u32),
+    #[error("{0} handle is no longer in the registry")]
+    StaleHandle(&'static str),
+    
+    #[error("invalid SPIR-V in {0}: {1}")]
+    InvalidSpirv(String, &'static str),
+    #[error("shader interface mismatch: {0}")]
+    ShaderInterface(String),
+    #[error("could not preprocess {0}: {1}")]
+    ShaderPreprocess(String, String),
+    #[error("could not compile {0}:\n{1}")]
+    ShaderCompile(String, String),
+    #[error("invalid effect manifest {0}: {1}")]
+    EffectManifest(String, String),
+    #[error("invalid preset {0}: {1}")]
+    Preset(String, String),
+    
     
     #[error("invalid load transform")]
     InvalidLoadTransform,
@@ -68,10 +110,10 @@ pub mod messages {
     pub const SIMPLE_VK_FN:&'static str = "simple vk functions should not fail";
     pub const SIMPLE_SDL_FN:&'static str = "simple sdl2 functions should not fail";
     pub const BAD_DESTRUCTOR:&'static str = "destruct did not receive the right information";
-    pub const GPU_ALLOCATION:&'static str = "gpu allocation should not fail";
     pub const GPU_FREE:&'static str = "gpu free should not fail";
     pub const STANDARD_CONV:&'static str = "conversion is granted by de standard";
     pub const GRANTED:&'static str = "things the programer knows but the compiled does not";
+    #[allow(dead_code)]
     pub const RESOURCE_FILE:&'static str = "the resource file should be found in a particular path";
     
     pub const VK_UNRECOVERABLE:&'static str = "things the programer knows but the compiled does not";
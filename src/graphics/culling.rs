@@ -0,0 +1,222 @@
+use nalgebra as na;
+use na::Matrix4;
+use na::Vector3;
+use na::Vector4;
+use na::Point3;
+
+
+// Box and sphere around the same origin, in the space of the mesh.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bounds {
+    pub origin: Vector3<f32>,
+    // Half the size of the box on each axis.
+    pub extents: Vector3<f32>,
+    pub sphere_radius: f32,
+}
+
+impl Bounds {
+    pub fn from_points<'a, I:Iterator<Item = &'a Vector3<f32>> + Clone>(points:I) -> Self {
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+        for point in points.clone() {
+            min = min.inf(point);
+            max = max.sup(point);
+        }
+        if min.x > max.x {
+            return Self::default();
+        }
+        
+        let origin = (min + max) * 0.5;
+        // Tighter than the corner of the box when the points are not spread to it.
+        let sphere_radius = points.map(|point|(point - origin).norm()).fold(0f32, f32::max);
+        Self{
+            origin,
+            extents: (max - min) * 0.5,
+            sphere_radius,
+        }
+    }
+    
+    // Encloses both, the sphere is kept around the new box origin.
+    pub fn merge(&self, other:&Self) -> Self {
+        let min = (self.origin - self.extents).inf(&(other.origin - other.extents));
+        let max = (self.origin + self.extents).sup(&(other.origin + other.extents));
+        let origin = (min + max) * 0.5;
+        let sphere_radius = f32::max(
+            (self.origin - origin).norm() + self.sphere_radius,
+            (other.origin - origin).norm() + other.sphere_radius,
+        );
+        Self{
+            origin,
+            extents: (max - min) * 0.5,
+            sphere_radius,
+        }
+    }
+}
+
+
+// Planes of a clip matrix facing inwards, unnormalized so a plane at infinity stays finite.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Clip space is -w..w on x and y and 0..w on z.
+    pub fn from_matrix(clip:&Matrix4<f32>) -> Self {
+        let row = |index:usize|clip.row(index).transpose();
+        Self{
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+    
+    // False only when the bounds are entirely behind a plane, whichever of the box and the sphere reaches less counts.
+    pub fn intersects(&self, bounds:&Bounds, transform:&Matrix4<f32>) -> bool {
+        let center = transform.transform_point(&Point3::from(bounds.origin)).coords;
+        let linear = transform.fixed_view::<3, 3>(0, 0);
+        let extents = linear.abs() * bounds.extents;
+        let scale = linear.column_iter().map(|column|column.norm()).fold(0f32, f32::max);
+        let radius = bounds.sphere_radius * scale;
+        
+        self.planes.iter().all(|plane|{
+            let normal = plane.xyz();
+            let distance = normal.dot(&center) + plane.w;
+            let box_reach = normal.abs().dot(&extents);
+            let sphere_reach = radius * normal.norm();
+            distance >= -box_reach.min(sphere_reach)
+        })
+    }
+}
+
+
+// Objects of the last frame that were drawn and left out by the frustum.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CullingStats {
+    pub visible: usize,
+    pub culled: usize,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::DepthProjection;
+    
+    use nalgebra_glm as glm;
+    
+    const NEAR:f32 = 0.1;
+    const FAR:f32 = 100.0;
+    
+    // Built like the renderer's projection, a square 90 degree view down -z from the origin.
+    fn projection(depth_projection:DepthProjection) -> Matrix4<f32> {
+        let mut projection = glm::perspective_zo(1.0, std::f32::consts::FRAC_PI_2, FAR, NEAR);
+        if depth_projection == DepthProjection::InfiniteReverse {
+            projection[(2,2)] = 0.0;
+            projection[(2,3)] = NEAR;
+        }
+        projection[(1,1)] *= -1.0;
+        projection
+    }
+    
+    fn unit_box() -> Bounds {
+        Bounds{
+            origin: Vector3::zeros(),
+            extents: Vector3::repeat(1.0),
+            sphere_radius: 3f32.sqrt(),
+        }
+    }
+    
+    fn visible(depth_projection:DepthProjection, bounds:&Bounds, position:Vector3<f32>) -> bool {
+        let frustum = Frustum::from_matrix(&projection(depth_projection));
+        frustum.intersects(bounds, &Matrix4::new_translation(&position))
+    }
+    
+    #[test]
+    fn from_points_fits_box_and_sphere() {
+        let points = [
+            Vector3::new(-1.0, 0.0, 2.0),
+            Vector3::new(1.0, 0.0, 2.0),
+            Vector3::new(0.0, -1.0, 2.0),
+            Vector3::new(0.0, 1.0, 2.0),
+        ];
+        let bounds = Bounds::from_points(points.iter());
+        
+        assert_eq!(bounds.origin, Vector3::new(0.0, 0.0, 2.0));
+        assert_eq!(bounds.extents, Vector3::new(1.0, 1.0, 0.0));
+        // The points sit on the axes, closer than the corners of their box.
+        assert_eq!(bounds.sphere_radius, 1.0);
+        
+        let empty = Bounds::from_points([].iter());
+        assert_eq!(empty.extents, Vector3::zeros());
+        assert_eq!(empty.sphere_radius, 0.0);
+    }
+    
+    #[test]
+    fn merge_encloses_both() {
+        let left = Bounds{origin: Vector3::new(-2.0, 0.0, 0.0), ..unit_box()};
+        let right = Bounds{origin: Vector3::new(2.0, 0.0, 0.0), ..unit_box()};
+        let merged = left.merge(&right);
+        
+        assert_eq!(merged.origin, Vector3::zeros());
+        assert_eq!(merged.extents, Vector3::new(3.0, 1.0, 1.0));
+        assert_eq!(merged.sphere_radius, 2.0 + 3f32.sqrt());
+    }
+    
+    #[test]
+    fn boxes_inside_outside_and_straddling() {
+        for depth_projection in DepthProjection::ALL {
+            let bounds = unit_box();
+            assert!(visible(depth_projection, &bounds, Vector3::new(0.0, 0.0, -5.0)), "{:?} inside", depth_projection);
+            assert!(!visible(depth_projection, &bounds, Vector3::new(0.0, 0.0, 5.0)), "{:?} behind", depth_projection);
+            assert!(!visible(depth_projection, &bounds, Vector3::new(10.0, 0.0, -5.0)), "{:?} right", depth_projection);
+            assert!(!visible(depth_projection, &bounds, Vector3::new(0.0, -10.0, -5.0)), "{:?} below", depth_projection);
+            // The side planes cross x = 5 and y = 5 at this depth, the near plane is just in front of the origin.
+            assert!(visible(depth_projection, &bounds, Vector3::new(5.5, 0.0, -5.0)), "{:?} straddling right", depth_projection);
+            assert!(visible(depth_projection, &bounds, Vector3::new(0.0, 5.5, -5.0)), "{:?} straddling above", depth_projection);
+            assert!(visible(depth_projection, &bounds, Vector3::new(0.0, 0.0, 0.5)), "{:?} straddling near", depth_projection);
+        }
+    }
+    
+    #[test]
+    fn only_the_finite_projection_has_a_far_plane() {
+        let bounds = unit_box();
+        let beyond = Vector3::new(0.0, 0.0, -2.0 * FAR);
+        let straddling = Vector3::new(0.0, 0.0, -FAR - 0.5);
+        
+        assert!(!visible(DepthProjection::FiniteReverse, &bounds, beyond));
+        assert!(visible(DepthProjection::FiniteReverse, &bounds, straddling));
+        assert!(visible(DepthProjection::InfiniteReverse, &bounds, beyond));
+    }
+    
+    #[test]
+    fn the_shorter_reach_of_box_and_sphere_counts() {
+        // Against the right plane the box reaches 2 and the sphere sqrt(2) in units of its unnormalized normal.
+        let round = Bounds{sphere_radius: 1.0, ..unit_box()};
+        let position = Vector3::new(6.5, 0.0, -5.0);
+        assert!(!visible(DepthProjection::InfiniteReverse, &round, position));
+        assert!(visible(DepthProjection::InfiniteReverse, &unit_box(), position));
+        
+        // A flat box reaches 1 where its sphere would still reach sqrt(2).
+        let flat = Bounds{extents: Vector3::new(1.0, 1.0, 0.0), sphere_radius: 1.0, ..unit_box()};
+        let position = Vector3::new(6.2, 0.0, -5.0);
+        assert!(!visible(DepthProjection::InfiniteReverse, &flat, position));
+        assert!(visible(DepthProjection::InfiniteReverse, &Bounds{extents: Vector3::repeat(1.0), ..flat}, position));
+    }
+    
+    #[test]
+    fn transforms_scale_the_bounds() {
+        let bounds = unit_box();
+        let position = Vector3::new(7.5, 0.0, -5.0);
+        let scaled = Matrix4::new_translation(&position) * Matrix4::new_scaling(2.0);
+        let frustum = Frustum::from_matrix(&projection(DepthProjection::InfiniteReverse));
+        
+        assert!(!frustum.intersects(&bounds, &Matrix4::new_translation(&position)));
+        assert!(frustum.intersects(&bounds, &scaled));
+    }
+}
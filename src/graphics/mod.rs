@@ -1,4 +1,11 @@
+mod parameters;
+pub use parameters::*;
+
+mod culling;
+pub use culling::*;
+
 use crate::errors::messages::COMPILETIME_ASSERT;
+use crate::constants::exposure;
 
 use std::ops::Deref;
 use std::ops::DerefMut;
@@ -37,6 +44,131 @@ impl DerefMut for ComputePushConstants {
     }
 }
 
+// What the GUI tunes of the auto-exposure, the metering range is in EV.
+#[derive(Debug, Clone, Copy)]
+pub struct ExposureSettings {
+    pub enabled: bool,
+    pub min_ev: f32,
+    pub max_ev: f32,
+    pub speed: f32,
+}
+
+// Last values the auto-exposure wrote, read back a few frames late.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExposureReadout {
+    pub luminance: f32,
+    pub exposure: f32,
+    pub target: f32,
+}
+
+// State the geometry pass of the last frame bound, a bind is only counted when it changed from the previous draw.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawStats {
+    pub draws: u32,
+    pub pipeline_binds: u32,
+    pub descriptor_binds: u32,
+    pub index_buffer_binds: u32,
+}
+
+impl Default for ExposureSettings {
+    fn default() -> Self {
+        Self{
+            enabled: true,
+            min_ev: exposure::MIN_EV,
+            max_ev: exposure::MAX_EV,
+            speed: exposure::SPEED,
+        }
+    }
+}
+
+// What the material pass shows instead of the shaded scene, each one is a variant of mesh.frag built with DEBUG_VIEW.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    Wireframe,
+    WireframeOverShaded,
+    Normals,
+    UvChecker,
+    Depth,
+    SurfaceId,
+    Overdraw,
+}
+
+impl DebugView {
+    pub const ALL:[Self;8] = [
+        Self::Shaded,
+        Self::Wireframe,
+        Self::WireframeOverShaded,
+        Self::Normals,
+        Self::UvChecker,
+        Self::Depth,
+        Self::SurfaceId,
+        Self::Overdraw,
+    ];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Shaded => "Shaded",
+            Self::Wireframe => "Wireframe",
+            Self::WireframeOverShaded => "Wireframe over shaded",
+            Self::Normals => "World normals",
+            Self::UvChecker => "UV checker",
+            Self::Depth => "Linear depth",
+            Self::SurfaceId => "Surface ID",
+            Self::Overdraw => "Overdraw",
+        }
+    }
+    
+    // Value of DEBUG_VIEW, the wireframe overlay shares the flat color of the plain wireframe.
+    pub fn shader_variant(self) -> u32 {
+        match self {
+            Self::Shaded => 0,
+            Self::Wireframe => 1,
+            Self::WireframeOverShaded => 1,
+            Self::Normals => 2,
+            Self::UvChecker => 3,
+            Self::Depth => 4,
+            Self::SurfaceId => 5,
+            Self::Overdraw => 6,
+        }
+    }
+    
+    // Drawn with lines, only offered when the device has fillModeNonSolid.
+    pub fn is_wireframe(self) -> bool {
+        matches!(self, Self::Wireframe | Self::WireframeOverShaded)
+    }
+    
+    // Views whose colors only mean something on a black background.
+    pub fn clears_background(self) -> bool {
+        matches!(self, Self::Overdraw)
+    }
+}
+
+// Both put 1 at the near plane and fall towards 0 with distance, the GREATER_OR_EQUAL test and the clear to 0 suit either.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepthProjection {
+    // Reaches 0 at infinity, the far plane is ignored.
+    #[default]
+    InfiniteReverse,
+    // Reaches 0 at the far plane.
+    FiniteReverse,
+}
+
+impl DepthProjection {
+    pub const ALL:[Self;2] = [
+        Self::InfiniteReverse,
+        Self::FiniteReverse,
+    ];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::InfiniteReverse => "Reverse-Z, infinite far",
+            Self::FiniteReverse => "Reverse-Z, finite far",
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Vertex {
@@ -64,7 +196,7 @@ impl ComputePushConstants {
 pub struct GeoSurface {
     pub start_index: u32,
     pub count: u32,
-    
+    pub bounds: Bounds,
 }
 
 #[derive(Debug, Default)]
@@ -88,6 +220,8 @@ pub struct GPUSceneData {
     ambient_color: Vector4<f32>,
     sunlight_direction: Vector4<f32>,
     sunlight_color: Vector4<f32>,
+    // xy sub-pixel offset of this frame's projection in NDC, the mesh pass removes it from its motion vectors.
+    jitter: Vector4<f32>,
 }
 
 const _:u64 = GPUSceneData::size_u64();
@@ -109,10 +243,17 @@ impl Default for GPUSceneData {
             ambient_color: Vector4::<f32>::new(1.0,1.0,1.0,1.0),
             sunlight_direction: Vector4::<f32>::new(1.0,1.0,1.0,1.0),
             sunlight_color: Vector4::<f32>::new(1.0,1.0,1.0,1.0),
+            jitter: Vector4::<f32>::zeros(),
         }
     }
 }
 
+impl GPUSceneData {
+    pub fn set_jitter(&mut self, jitter:na::Vector2<f32>) {
+        self.jitter = Vector4::new(jitter.x, jitter.y, 0.0, 0.0);
+    }
+}
+
 
 /*
 #[derive(Debug, Default)]
@@ -0,0 +1,416 @@
+use crate::AAError;
+use crate::logger;
+
+use super::ComputePushConstants;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::mem::size_of;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+const PUSH_CONSTANT_WORDS:u32 = (size_of::<ComputePushConstants>() / 4) as u32;
+
+const RAW_ROW_NAMES:[&str; 4] = [
+    "push_constant 1",
+    "push_constant 2",
+    "push_constant 3",
+    "push_constant 4",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Color,
+    Int,
+    Bool,
+}
+
+// What a manifest or a preset file holds for one parameter, integers are accepted where floats are expected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Vector(Vec<f64>),
+}
+
+// One entry of an effect schema as the manifest declares it, the offset is in bytes and packed in order when left out.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterDescription {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+    #[serde(default)]
+    pub offset: Option<u32>,
+    #[serde(default)]
+    pub default: Option<ParameterValue>,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: ParameterType,
+    pub range: Option<(f32, f32)>,
+    word: u32,
+}
+
+// Names and types laid over the push constant words of a compute effect.
+#[derive(Debug, Clone, Default)]
+pub struct ParameterSchema {
+    pub parameters: Vec<Parameter>,
+}
+
+impl ParameterType {
+    
+//----
+    pub fn components(self) -> u32 {
+        match self {
+            Self::Float | Self::Int | Self::Bool => 1,
+            Self::Vec2 => 2,
+            Self::Vec3 => 3,
+            Self::Vec4 | Self::Color => 4,
+        }
+    }
+    
+//----
+    // std430 alignment in words, vec3 takes the alignment of a vec4.
+    fn alignment(self) -> u32 {
+        match self.components() {
+            1 => 1,
+            2 => 2,
+            _ => 4,
+        }
+    }
+}
+
+impl ParameterSchema {
+    
+//----
+    // Effects without a schema get their four raw rows back, labeled like the old window did.
+    pub fn raw_rows() -> Self {
+        Self{
+            parameters: RAW_ROW_NAMES.iter().enumerate().map(|(row, label)|Parameter{
+                name: label.to_string(),
+                kind: ParameterType::Vec4,
+                range: None,
+                word: row as u32 * 4,
+            }).collect(),
+        }
+    }
+    
+//----
+    // Lays the declared parameters out and writes their defaults over `constants`.
+    pub fn build(descriptions:&[ParameterDescription], constants:&mut ComputePushConstants) -> Result<Self, String> {
+        let mut parameters:Vec<Parameter> = Vec::new();
+        let mut next_word:u32 = 0;
+        
+        for description in descriptions {
+            let kind = description.kind;
+            let word = match description.offset {
+                Some(offset) if offset % 4 != 0 => return Err(format!("{:?} has offset {} that is not a multiple of 4", description.name, offset)),
+                Some(offset) => offset / 4,
+                None => next_word.div_ceil(kind.alignment()) * kind.alignment(),
+            };
+            if word + kind.components() > PUSH_CONSTANT_WORDS {
+                return Err(format!("{:?} ends past the {} bytes of push constants", description.name, PUSH_CONSTANT_WORDS*4));
+            }
+            if parameters.iter().any(|other|other.name == description.name) {
+                return Err(format!("parameter {:?} is declared twice", description.name));
+            }
+            if let Some(other) = parameters.iter().find(|other|word < other.word + other.kind.components() && other.word < word + kind.components()) {
+                return Err(format!("{:?} overlaps {:?}", description.name, other.name));
+            }
+            let range = match (description.min, description.max) {
+                (Some(min), Some(max)) if min < max => Some((min, max)),
+                (None, None) => None,
+                _ => return Err(format!("{:?} needs both min and max, with min below max", description.name)),
+            };
+            
+            let parameter = Parameter{
+                name: description.name.clone(),
+                kind,
+                range,
+                word,
+            };
+            if let Some(default) = description.default.as_ref() {
+                parameter.set(constants, default)?;
+            }
+            next_word = word + kind.components();
+            parameters.push(parameter);
+        }
+        Ok(Self{parameters})
+    }
+    
+//----
+    pub fn save_preset<P:AsRef<Path>>(&self, path:P, constants:&ComputePushConstants) -> Result<(), AAError> {
+        let path = path.as_ref();
+        let invalid = |reason:String|AAError::Preset(path.to_string_lossy().to_string(), reason);
+        
+        let values:BTreeMap<&str, ParameterValue> = self.parameters.iter().map(|parameter|(parameter.name.as_str(), parameter.get(constants))).collect();
+        let contents = toml::to_string(&values).map_err(|error|invalid(error.to_string()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|error|invalid(error.to_string()))?;
+        }
+        fs::write(path, contents).map_err(|error|invalid(error.to_string()))
+    }
+    
+//----
+    // Parameters missing from the file keep their value, unknown names are only logged.
+    pub fn load_preset<P:AsRef<Path>>(&self, path:P, constants:&mut ComputePushConstants) -> Result<(), AAError> {
+        let path = path.as_ref();
+        let invalid = |reason:String|AAError::Preset(path.to_string_lossy().to_string(), reason);
+        
+        let contents = fs::read_to_string(path).map_err(|error|invalid(error.to_string()))?;
+        let values:BTreeMap<String, ParameterValue> = toml::from_str(&contents).map_err(|error|invalid(error.to_string()))?;
+        
+        let mut holder = *constants;
+        for (name, value) in values.iter() {
+            match self.parameters.iter().find(|parameter|&parameter.name == name) {
+                Some(parameter) => parameter.set(&mut holder, value).map_err(invalid)?,
+                None => {
+                    logger::various_log!("parameters",
+                        (logger::Warn, "{:?}: no parameter named {:?}", path, name)
+                    );
+                }
+            }
+        }
+        *constants = holder;
+        Ok(())
+    }
+    
+//----
+    // Stems of the `.toml` files in a preset directory, sorted.
+    pub fn presets_in<P:AsRef<Path>>(dir:P) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut holder:Vec<String> = entries
+            .filter_map(|entry|entry.ok().map(|entry|entry.path()))
+            .filter(|path|path.extension().is_some_and(|extension|extension == "toml"))
+            .filter_map(|path|path.file_stem().map(|stem|stem.to_string_lossy().to_string()))
+            .collect();
+        holder.sort();
+        holder
+    }
+}
+
+impl Parameter {
+    
+//----
+    // Float components of the parameter, unused ones are zero.
+    pub fn floats(&self, constants:&ComputePushConstants) -> [f32; 4] {
+        let mut holder = [0.0; 4];
+        for (index, value) in holder.iter_mut().take(self.kind.components() as usize).enumerate() {
+            *value = word(constants, self.word + index as u32);
+        }
+        holder
+    }
+    
+//----
+    pub fn set_floats(&self, constants:&mut ComputePushConstants, values:&[f32; 4]) {
+        for (index, value) in values.iter().take(self.kind.components() as usize).enumerate() {
+            *word_mut(constants, self.word + index as u32) = *value;
+        }
+    }
+    
+//----
+    // Ints and bools are stored as their bits, the shader reads them as int and uint.
+    pub fn int(&self, constants:&ComputePushConstants) -> i32 {
+        word(constants, self.word).to_bits() as i32
+    }
+    
+//----
+    pub fn set_int(&self, constants:&mut ComputePushConstants, value:i32) {
+        *word_mut(constants, self.word) = f32::from_bits(value as u32);
+    }
+    
+//----
+    pub fn get(&self, constants:&ComputePushConstants) -> ParameterValue {
+        match self.kind {
+            ParameterType::Float => ParameterValue::Float(widen(self.floats(constants)[0])),
+            ParameterType::Int => ParameterValue::Int(self.int(constants) as i64),
+            ParameterType::Bool => ParameterValue::Bool(self.int(constants) != 0),
+            kind => ParameterValue::Vector(self.floats(constants)[..kind.components() as usize].iter().map(|value|widen(*value)).collect()),
+        }
+    }
+    
+//----
+    pub fn set(&self, constants:&mut ComputePushConstants, value:&ParameterValue) -> Result<(), String> {
+        match (self.kind, value) {
+            (ParameterType::Float, ParameterValue::Float(value)) => self.set_floats(constants, &[*value as f32, 0.0, 0.0, 0.0]),
+            (ParameterType::Float, ParameterValue::Int(value)) => self.set_floats(constants, &[*value as f32, 0.0, 0.0, 0.0]),
+            (ParameterType::Int, ParameterValue::Int(value)) => self.set_int(constants, *value as i32),
+            (ParameterType::Bool, ParameterValue::Bool(value)) => self.set_int(constants, *value as i32),
+            (kind, ParameterValue::Vector(values)) if values.len() == kind.components() as usize && kind.components() > 1 => {
+                let mut holder = [0.0; 4];
+                for (held, value) in holder.iter_mut().zip(values.iter()) {
+                    *held = *value as f32;
+                }
+                self.set_floats(constants, &holder);
+            }
+            (kind, value) => return Err(format!("{:?} is a {:?}, got {:?}", self.name, kind, value)),
+        }
+        Ok(())
+    }
+}
+
+
+// Shortest decimal form of the f32, so presets read 0.4 and not 0.4000000059604645.
+fn widen(value:f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+fn word(constants:&ComputePushConstants, index:u32) -> f32 {
+    constants[(index / 4) as usize][(index % 4) as usize]
+}
+
+fn word_mut(constants:&mut ComputePushConstants, index:u32) -> &mut f32 {
+    &mut constants[(index / 4) as usize][(index % 4) as usize]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn description(name:&str, kind:ParameterType, offset:Option<u32>) -> ParameterDescription {
+        ParameterDescription{
+            name: name.to_string(),
+            kind,
+            offset,
+            default: None,
+            min: None,
+            max: None,
+        }
+    }
+    
+    fn ranged(name:&str, min:Option<f32>, max:Option<f32>) -> ParameterDescription {
+        ParameterDescription{
+            min,
+            max,
+            ..description(name, ParameterType::Float, None)
+        }
+    }
+    
+    fn build(descriptions:&[ParameterDescription]) -> Result<ParameterSchema, String> {
+        ParameterSchema::build(descriptions, &mut ComputePushConstants::default())
+    }
+    
+    #[test]
+    fn packs_in_order_with_std430_alignment() {
+        let schema = build(&[
+            description("strength", ParameterType::Float, None),
+            description("tint", ParameterType::Vec3, None),
+            description("radius", ParameterType::Float, None),
+            description("center", ParameterType::Vec2, None),
+            description("color", ParameterType::Color, None),
+        ]).expect("the parameters fit");
+        
+        let words:Vec<u32> = schema.parameters.iter().map(|parameter|parameter.word).collect();
+        // The vec3 starts on a vec4 boundary, the float fills the word it leaves free.
+        assert_eq!(words, [0, 4, 7, 8, 12]);
+    }
+    
+    #[test]
+    fn explicit_offsets_place_and_restart_the_packing() {
+        let schema = build(&[
+            description("late", ParameterType::Float, Some(32)),
+            description("after", ParameterType::Vec2, None),
+        ]).expect("the parameters fit");
+        
+        assert_eq!(schema.parameters[0].word, 8);
+        assert_eq!(schema.parameters[1].word, 10);
+    }
+    
+    #[test]
+    fn rejects_offsets_off_words_and_out_of_range() {
+        assert!(build(&[description("unaligned", ParameterType::Float, Some(6))]).is_err());
+        assert!(build(&[description("past", ParameterType::Float, Some(64))]).is_err());
+        assert!(build(&[description("straddling", ParameterType::Vec4, Some(56))]).is_err());
+        assert!(build(&[description("last", ParameterType::Float, Some(60))]).is_ok());
+    }
+    
+    #[test]
+    fn rejects_overlaps_and_repeated_names() {
+        let overlapping = build(&[
+            description("color", ParameterType::Vec4, None),
+            description("alpha", ParameterType::Float, Some(12)),
+        ]);
+        assert!(overlapping.is_err());
+        
+        let repeated = build(&[
+            description("strength", ParameterType::Float, None),
+            description("strength", ParameterType::Float, None),
+        ]);
+        assert!(repeated.is_err());
+    }
+    
+    #[test]
+    fn ranges_need_both_ends_in_order() {
+        assert!(build(&[ranged("min_only", Some(0.0), None)]).is_err());
+        assert!(build(&[ranged("max_only", None, Some(1.0))]).is_err());
+        assert!(build(&[ranged("reversed", Some(1.0), Some(0.0))]).is_err());
+        
+        let schema = build(&[ranged("both", Some(0.0), Some(1.0)), ranged("neither", None, None)]).expect("the ranges are valid");
+        assert_eq!(schema.parameters[0].range, Some((0.0, 1.0)));
+        assert_eq!(schema.parameters[1].range, None);
+    }
+    
+    #[test]
+    fn defaults_are_written_over_the_constants() {
+        let mut constants = ComputePushConstants::default();
+        let descriptions = [
+            ParameterDescription{default: Some(ParameterValue::Int(2)), ..description("strength", ParameterType::Float, None)},
+            ParameterDescription{default: Some(ParameterValue::Vector(vec![0.5, 0.25])), ..description("center", ParameterType::Vec2, None)},
+            ParameterDescription{default: Some(ParameterValue::Bool(true)), ..description("enabled", ParameterType::Bool, None)},
+        ];
+        let schema = ParameterSchema::build(&descriptions, &mut constants).expect("the defaults match their types");
+        
+        assert_eq!(schema.parameters[0].floats(&constants)[0], 2.0);
+        assert_eq!(schema.parameters[1].floats(&constants)[..2], [0.5, 0.25]);
+        assert_eq!(schema.parameters[2].int(&constants), 1);
+        
+        let mismatched = ParameterDescription{default: Some(ParameterValue::Float(1.0)), ..description("flag", ParameterType::Bool, None)};
+        assert!(build(&[mismatched]).is_err());
+    }
+    
+    #[test]
+    fn presets_round_trip_through_their_file() {
+        let schema = build(&[
+            description("strength", ParameterType::Float, None),
+            description("steps", ParameterType::Int, None),
+            description("enabled", ParameterType::Bool, None),
+            description("color", ParameterType::Color, None),
+        ]).expect("the parameters fit");
+        
+        let mut saved = ComputePushConstants::default();
+        schema.parameters[0].set_floats(&mut saved, &[0.4, 0.0, 0.0, 0.0]);
+        schema.parameters[1].set_int(&mut saved, -3);
+        schema.parameters[2].set_int(&mut saved, 1);
+        schema.parameters[3].set_floats(&mut saved, &[1.0, 0.5, 0.25, 1.0]);
+        
+        let path = std::env::temp_dir().join(format!("c_bind_preset_{}", std::process::id())).join("round_trip.toml");
+        schema.save_preset(&path, &saved).expect("the preset is written");
+        let mut loaded = ComputePushConstants::default();
+        let result = schema.load_preset(&path, &mut loaded);
+        let _ = fs::remove_dir_all(path.parent().expect("the preset has a directory"));
+        result.expect("the preset is read back");
+        
+        for parameter in schema.parameters.iter() {
+            assert_eq!(parameter.get(&loaded), parameter.get(&saved), "{}", parameter.name);
+        }
+    }
+}
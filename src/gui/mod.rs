@@ -5,9 +5,23 @@ pub use vk_gui::Gui;
 
 use crate::window::Window;
 use crate::graphics::ComputePushConstants;
+use crate::graphics::Parameter;
+use crate::graphics::ParameterSchema;
+use crate::graphics::ParameterType;
+use crate::graphics::ExposureSettings;
+use crate::graphics::ExposureReadout;
+use crate::graphics::DebugView;
+use crate::graphics::DepthProjection;
+use crate::graphics::CullingStats;
+use crate::graphics::DrawStats;
+use crate::vulkan::MemoryReport;
+use crate::vulkan::DescriptorStats;
+use crate::vulkan::ShaderError;
+use crate::vulkan::memory_stats::format_bytes;
 
 use nalgebra as na;
 use na::Vector3;
+use arrayvec::ArrayString;
 
 
 
@@ -15,6 +29,11 @@ use na::Vector3;
 pub struct InputData {
     pub background_index: usize,
     pub push_constants: ComputePushConstants,
+    pub dump_memory_report: bool,
+    pub unload_mesh: bool,
+    pub preset_name: String,
+    pub save_preset: Option<String>,
+    pub load_preset: Option<String>,
     
     /*
     pub mesh_index: usize,
@@ -23,16 +42,46 @@ pub struct InputData {
     
 }
 
-const PUSH_CONSTANT_FIELD_TEXT:[&str; 4] = [
-    "push_constant 1",
-    "push_constant 2",
-    "push_constant 3",
-    "push_constant 4",
-];
+// What the windows show of the renderer this frame.
+pub struct GuiView<'a> {
+    pub effect_names: &'a [ArrayString<64>],
+    pub mesh_names: &'a [ArrayString<64>],
+    pub memory_report: &'a MemoryReport,
+    pub descriptor_stats: &'a [DescriptorStats],
+    pub shader_errors: &'a [ShaderError],
+    // Schema of the selected background effect.
+    pub parameter_schema: &'a ParameterSchema,
+    pub presets: &'a [String],
+    pub post_names: &'a [ArrayString<64>],
+    pub post_schemas: &'a [ParameterSchema],
+    pub exposure_readout: ExposureReadout,
+    pub sample_counts: &'a [u32],
+    pub debug_views: &'a [DebugView],
+    pub culling_stats: CullingStats,
+    pub draw_stats: DrawStats,
+}
+
+// The renderer settings the windows edit in place.
+pub struct GuiState<'a> {
+    pub effect_index: &'a mut usize,
+    // Constants of the selected background effect.
+    pub push_constants: &'a mut ComputePushConstants,
+    pub mesh_index: &'a mut usize,
+    pub field_of_view: &'a mut Vector3<f32>,
+    pub depth_projection: &'a mut DepthProjection,
+    pub downscale_coheficient: &'a mut f32,
+    pub post_enabled: &'a mut [bool],
+    pub post_push_constants: &'a mut [ComputePushConstants],
+    pub exposure_settings: &'a mut ExposureSettings,
+    pub msaa_samples: &'a mut u32,
+    pub taa_enabled: &'a mut bool,
+    pub debug_view: &'a mut DebugView,
+    pub freeze_culling: &'a mut bool,
+}
 
 const FOV_FIELD_TEXT:[&str; 3] = [
-    "near",
     "far",
+    "near",
     "angle",
 ];
 
@@ -46,69 +95,240 @@ impl Gui{
         platform_holder.prepare_frame(context_holder.io_mut(), window.underlying(), &window.event_pump().mouse_state());
     }
     
-    pub fn draw_ui<C, CC:Fn(&C)->&str, D, DD:Fn(&D)->&str>(
+    pub fn draw_ui(
         &mut self,
         window: &mut Window,
-        args: (&[C], &[D]),
-        transform: (CC, DD),
-        parameters: (&mut usize, &mut ComputePushConstants, &mut usize, &mut Vector3<f32>, &mut f32),
+        view: GuiView,
+        state: GuiState,
     ) {
         
-        let (compute_effects_name, mesh_assets_metadata) = args;
-        let (c_transform, d_transform) = transform;
-        let (compute_effect_index, compute_push_constant, mesh_index, near_far, downscale_coheficient) = parameters;
+        let GuiView{effect_names, mesh_names, memory_report, descriptor_stats, shader_errors, parameter_schema, presets, post_names, post_schemas, exposure_readout, sample_counts, debug_views, culling_stats, draw_stats} = view;
+        let GuiState{effect_index, push_constants, mesh_index, field_of_view, depth_projection, downscale_coheficient, post_enabled, post_push_constants, exposure_settings, msaa_samples, taa_enabled, debug_view, freeze_culling} = state;
         
         let (context, platform, ui_data) = self.get_common_mut();
         let ui = context.new_frame();
         
-        let _background = Self::get_next_window(&ui, "Background", [0,0]).build(||{
+        let _background = Self::get_next_window(ui, "Background", [0,0]).build(||{
             
             let _disabled_token = ui.begin_disabled(false);
             ui.text("Compute shader");
             
-            for (index, effect) in compute_effects_name.into_iter().enumerate() {
-                ui.radio_button(c_transform(effect), compute_effect_index, index);
+            for (index, effect) in effect_names.iter().enumerate() {
+                ui.radio_button(effect.as_str(), effect_index, index);
             }
             
             //ui_data.push_constants = compute_effect_metadata[ui_data.background_index].data.clone();
             
-            for (index, line) in compute_push_constant.0.iter_mut().enumerate() {
-                let _ = ui.input_float4(PUSH_CONSTANT_FIELD_TEXT[index], line)
-                    .build();
+            for parameter in parameter_schema.parameters.iter() {
+                Self::parameter_widget(ui, parameter, push_constants);
+            }
+            
+            ui.separator();
+            ui.text("Presets");
+            ui.input_text("Preset name", &mut ui_data.preset_name).build();
+            ui_data.save_preset = (ui.button("Save preset") && !ui_data.preset_name.is_empty()).then(||ui_data.preset_name.clone());
+            ui_data.load_preset = None;
+            for preset in presets.iter() {
+                if ui.button(format!("Load {}", preset)) {
+                    ui_data.load_preset = Some(preset.clone());
+                }
             }
             ui.text("Dangerous button");
             
         });
         
-        let _global = Self::get_next_window(&ui, "Global", [0,1]).build(||{
+        let _global = Self::get_next_window(ui, "Global", [0,1]).build(||{
             let _disabled_token = ui.begin_disabled(false);
             
             ui.text("Render scale");
             ui.slider("Scale", 0.1, 1.0, downscale_coheficient);
             
+            ui.text("Anti-aliasing");
+            ui.checkbox("TAA", taa_enabled);
+            {
+                let _msaa_disabled_token = ui.begin_disabled(*taa_enabled);
+                for count in sample_counts.iter() {
+                    let label = match count {
+                        1 => String::from("MSAA off"),
+                        _ => format!("MSAA {}x", count),
+                    };
+                    ui.radio_button(label, msaa_samples, *count);
+                }
+            }
+            
+            ui.separator();
+            ui.text("Debug view");
+            for view in debug_views.iter() {
+                ui.radio_button(view.name(), debug_view, *view);
+            }
+            
+            ui.separator();
+            ui.text("Frustum culling");
+            ui.text(format!("visible {} / culled {}", culling_stats.visible, culling_stats.culled));
+            ui.checkbox("Freeze culling", freeze_culling);
+            
+            ui.separator();
+            ui.text(format!("draws {}", draw_stats.draws));
+            ui.text(format!("binds: pipeline {} / descriptor {} / index buffer {}", draw_stats.pipeline_binds, draw_stats.descriptor_binds, draw_stats.index_buffer_binds));
+            
         });
         
-        let _model = Self::get_next_window(&ui, "Model", [0,2]).build(||{
+        let _model = Self::get_next_window(ui, "Model", [0,2]).build(||{
             let _disabled_token = ui.begin_disabled(false);
             ui.text("Select Model");
-            for (index, mesh) in mesh_assets_metadata.into_iter().enumerate() {
-                ui.radio_button(d_transform(mesh), mesh_index, index);
+            for (index, mesh) in mesh_names.iter().enumerate() {
+                ui.radio_button(mesh.as_str(), mesh_index, index);
             }
+            ui_data.unload_mesh = ui.button("Unload selected");
         });
         
-        let mut _window = Self::get_next_window(&ui, "Field of View(FOV)", [0,3]).build(||{
+        let mut _window = Self::get_next_window(ui, "Field of View(FOV)", [0,3]).build(||{
             let _disabled_token = ui.begin_disabled(false);
             //ui_data.push_constants = compute_effect_metadata[ui_data.background_index].data.clone();
-            let _ = ui.slider(FOV_FIELD_TEXT[0], 0.0, 10000.0, &mut near_far[0]);
-            let _ = ui.slider(FOV_FIELD_TEXT[1], 0.0, 10.0, &mut near_far[1]);
-            let _ = ui.slider(FOV_FIELD_TEXT[2], 1.0, 180.0, &mut near_far[2]);
+            for projection in DepthProjection::ALL {
+                ui.radio_button(projection.name(), depth_projection, projection);
+            }
+            {
+                // An infinite projection has no far plane to move.
+                let _far_disabled_token = ui.begin_disabled(*depth_projection == DepthProjection::InfiniteReverse);
+                let _ = ui.slider(FOV_FIELD_TEXT[0], 0.0, 10000.0, &mut field_of_view[0]);
+            }
+            let _ = ui.slider(FOV_FIELD_TEXT[1], 0.0, 10.0, &mut field_of_view[1]);
+            let _ = ui.slider(FOV_FIELD_TEXT[2], 1.0, 180.0, &mut field_of_view[2]);
             ui.text("Dangerous button");
             
         });
         
-        platform.prepare_render(&ui, window.underlying());
+        let _memory = Self::get_next_window(ui, "GPU Memory", [0,4]).build(||{
+            let _disabled_token = ui.begin_disabled(false);
+            ui.text(format!("used {} / reserved {}", format_bytes(memory_report.total_used), format_bytes(memory_report.total_reserved)));
+            ui.text(format!("allocations {} (peak {})", memory_report.allocation_count, memory_report.peak_allocation_count));
+            ui.text(format!("peak used {}", format_bytes(memory_report.peak_used)));
+            ui_data.dump_memory_report = ui.button("Dump to log");
+            
+            for (index, heap) in memory_report.heaps.iter().enumerate() {
+                let fraction = match heap.size {
+                    0 => 0.0,
+                    size => heap.used as f32 / size as f32,
+                };
+                ui.separator();
+                ui.text(format!("heap {} {}", index, if heap.device_local {"(device local)"} else {"(host)"}));
+                imgui::ProgressBar::new(fraction)
+                    .overlay_text(format!("{} / {}", format_bytes(heap.used), format_bytes(heap.size)))
+                    .build(ui);
+                ui.text(format!("reserved {} peak {}", format_bytes(heap.reserved), format_bytes(heap.peak)));
+            }
+            
+            for (index, block) in memory_report.blocks.iter().enumerate() {
+                let label = format!("block {} heap {} {} / {}##block{}", index, block.heap_index, format_bytes(block.used), format_bytes(block.size), index);
+                if let Some(_node) = ui.tree_node(label) {
+                    ui.text(format!("fragmentation {:.1}%", block.fragmentation*100.0));
+                    ui.text(format!("largest free range {}", format_bytes(block.largest_free)));
+                    if block.dedicated {
+                        ui.text("dedicated");
+                    }
+                    for record in block.allocations.iter() {
+                        ui.bullet_text(format!("{} {} {:?}", record.name, format_bytes(record.size), record.location));
+                    }
+                }
+            }
+        });
+        
+        let _descriptors = Self::get_next_window(ui, "Descriptors", [0,5]).build(||{
+            let _disabled_token = ui.begin_disabled(false);
+            for (frame, stats) in descriptor_stats.iter().enumerate() {
+                ui.separator();
+                ui.text(format!("frame {}", frame));
+                ui.text(format!("sets allocated {}", stats.last_sets_allocated));
+                ui.text(format!("pools created {} filled {}", stats.last_pools_created, stats.last_pools_filled));
+                ui.text(format!("live pools {} ({} sets each)", stats.live_pools, stats.sets_per_pool));
+                ui.text(format!("resets {}", stats.resets));
+            }
+        });
+        
+        let _shaders = Self::get_next_window(ui, "Shaders", [0,6]).build(||{
+            let _disabled_token = ui.begin_disabled(false);
+            if shader_errors.is_empty() {
+                ui.text("all shaders up to date");
+            }
+            for error in shader_errors.iter() {
+                ui.separator();
+                ui.text(format!("{}", error.file.display()));
+                ui.text_wrapped(&error.message);
+            }
+        });
+        
+        let _post = Self::get_next_window(ui, "Post-processing", [0,7]).build(||{
+            let _disabled_token = ui.begin_disabled(false);
+            ui.checkbox("Auto-exposure", &mut exposure_settings.enabled);
+            ui.text(format!("average luminance {:.4}", exposure_readout.luminance));
+            ui.text(format!("exposure {:+.2} EV (target {:+.2} EV)", exposure_readout.exposure, exposure_readout.target));
+            if let Some(_node) = ui.tree_node("metering") {
+                let max_ev = exposure_settings.max_ev;
+                let min_ev = exposure_settings.min_ev;
+                ui.slider("min EV", -16.0, max_ev - 1.0, &mut exposure_settings.min_ev);
+                ui.slider("max EV", min_ev + 1.0, 16.0, &mut exposure_settings.max_ev);
+                ui.slider("adaptation speed", 0.1, 10.0, &mut exposure_settings.speed);
+            }
+            ui.separator();
+            
+            if post_names.is_empty() {
+                ui.text("no post-processing passes loaded");
+            }
+            for (index, name) in post_names.iter().enumerate() {
+                let _id = ui.push_id_usize(index);
+                ui.checkbox(name.as_str(), &mut post_enabled[index]);
+                if let Some(_node) = ui.tree_node("parameters") {
+                    let _disabled_token = ui.begin_disabled(!post_enabled[index]);
+                    for parameter in post_schemas[index].parameters.iter() {
+                        Self::parameter_widget(ui, parameter, &mut post_push_constants[index]);
+                    }
+                }
+            }
+        });
+        
+        platform.prepare_render(ui, window.underlying());
     }
     
+    
+    // The widget follows the declared type, parameters with a range get sliders.
+    fn parameter_widget(ui:&imgui::Ui, parameter:&Parameter, constants:&mut ComputePushConstants) {
+        match parameter.kind {
+            ParameterType::Bool => {
+                let mut value = parameter.int(constants) != 0;
+                if ui.checkbox(&parameter.name, &mut value) {
+                    parameter.set_int(constants, value as i32);
+                }
+            }
+            ParameterType::Int => {
+                let mut value = parameter.int(constants);
+                let changed = match parameter.range {
+                    Some((min, max)) => ui.slider(&parameter.name, min as i32, max as i32, &mut value),
+                    None => ui.input_int(&parameter.name, &mut value).build(),
+                };
+                if changed {
+                    parameter.set_int(constants, value);
+                }
+            }
+            ParameterType::Color => {
+                let mut value = parameter.floats(constants);
+                if ui.color_edit4(&parameter.name, &mut value) {
+                    parameter.set_floats(constants, &value);
+                }
+            }
+            kind => {
+                let mut value = parameter.floats(constants);
+                let components = &mut value[..kind.components() as usize];
+                let changed = match parameter.range {
+                    Some((min, max)) => ui.slider_config(&parameter.name, min, max).build_array(components),
+                    None => imgui::Drag::new(&parameter.name).speed(0.01).build_array(ui, components),
+                };
+                if changed {
+                    parameter.set_floats(constants, &value);
+                }
+            }
+        }
+    }
 
     fn get_next_window<'a>(ui:&'a imgui::Ui, name:&'a str, position:[u8; 2]) -> imgui::Window<'a, 'a, &'a str> {
         let position = [32.0+position[0] as f32*256.0, 32.0+position[1] as f32*32.0];
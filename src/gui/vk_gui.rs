@@ -10,6 +10,7 @@ use crate::vulkan::pipeline;
 
 use super::InputData;
 
+use std::slice::from_ref;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::mem::ManuallyDrop;
@@ -35,6 +36,7 @@ pub struct Gui{
 
 impl Gui {
     pub fn init(window:&mut Window, v_init:&mut VInit) -> Self {
+        let in_flight_frames = v_init.frames_in_flight();
         let VInit{
             instance,
             p_device,
@@ -45,7 +47,7 @@ impl Gui {
         } = v_init;
         
         let imgui_allocator = vk_create_interpreter(Allocator::create(instance, &p_device, device), "allocator").into_inner();
-        Self::create(window, device, swapchain, &command_control.pool, imgui_allocator)
+        Self::create(window, device, swapchain, &command_control.pool, imgui_allocator, in_flight_frames)
     }
     
     pub fn create(
@@ -54,7 +56,7 @@ impl Gui {
         swapchain: &Swapchain,
         command_pool: &vk::CommandPool,
         allocator: gpu_vk::Allocator,
-        
+        in_flight_frames: usize,
     ) -> Self {
         logger::create!("imgui");
         
@@ -71,7 +73,7 @@ impl Gui {
         let mut context = imgui::Context::create();
         
         let options_arg = Some(Options{
-            in_flight_frames:2,
+            in_flight_frames,
             enable_depth_test:false,
             enable_depth_write:false,
         });
@@ -115,7 +117,7 @@ impl Gui {
         let draw_data = self.context.render();
         
         let color_attachment_info = pipeline::rendering_attachment_info(view, None, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
-        let rendering_info = pipeline::rendering_info(extent, &color_attachment_info, None);
+        let rendering_info = pipeline::rendering_info(extent, from_ref(&color_attachment_info), None);
         
         unsafe{device.cmd_begin_rendering(cmd, &rendering_info)};
         self.renderer.cmd_draw(cmd, draw_data).unwrap();
@@ -4,6 +4,7 @@ mod gui;
 mod errors;
 mod logger;
 mod constants;
+mod config;
 mod utility;
 mod graphics; 
 mod player;
@@ -43,8 +44,18 @@ fn main() {
     
     //run("res/gltf/basicmesh.glb").expect("runtime error");
     
+    let config = config::Config::load(constants::CONFIG_PATH);
+    
     let mut window = window::Window::init();
-    let mut v_init = vulkan::VInit::init(&mut window);
+    let mut v_init = match vulkan::VInit::init(&mut window, &config) {
+        Ok(v_init) => v_init,
+        Err(error) => {
+            logger::various_log!("main",
+                (logger::Error, "vulkan could not be initialized: {}", error)
+            );
+            return;
+        }
+    };
     let gui = gui::Gui::init(&mut window, &mut v_init);
     
     let mut holder_struct = HolderStruct::new(window, v_init, gui);
@@ -61,8 +72,8 @@ fn main() {
         
         v_init.handle_events(window);
         
-        let (static_metadata, transformations, modifiable_metadata) = v_init.get_gui_data();
-        gui.draw_ui(window, static_metadata, transformations, modifiable_metadata);
+        let (view, state) = v_init.get_gui_data();
+        gui.draw_ui(window, view, state);
         
         v_init.gui_tick(gui.get_ui_data());
         
@@ -6,15 +6,28 @@ layout(set = 0, binding = 0) uniform  SceneData{
 	vec4 ambientColor;
 	vec4 sunlightDirection; //w for sun power
 	vec4 sunlightColor;
+	vec4 jitter; //xy sub-pixel offset of the projection in NDC
 } sceneData;
 
-layout(set = 1, binding = 0) uniform GLTFMaterialData{   
+struct MaterialData {
 
 	vec4 colorFactors;
 	vec4 metal_rough_factors;
-	
-} materialData;
+	uint colorImage;
+	uint colorSampler;
+	uint metalImage;
+	uint metalSampler;
+	vec4 extra[13];
+};
 
-layout(set = 1, binding = 1) uniform sampler2D colorTex;
-layout(set = 1, binding = 2) uniform sampler2D metalRoughTex;
+//bindless arrays, indices come from the material constants
+layout(set = 1, binding = 0) uniform texture2D bindlessTextures[];
+layout(set = 1, binding = 1) uniform sampler bindlessSamplers[];
+layout(set = 1, binding = 2, std430) readonly buffer MaterialBuffer{
+
+	MaterialData materials[];
+} bindlessMaterials[];
+
+#define BINDLESS_TEXTURE(image, smp) sampler2D(bindlessTextures[nonuniformEXT(image)], bindlessSamplers[nonuniformEXT(smp)])
+#define BINDLESS_MATERIAL(buffer, index) bindlessMaterials[nonuniformEXT(buffer)].materials[index]
 
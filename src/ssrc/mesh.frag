@@ -1,21 +1,74 @@
 #version 450
 
 #extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_nonuniform_qualifier : require
 #include "input_structures.glsl"
 
+//debug variants of the material pass, 0 is the shaded scene
+//1 wireframe, 2 world normals, 3 uv checker, 4 linear depth, 5 surface id, 6 overdraw
+#ifndef DEBUG_VIEW
+#define DEBUG_VIEW 0
+#endif
+
+//bindless indices of the checkerboard the uv checker samples
+#ifndef CHECKER_IMAGE
+#define CHECKER_IMAGE 0
+#endif
+#ifndef CHECKER_SAMPLER
+#define CHECKER_SAMPLER 0
+#endif
+
 layout (location = 0) in vec3 inNormal;
 layout (location = 1) in vec3 inColor;
 layout (location = 2) in vec2 inUV;
+layout (location = 3) flat in uvec2 inMaterial;
+layout (location = 4) in vec4 inCurrentPosition;
+layout (location = 5) in vec4 inPreviousPosition;
+layout (location = 6) in vec3 inWorldNormal;
+layout (location = 7) flat in uint inSurface;
 
 layout (location = 0) out vec4 outFragColor;
+//uv offset from where the surface was last frame to where it is now
+layout (location = 1) out vec2 outMotion;
+
+//spreads neighbouring ids over unrelated hues
+vec3 surfaceColor(uint id)
+{
+	id ^= id >> 16;
+	id *= 0x7feb352du;
+	id ^= id >> 15;
+	id *= 0x846ca68bu;
+	id ^= id >> 16;
+	return vec3(id & 0xffu, (id >> 8) & 0xffu, (id >> 16) & 0xffu) / 255.0f;
+}
 
 void main() 
 {
+	outMotion = (inCurrentPosition.xy / inCurrentPosition.w - inPreviousPosition.xy / inPreviousPosition.w) * 0.5f;
+
+#if DEBUG_VIEW == 1
+	outFragColor = vec4(0.1f, 1.0f, 0.3f, 1.0f);
+#elif DEBUG_VIEW == 2
+	outFragColor = vec4(normalize(inWorldNormal) * 0.5f + 0.5f, 1.0f);
+#elif DEBUG_VIEW == 3
+	//repeated so the texel density can be compared between surfaces
+	outFragColor = vec4(texture(BINDLESS_TEXTURE(CHECKER_IMAGE, CHECKER_SAMPLER), inUV * 8.0f).xyz, 1.0f);
+#elif DEBUG_VIEW == 4
+	//clip w is the distance along the view axis, squashed so the surroundings of the camera span most of the range
+	float viewDistance = inCurrentPosition.w;
+	outFragColor = vec4(vec3(viewDistance / (viewDistance + 4.0f)), 1.0f);
+#elif DEBUG_VIEW == 5
+	outFragColor = vec4(surfaceColor(inSurface), 1.0f);
+#elif DEBUG_VIEW == 6
+	//summed by the blending, every layer warms the pixel up
+	outFragColor = vec4(0.12f, 0.05f, 0.01f, 1.0f);
+#else
 	float lightValue = max(dot(inNormal, sceneData.sunlightDirection.xyz), 0.1f);
 
-	vec3 color = inColor * texture(colorTex,inUV).xyz;
+	MaterialData material = BINDLESS_MATERIAL(inMaterial.x, inMaterial.y);
+	vec3 color = inColor * texture(BINDLESS_TEXTURE(material.colorImage, material.colorSampler),inUV).xyz;
 	vec3 ambient = color *  sceneData.ambientColor.xyz;
 
 	outFragColor = vec4(color * lightValue *  sceneData.sunlightColor.w + ambient ,1.0f);
+#endif
 }
-
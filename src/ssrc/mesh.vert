@@ -2,12 +2,18 @@
 
 #extension GL_GOOGLE_include_directive : require
 #extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
 
 #include "input_structures.glsl"
 
 layout (location = 0) out vec3 outNormal;
 layout (location = 1) out vec3 outColor;
 layout (location = 2) out vec2 outUV;
+layout (location = 3) flat out uvec2 outMaterial;
+layout (location = 4) out vec4 outCurrentPosition;
+layout (location = 5) out vec4 outPreviousPosition;
+layout (location = 6) out vec3 outWorldNormal;
+layout (location = 7) flat out uint outSurface;
 
 struct Vertex {
 
@@ -22,11 +28,21 @@ layout(buffer_reference, std430) readonly buffer VertexBuffer{
 	Vertex vertices[];
 };
 
+//per object data of the frame, read by the motion vectors and the debug views
+layout(buffer_reference, std430) readonly buffer ObjectData{ 
+	mat4 previousMatrix; //clip matrix the object was drawn with last frame, without jitter
+	mat4 worldMatrix;
+	uint surface; //stable id of the surface, colored by the surface id view
+};
+
 //push constants block
 layout( push_constant ) uniform constants
 {
 	mat4 render_matrix;
 	VertexBuffer vertexBuffer;
+	ObjectData objectData;
+	uint materialBuffer;
+	uint materialIndex;
 } PushConstants;
 
 void main() 
@@ -36,10 +52,17 @@ void main()
 	vec4 position = vec4(v.position, 1.0f);
 
 	gl_Position =  sceneData.viewproj * PushConstants.render_matrix *position;
+	//motion is measured between unjittered positions so the jitter itself never shows up as movement
+	outCurrentPosition = gl_Position - vec4(sceneData.jitter.xy * gl_Position.w, 0.0f, 0.0f);
+	outPreviousPosition = sceneData.viewproj * PushConstants.objectData.previousMatrix * position;
 
 	outNormal = (PushConstants.render_matrix * vec4(v.normal, 0.f)).xyz;
-	outColor = v.color.xyz * materialData.colorFactors.xyz;	
+	outWorldNormal = transpose(inverse(mat3(PushConstants.objectData.worldMatrix))) * v.normal;
+	outSurface = PushConstants.objectData.surface;
+	MaterialData material = BINDLESS_MATERIAL(PushConstants.materialBuffer, PushConstants.materialIndex);
+	outColor = v.color.xyz * material.colorFactors.xyz;	
 	outUV.x = v.uv_x;
 	outUV.y = v.uv_y;
+	outMaterial = uvec2(PushConstants.materialBuffer, PushConstants.materialIndex);
 }
 
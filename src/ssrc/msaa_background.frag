@@ -0,0 +1,11 @@
+#version 460
+
+//the single-sampled background, every sample of the pixel gets its texel
+layout(rgba16f,set = 0, binding = 0) uniform readonly image2D background;
+
+layout (location = 0) out vec4 outFragColor;
+
+void main() 
+{
+    outFragColor = imageLoad(background, ivec2(gl_FragCoord.xy));
+}
@@ -0,0 +1,263 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::exposure;
+
+use crate::graphics::ComputePushConstants;
+use crate::graphics::ExposureSettings;
+use crate::graphics::ExposureReadout;
+
+use super::VkDestructor;
+use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::super::VkDynamicDestructor;
+use super::super::object_name;
+use super::super::Device;
+use super::super::Allocator;
+use super::super::CommandControl;
+use super::super::Image;
+use super::super::TypedBuffer;
+use super::super::CPipeline;
+use super::super::EffectImage;
+use super::super::DescriptorWriter;
+use super::super::GDescriptorAllocator;
+use super::super::DestructionStack;
+use super::super::memory;
+use super::super::image;
+
+use std::slice::from_ref;
+use std::path::Path;
+use std::time::Instant;
+
+use ash::vk;
+use nalgebra::Vector4;
+
+// Meters the render image into a histogram and eases the exposure toward mid grey, the post chain reads the result.
+pub struct AutoExposure {
+    // Histogram and averaging, missing while their shaders don't build so the chain keeps running unexposed.
+    pipelines: Option<(CPipeline, CPipeline)>,
+    histogram: TypedBuffer<u32>,
+    state: Image,
+    // One per frame in flight, read once the frame that copied into it is done.
+    readbacks: Vec<TypedBuffer<[f32; 4]>>,
+    written: Vec<bool>,
+    pub settings: ExposureSettings,
+    readout: ExposureReadout,
+    last_update: Instant,
+}
+
+impl AutoExposure {
+
+//----
+    pub fn create(device:&mut Device, allocator:&mut Allocator, cmd_ctrl:&mut CommandControl, frames_in_flight:usize) -> Result<Self, AAError> {
+        logger::create!("auto_exposure");
+        
+        let histogram = TypedBuffer::<u32>::create(device, allocator, Some("luminance histogram"), exposure::HISTOGRAM_BINS, vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST, memory::GpuOnly)?;
+        let mut state = match Image::create(device, allocator, vk::Extent3D{width:1, height:1, depth:1}, image::EXPOSURE, None) {
+            Ok(state) => state,
+            Err(error) => {
+                histogram.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
+        
+        // The averaging pass clears the bins after reading them, they only have to start at zero.
+        let cleared = cmd_ctrl.run_su_buffer(device, &mut |device, cmd|{
+            unsafe{device.cmd_fill_buffer(cmd, histogram.underlying(), 0, vk::WHOLE_SIZE, 0)};
+            state.transition_to(device, cmd, vk::ImageLayout::GENERAL);
+            unsafe{device.cmd_clear_color_image(cmd, state.underlying(), vk::ImageLayout::GENERAL, &vk::ClearColorValue::default(), from_ref(&Image::subresource_range(vk::ImageAspectFlags::COLOR)))};
+            Ok(())
+        });
+        
+        let mut readbacks = Vec::with_capacity(frames_in_flight);
+        let built = cleared.and_then(|_|{
+            for _ in 0..frames_in_flight {
+                readbacks.push(TypedBuffer::<[f32; 4]>::create(device, allocator, Some("exposure readback"), 1, vk::BufferUsageFlags::TRANSFER_DST, memory::GpuToCpu)?);
+            }
+            Ok(())
+        });
+        if let Err(error) = built {
+            for readback in readbacks.into_iter() {
+                readback.destruct(VkDestructorArguments::DevAll(device, allocator));
+            }
+            histogram.destruct(VkDestructorArguments::DevAll(device, allocator));
+            state.destruct(VkDestructorArguments::DevAll(device, allocator));
+            return Err(error);
+        }
+        
+        Ok(Self{
+            pipelines: None,
+            histogram,
+            state,
+            written: vec![false; frames_in_flight],
+            readbacks,
+            settings: ExposureSettings::default(),
+            readout: ExposureReadout::default(),
+            last_update: Instant::now(),
+        })
+    }
+    
+//----
+    // Builds both pipelines, on failure the previous ones, if any, stay in use.
+    pub fn build_pipelines(&mut self, device:&mut Device, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        let histogram = CPipeline::create(device, exposure::HISTOGRAM_SHADER, &[EffectImage::Draw], [16, 16])?;
+        let average = match CPipeline::create(device, exposure::AVERAGE_SHADER, &[EffectImage::Draw], [exposure::HISTOGRAM_BINS as u32, 1]) {
+            Ok(average) => average,
+            Err(error) => {
+                histogram.destruct(VkDestructorArguments::Dev(device));
+                return Err(error);
+            }
+        };
+        if let Some(old) = self.pipelines.replace((histogram, average)) {
+            let callback = Box::new(move |mut args:VkDestructorArguments|{
+                let device = args.unwrap_dev();
+                old.0.destruct(VkDestructorArguments::Dev(device));
+                old.1.destruct(VkDestructorArguments::Dev(device));
+            });
+            destruction_stack.push(VkDynamicDestructor::new::<CPipeline>(object_name("auto_exposure"), VkDestructorType::Dev, callback));
+        }
+        Ok(())
+    }
+    
+//----
+    pub fn reload(&mut self, device:&mut Device, shader:&Path, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        if [exposure::HISTOGRAM_SHADER, exposure::AVERAGE_SHADER].iter().all(|file|Path::new(file) != shader) {
+            return Ok(());
+        }
+        logger::various_log!("auto_exposure",
+            (logger::Debug, "reloading auto-exposure pipelines")
+        );
+        self.build_pipelines(device, destruction_stack)
+    }
+    
+//----
+    // Picks up what the frame wrote the last time it ran, the caller has already waited for it.
+    pub fn update_readout(&mut self, device:&Device, frame:usize, value:u64) {
+        if !self.written[frame] {
+            return;
+        }
+        if let Ok(values) = self.readbacks[frame].read_back(device, value, 0, 1) {
+            let [luminance, exposure, target, _] = values[0];
+            self.readout = ExposureReadout{luminance, exposure, target};
+        }
+    }
+    
+//----
+    pub fn readout(&self) -> ExposureReadout {
+        self.readout
+    }
+    
+//----
+    pub fn state(&self) -> &Image {
+        &self.state
+    }
+    
+//----
+    pub fn state_mut(&mut self) -> &mut Image {
+        &mut self.state
+    }
+    
+//----
+    pub fn histogram(&self) -> &TypedBuffer<u32> {
+        &self.histogram
+    }
+    
+//----
+    pub fn readback(&self, frame:usize) -> &TypedBuffer<[f32; 4]> {
+        &self.readbacks[frame]
+    }
+    
+//----
+    pub fn pipelines(&self) -> Option<&(CPipeline, CPipeline)> {
+        self.pipelines.as_ref()
+    }
+    
+//----
+    // Push constants of both passes for a frame metering `extent`, adaptation follows the time since the last call.
+    pub fn push_constants(&mut self, extent:vk::Extent2D) -> ComputePushConstants {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+        
+        let ExposureSettings{enabled, min_ev, max_ev, speed} = self.settings;
+        let adaptation = 1.0 - (-elapsed * speed).exp();
+        ComputePushConstants([
+            Vector4::new(min_ev, (max_ev - min_ev).max(f32::EPSILON), adaptation, enabled as u32 as f32),
+            Vector4::new((extent.width * extent.height) as f32, exposure::KEY, extent.width as f32, extent.height as f32),
+            Vector4::zeros(),
+            Vector4::zeros(),
+        ])
+    }
+    
+//----
+    // Sets for the histogram and the averaging pass, both bind their image at 0 and the histogram at 1.
+    pub fn allocate_descriptors(&self, device:&mut Device, allocator:&mut GDescriptorAllocator, render_image:&Image) -> Result<Option<(vk::DescriptorSet, vk::DescriptorSet)>, AAError> {
+        let Some((histogram_pipeline, average_pipeline)) = self.pipelines.as_ref() else {
+            return Ok(None);
+        };
+        let mut sets = [vk::DescriptorSet::null(); 2];
+        for (set, (pipeline, view)) in sets.iter_mut().zip([(histogram_pipeline, render_image.view), (average_pipeline, self.state.view)]) {
+            *set = allocator.allocate(device, &pipeline.descriptor_layout)?;
+            let mut writer = DescriptorWriter::validated(pipeline.signature.clone());
+            writer.write_image(0, view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+            writer.write_buffer(1, self.histogram.underlying(), self.histogram.size(), 0, vk::DescriptorType::STORAGE_BUFFER);
+            writer.update_set(device, *set)?;
+        }
+        Ok(Some((sets[0], sets[1])))
+    }
+    
+//----
+    pub fn record_histogram(device:&Device, cmd:vk::CommandBuffer, pipeline:&CPipeline, descriptor:vk::DescriptorSet, push_constants:&ComputePushConstants, extent:vk::Extent2D) {
+        Self::bind(device, cmd, pipeline, descriptor, push_constants);
+        unsafe{device.cmd_dispatch(cmd, extent.width.div_ceil(16), extent.height.div_ceil(16), 1)};
+    }
+    
+//----
+    pub fn record_average(device:&Device, cmd:vk::CommandBuffer, pipeline:&CPipeline, descriptor:vk::DescriptorSet, push_constants:&ComputePushConstants) {
+        Self::bind(device, cmd, pipeline, descriptor, push_constants);
+        unsafe{device.cmd_dispatch(cmd, 1, 1, 1)};
+    }
+    
+//----
+    pub fn record_readback(device:&Device, cmd:vk::CommandBuffer, state:vk::Image, readback:vk::Buffer) {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(*subresource)
+            .image_extent(vk::Extent3D{width:1, height:1, depth:1});
+        
+        unsafe{device.cmd_copy_image_to_buffer(cmd, state, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, readback, from_ref(&region))};
+    }
+    
+//----
+    pub fn mark_written(&mut self, frame:usize) {
+        self.written[frame] = true;
+    }
+    
+//----
+    fn bind(device:&Device, cmd:vk::CommandBuffer, pipeline:&CPipeline, descriptor:vk::DescriptorSet, push_constants:&ComputePushConstants) {
+        unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline)};
+        unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::COMPUTE, pipeline.layout, 0, from_ref(&descriptor), &[])};
+        let push_constants_slice = unsafe{crate::any_as_u8_slice(push_constants)};
+        unsafe{device.cmd_push_constants(cmd, pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, push_constants_slice)};
+    }
+}
+
+impl VkDestructor for AutoExposure {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        logger::destruct!("auto_exposure");
+        let (device, allocator) = args.unwrap_dev_all();
+        if let Some((histogram, average)) = self.pipelines {
+            histogram.destruct(VkDestructorArguments::Dev(device));
+            average.destruct(VkDestructorArguments::Dev(device));
+        }
+        for readback in self.readbacks.into_iter() {
+            readback.destruct(VkDestructorArguments::DevAll(device, allocator));
+        }
+        self.histogram.destruct(VkDestructorArguments::DevAll(device, allocator));
+        self.state.destruct(VkDestructorArguments::DevAll(device, allocator));
+    }
+}
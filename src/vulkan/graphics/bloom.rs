@@ -0,0 +1,191 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::bloom;
+
+use crate::graphics::ComputePushConstants;
+
+use super::VkDestructor;
+use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::super::VkDynamicDestructor;
+use super::super::object_name;
+use super::super::Device;
+use super::super::Allocator;
+use super::super::Image;
+use super::super::CPipeline;
+use super::super::EffectImage;
+use super::super::DescriptorWriter;
+use super::super::GDescriptorAllocator;
+use super::super::DestructionStack;
+use super::super::image;
+
+use std::path::Path;
+
+use ash::vk;
+use nalgebra::Vector4;
+
+// Blurs the bright part of the image down a chain of halved levels and back up, the bloom post effect adds the first level.
+pub struct Bloom {
+    // Downsampling and upsampling, missing while their shaders don't build so effects binding the chain are skipped.
+    pipelines: Option<(CPipeline, CPipeline)>,
+    levels: Vec<Image>,
+}
+
+// Sets for one run of the chain, downsampling fills every level from the one above and upsampling walks them back.
+pub struct BloomDescriptors {
+    pub downsample: Vec<vk::DescriptorSet>,
+    pub upsample: Vec<vk::DescriptorSet>,
+}
+
+impl Bloom {
+    
+//----
+    pub fn create(device:&mut Device, allocator:&mut Allocator, extent:vk::Extent3D) -> Result<Self, AAError> {
+        logger::create!("bloom");
+        
+        let mut levels:Vec<Image> = Vec::with_capacity(bloom::LEVELS);
+        let mut level_extent = extent;
+        while levels.len() < bloom::LEVELS && (level_extent.width > 1 || level_extent.height > 1) {
+            level_extent = vk::Extent3D{
+                width: level_extent.width.div_ceil(2),
+                height: level_extent.height.div_ceil(2),
+                depth: 1,
+            };
+            match Image::create(device, allocator, level_extent, image::BLOOM, None) {
+                Ok(level) => levels.push(level),
+                Err(error) => {
+                    for level in levels.into_iter() {
+                        level.destruct(VkDestructorArguments::DevAll(device, allocator));
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        
+        Ok(Self{
+            pipelines: None,
+            levels,
+        })
+    }
+    
+//----
+    // Builds both pipelines, on failure the previous ones, if any, stay in use.
+    pub fn build_pipelines(&mut self, device:&mut Device, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        let images = [EffectImage::Draw, EffectImage::Input];
+        let downsample = CPipeline::create(device, bloom::DOWNSAMPLE_SHADER, &images, [16, 16])?;
+        let upsample = match CPipeline::create(device, bloom::UPSAMPLE_SHADER, &images, [16, 16]) {
+            Ok(upsample) => upsample,
+            Err(error) => {
+                downsample.destruct(VkDestructorArguments::Dev(device));
+                return Err(error);
+            }
+        };
+        if let Some(old) = self.pipelines.replace((downsample, upsample)) {
+            let callback = Box::new(move |mut args:VkDestructorArguments|{
+                let device = args.unwrap_dev();
+                old.0.destruct(VkDestructorArguments::Dev(device));
+                old.1.destruct(VkDestructorArguments::Dev(device));
+            });
+            destruction_stack.push(VkDynamicDestructor::new::<CPipeline>(object_name("bloom"), VkDestructorType::Dev, callback));
+        }
+        Ok(())
+    }
+    
+//----
+    pub fn reload(&mut self, device:&mut Device, shader:&Path, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        if [bloom::DOWNSAMPLE_SHADER, bloom::UPSAMPLE_SHADER].iter().all(|file|Path::new(file) != shader) {
+            return Ok(());
+        }
+        logger::various_log!("bloom",
+            (logger::Debug, "reloading bloom pipelines")
+        );
+        self.build_pipelines(device, destruction_stack)
+    }
+    
+//----
+    pub fn levels(&self) -> &[Image] {
+        &self.levels
+    }
+    
+//----
+    pub fn levels_mut(&mut self) -> &mut [Image] {
+        &mut self.levels
+    }
+    
+//----
+    pub fn pipelines(&self) -> Option<&(CPipeline, CPipeline)> {
+        self.pipelines.as_ref()
+    }
+    
+//----
+    // View the effects bind as their bloom image, the first level of the chain.
+    pub fn view(&self) -> vk::ImageView {
+        self.levels.first().map(|level|level.view).unwrap_or_default()
+    }
+    
+//----
+    // Push constants of the downsampling passes, only the first one thresholds and weights down bright texels.
+    pub fn downsample_constants(threshold:f32, first:bool) -> ComputePushConstants {
+        ComputePushConstants([
+            Vector4::new(threshold, first as u32 as f32, 0.0, 0.0),
+            Vector4::zeros(),
+            Vector4::zeros(),
+            Vector4::zeros(),
+        ])
+    }
+    
+//----
+    // Push constants of the upsampling passes, `radius` spreads the tent filter in texels of the smaller level.
+    pub fn upsample_constants(radius:f32) -> ComputePushConstants {
+        ComputePushConstants([
+            Vector4::new(radius, 0.0, 0.0, 0.0),
+            Vector4::zeros(),
+            Vector4::zeros(),
+            Vector4::zeros(),
+        ])
+    }
+    
+//----
+    // Both passes bind the level they write at 0 and the one they read at 1, the first level reads `input`.
+    pub fn allocate_descriptors(&self, device:&mut Device, allocator:&mut GDescriptorAllocator, input:vk::ImageView) -> Result<Option<BloomDescriptors>, AAError> {
+        let Some((downsample_pipeline, upsample_pipeline)) = self.pipelines.as_ref() else {
+            return Ok(None);
+        };
+        let mut descriptors = BloomDescriptors{
+            downsample: Vec::with_capacity(self.levels.len()),
+            upsample: Vec::with_capacity(self.levels.len()),
+        };
+        let sources = std::iter::once(input).chain(self.levels.iter().map(|level|level.view));
+        for (level, source) in self.levels.iter().zip(sources) {
+            descriptors.downsample.push(Self::write_set(device, allocator, downsample_pipeline, level.view, source)?);
+        }
+        for pair in self.levels.windows(2) {
+            descriptors.upsample.push(Self::write_set(device, allocator, upsample_pipeline, pair[0].view, pair[1].view)?);
+        }
+        Ok(Some(descriptors))
+    }
+    
+//----
+    fn write_set(device:&mut Device, allocator:&mut GDescriptorAllocator, pipeline:&CPipeline, target:vk::ImageView, source:vk::ImageView) -> Result<vk::DescriptorSet, AAError> {
+        let set = allocator.allocate(device, &pipeline.descriptor_layout)?;
+        let mut writer = DescriptorWriter::validated(pipeline.signature.clone());
+        writer.write_image(0, target, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+        writer.write_image(1, source, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+        writer.update_set(device, set)?;
+        Ok(set)
+    }
+}
+
+impl VkDestructor for Bloom {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        logger::destruct!("bloom");
+        let (device, allocator) = args.unwrap_dev_all();
+        if let Some((downsample, upsample)) = self.pipelines {
+            downsample.destruct(VkDestructorArguments::Dev(device));
+            upsample.destruct(VkDestructorArguments::Dev(device));
+        }
+        for level in self.levels.into_iter() {
+            level.destruct(VkDestructorArguments::DevAll(device, allocator));
+        }
+    }
+}
@@ -1,8 +1,5 @@
-use crate::constants;
 use crate::AAError;
 
-use crate::errors::messages::GRANTED;
-
 use crate::logger;
 
 
@@ -11,12 +8,12 @@ use super::VkDestructorArguments;
 use super::super::PDevice;
 use super::super::Device;
 use super::super::GDescriptorAllocator;
-use super::super::DescriptorLayoutBuilder;
+use super::super::DescriptorPoolCount;
+use super::super::DescriptorStats;
 use super::super::DestructionStack;
 
 
 use ash::vk;
-use arrayvec::ArrayVec;
 use derivative::Derivative;
 
 #[derive(Derivative)]
@@ -24,7 +21,7 @@ use derivative::Derivative;
 pub struct FrameData {
     pub image_available_semaphore: vk::Semaphore,
     pub render_finished_semaphore: vk::Semaphore,
-    pub inflight_fence: vk::Fence,
+    pub timeline_value: u64,
     
     pub cmd_pool: vk::CommandPool,
     pub cmd_buffer: vk::CommandBuffer,
@@ -37,21 +34,18 @@ pub struct FrameData {
 
 
 pub struct FramesData (
-    [FrameData; constants::fif::USIZE],
+    Vec<FrameData>,
 );
 
 
 
 impl FrameData {
-    pub fn create(p_device:&PDevice, device:&mut Device) -> Result<Self, AAError> {
+    pub fn create(p_device:&PDevice, device:&mut Device, descriptor_ratios:&DescriptorPoolCount) -> Result<Self, AAError> {
         
         let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
-        let fence_create_info = vk::FenceCreateInfo::builder()
-            .flags(vk::FenceCreateFlags::SIGNALED);
         
         let image_available_semaphore = unsafe{device.create_semaphore(&semaphore_create_info, None)}?;
         let render_finished_semaphore = unsafe{device.create_semaphore(&semaphore_create_info, None)}?;
-        let inflight_fence =  unsafe{device.create_fence(&fence_create_info, None)}?;
         
         let create_info = vk::CommandPoolCreateInfo::builder()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
@@ -67,20 +61,13 @@ impl FrameData {
         let buffer_vec = unsafe{device.allocate_command_buffers(&create_info)}?;
         let cmd_buffer = buffer_vec[0];
         
-        let mut ds_layout_builder = DescriptorLayoutBuilder::create();
-        ds_layout_builder.add_binding(0, vk::DescriptorType::STORAGE_IMAGE, 3);
-        ds_layout_builder.add_binding(0, vk::DescriptorType::STORAGE_BUFFER, 3);
-        ds_layout_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 3);
-        ds_layout_builder.add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4);
-        let descriptor_counts = ds_layout_builder.assemble();
-        
-        let descriptor_allocator:GDescriptorAllocator = GDescriptorAllocator::create(device, descriptor_counts).unwrap();
+        let descriptor_allocator:GDescriptorAllocator = GDescriptorAllocator::create(device, descriptor_ratios.clone())?;
         let destruction_stack = DestructionStack::default();
         
         Ok(Self{
             image_available_semaphore,
             render_finished_semaphore,
-            inflight_fence,
+            timeline_value: 0,
             cmd_pool,
             cmd_buffer,
             descriptor_allocator,
@@ -89,8 +76,12 @@ impl FrameData {
     }
     
 
-    pub(in self) fn get_sync(&mut self) -> (vk::Semaphore, vk::Semaphore, vk::Fence) {
-        (self.image_available_semaphore, self.render_finished_semaphore, self.inflight_fence)
+    pub(in self) fn get_sync(&mut self) -> (vk::Semaphore, vk::Semaphore, u64) {
+        (self.image_available_semaphore, self.render_finished_semaphore, self.timeline_value)
+    }
+    
+    pub(in self) fn set_timeline_value(&mut self, value:u64) {
+        self.timeline_value = value;
     }
     
     pub(in self) fn get_command_buffer(&mut self) -> vk::CommandBuffer {
@@ -117,21 +108,26 @@ impl FrameData {
 }
 
 impl FramesData {
-    pub fn create(p_device:&PDevice, device:&mut Device) -> Result<Self, AAError> {
-        let mut holder:ArrayVec<FrameData, {constants::fif::USIZE}> = ArrayVec::new();
-        for _index in 0..constants::fif::USIZE {
+    // Descriptor pools are sized for the layouts allocated every frame, one group per set.
+    pub fn create(p_device:&PDevice, device:&mut Device, frames_in_flight:usize, descriptor_ratios:&DescriptorPoolCount) -> Result<Self, AAError> {
+        let mut holder = Vec::with_capacity(frames_in_flight);
+        for _index in 0..frames_in_flight {
             logger::create!("frame_data");
-            let frame_data = FrameData::create(p_device, device)?;
+            let frame_data = FrameData::create(p_device, device, descriptor_ratios)?;
             holder.push(frame_data);
         }
-        let holder = holder.into_inner().expect(GRANTED);
         Ok(Self(holder))
     }
     
-    pub fn get_frame_sync(&mut self, frame:usize) -> (vk::Semaphore, vk::Semaphore, vk::Fence) {
+    // The timeline value is the one signaled by the last submission of this frame.
+    pub fn get_frame_sync(&mut self, frame:usize) -> (vk::Semaphore, vk::Semaphore, u64) {
         self.0[frame].get_sync()
     }
     
+    pub fn set_frame_timeline_value(&mut self, frame:usize, value:u64) {
+        self.0[frame].set_timeline_value(value)
+    }
+    
     pub fn get_frame_command_buffer(&mut self, frame:usize) -> vk::CommandBuffer {
         self.0[frame].get_command_buffer()
     }
@@ -148,6 +144,10 @@ impl FramesData {
         self.0[frame].get_references()
     }
     
+    pub fn descriptor_stats(&self) -> Vec<DescriptorStats> {
+        self.0.iter().map(|frame_data|frame_data.descriptor_allocator.stats()).collect()
+    }
+    
 }
 
 
@@ -161,7 +161,6 @@ impl VkDestructor for FrameData {
         logger::destruct!("sync_objects");
         unsafe{device.destroy_semaphore(self.image_available_semaphore, None)};
         unsafe{device.destroy_semaphore(self.render_finished_semaphore, None)};
-        unsafe{device.destroy_fence(self.inflight_fence, None)};
         self.descriptor_allocator.destruct(VkDestructorArguments::Dev(device));
         
         self.destruction_stack.dispatch(device, allocator);
@@ -1,25 +1,24 @@
 use crate::AAError;
 use crate::logger;
-use crate::errors::messages::VK_CAST;
-use crate::errors::messages::GRANTED;
 use crate::errors::messages::MODEL_DENSITY;
 
 use super::Vertex;
 use super::VkGeoSurface;
+use super::Bounds;
 
 use super::VkDestructor;
 use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::Registered;
 use super::super::Device;
 use super::super::Allocator;
 use super::super::CommandControl;
-use super::super::Buffer;
+use super::super::TypedBuffer;
 use super::super::memory;
 
-use std::mem::size_of_val;
 use std::path::Path;
 use std::fs; 
 use std::io;
-use std::rc::Rc;
 
 use arrayvec::ArrayString;
 use ash::vk;
@@ -30,8 +29,8 @@ use na::Vector4;
 
 #[derive(Debug)]
 pub struct VkMeshBuffers {
-    pub index_buffer: Buffer,
-    pub vertex_buffer: Buffer,
+    pub index_buffer: TypedBuffer<u32>,
+    pub vertex_buffer: TypedBuffer<Vertex>,
     pub vertex_buffer_address: vk::DeviceAddress,
 }
 
@@ -40,10 +39,12 @@ pub struct VkMeshAsset {
     pub name: ArrayString<64>,
     pub surfaces: Vec<VkGeoSurface>,
     pub meshes: Vec<VkMeshBuffers>,
+    // Encloses every surface.
+    pub bounds: Bounds,
 }
 
 
-pub type VkMeshAssets = Vec<Rc<VkMeshAsset>>;
+pub type VkMeshAssets = Vec<VkMeshAsset>;
 
 pub fn load_gltf<P: AsRef<Path>>(
     device: &mut Device,
@@ -132,6 +133,7 @@ pub fn load_gltf<P: AsRef<Path>>(
                 }
             }
             
+            let first_vertex = vertices_vec.len();
             let positions = reader.read_positions().unwrap();
             logger::various_log!("mesh",
                 (logger::Trace, "vertex count {}", positions.len())
@@ -170,10 +172,15 @@ pub fn load_gltf<P: AsRef<Path>>(
             
             
             surface.count = u32::try_from(indices_vec.len()).expect(MODEL_DENSITY);
+            surface.bounds = Bounds::from_points(vertices_vec[first_vertex..].iter().map(|vertex|&vertex.position));
+            metadata_holder.bounds = match metadata_holder.surfaces.is_empty() {
+                true => surface.bounds,
+                false => metadata_holder.bounds.merge(&surface.bounds),
+            };
             metadata_holder.surfaces.push(surface);
         }
         metadata_holder.meshes.push(VkMeshBuffers::upload_mesh(device, allocator, command_control, &indices_vec, &vertices_vec[..]).unwrap());
-        holder.push(Rc::new(metadata_holder));
+        holder.push(metadata_holder);
         //holder.meshes
         
         
@@ -216,30 +223,17 @@ impl VkMeshBuffers {
         if indices.is_empty() || vertices.is_empty() {
             return Err(AAError::EmptyMesh);
         }
-        let indices_size = indices.len() * size_of_val(&indices[0]);
-        let indices_size_u64 = u64::try_from(indices_size).expect(VK_CAST);
-        let vertices_size = vertices.len() * size_of_val(&vertices[0]);
-        let vertices_size_u64 = u64::try_from(vertices_size).expect(VK_CAST);
-        
         use vk::BufferUsageFlags as buf;
-        let mut vertex_buffer = Buffer::create(device, allocator, Some("mesh vertex buffer"), vertices_size_u64, buf::VERTEX_BUFFER|buf::STORAGE_BUFFER|buf::SHADER_DEVICE_ADDRESS|buf::TRANSFER_DST, memory::GpuOnly)?;
+        let vertex_buffer = TypedBuffer::from_slice(device, allocator, command, Some("mesh vertex buffer"), vertices, buf::VERTEX_BUFFER|buf::STORAGE_BUFFER|buf::SHADER_DEVICE_ADDRESS, memory::GpuOnly)?;
         let vertex_buffer_address = vertex_buffer.get_device_address(device);
         
-        let mut index_buffer = Buffer::create(device, allocator, Some("mesh index buffer"), indices_size_u64, buf::INDEX_BUFFER|buf::TRANSFER_DST, memory::GpuOnly)?;
-        
-        let mut staging_buffer = Buffer::create(device, allocator, Some("mesh staging buffer"), indices_size_u64+vertices_size_u64, buf::TRANSFER_SRC, memory::CpuToGpu)?;
-        
-        
-        let mut vert_align = staging_buffer.get_align::<Vertex>(0, vertices_size_u64).expect(GRANTED);
-        vert_align.copy_from_slice(vertices);
-        
-        let mut index_align = staging_buffer.get_align::<u32>(vertices_size, indices_size_u64).expect(GRANTED);
-        index_align.copy_from_slice(indices);
-        
-        memory::copy_buffer_2_buffer(device, command, &staging_buffer, 0, &mut vertex_buffer, 0, vertices_size_u64);
-        memory::copy_buffer_2_buffer(device, command, &staging_buffer, vertices_size_u64, &mut index_buffer, 0, indices_size_u64);
-        
-        staging_buffer.destruct(VkDestructorArguments::DevAll(device, allocator));
+        let index_buffer = match TypedBuffer::from_slice(device, allocator, command, Some("mesh index buffer"), indices, buf::INDEX_BUFFER, memory::GpuOnly) {
+            Ok(holder) => holder,
+            Err(error) => {
+                vertex_buffer.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
         
         Ok(Self{
             vertex_buffer,
@@ -276,13 +270,16 @@ impl VkDestructor for VkMeshAsset {
     }
 }
 
+impl Registered for VkMeshAsset {
+    const DESTRUCTOR_TYPE:VkDestructorType = VkDestructorType::DevAll;
+}
+
 impl VkDestructor for VkMeshAssets {
     fn destruct(self, mut args:VkDestructorArguments) {
         //TODO: check loggic of this thing
         logger::destruct!("mesh_assets");
         let (device, allocator) = args.unwrap_dev_all();
         for metadata in self.into_iter() {
-            let metadata = Rc::try_unwrap(metadata).unwrap();
             metadata.destruct(VkDestructorArguments::DevAll(device, allocator));
         }
     }
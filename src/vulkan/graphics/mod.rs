@@ -2,7 +2,6 @@ mod mesh;
 pub use mesh::load_gltf;
 pub use mesh::VkMeshBuffers;
 pub use mesh::VkMeshAsset;
-pub use mesh::VkMeshAssets;
 
 mod frame;
 pub use frame::FramesData;
@@ -14,13 +13,40 @@ pub use r_object::IRenderable;
 mod types;
 pub use types::*;
 
+mod auto_exposure;
+pub use auto_exposure::AutoExposure;
+
+mod taa;
+pub use taa::TemporalAA;
+
+mod bloom;
+pub use bloom::Bloom;
+pub use bloom::BloomDescriptors;
+
+mod render_graph;
+pub use render_graph::RenderGraph;
+pub use render_graph::Pass;
+pub use render_graph::Access;
+pub use render_graph::ResolvedImage;
+pub use render_graph::TransientDesc;
+pub use render_graph::TransientImages;
+
 use crate::AAError;
+use crate::logger;
 use crate::gui::Gui;
+use crate::constants::msaa;
+use crate::graphics::DepthProjection;
+use crate::graphics::Frustum;
+use crate::graphics::CullingStats;
+use crate::graphics::DrawStats;
 use crate::errors::messages::SIMPLE_VK_FN;
 use crate::errors::messages::COMPILETIME_ASSERT;
 use crate::errors::messages::CPU_ACCESIBLE;
+use crate::errors::messages::VK_UNRECOVERABLE;
+use crate::errors::messages::GRANTED;
 
 pub use crate::graphics::GeoSurface;
+pub use crate::graphics::Bounds;
 pub use crate::graphics::ComputePushConstants;
 pub use crate::graphics::Vertex;
 pub use crate::graphics::GPUSceneData;
@@ -29,22 +55,40 @@ pub use crate::graphics::GPUSceneData;
 use super::VkDestructor;
 use super::VkDeferedDestructor;
 use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::VkDynamicDestructor;
+use super::Registered;
+use super::Handle;
+use super::Pool;
 use super::VInit;
 use super::Device;
 use super::Allocator;
-use super::Buffer;
+use super::TypedBuffer;
 use super::Image;
+use super::ImageMetadata;
 use super::CPipeline;
+use super::GPipelineBuilder;
+use super::DescriptorLayout;
+use super::LayoutSignature;
+use super::ShaderReflection;
+use super::EffectTargets;
+use super::EffectImage;
 use super::pipeline;
 use super::image;
+use super::DestructionStack;
+use super::object_name;
 
 use super::DescriptorWriter;
 
 use super::materials::MaterialInstance;
+use super::materials::MaterialPass;
+use super::materials::DebugPipelines;
 
 use std::slice::from_ref;
 use std::mem::size_of;
 use std::cmp::min;
+use std::path::Path;
+use std::cell::Cell;
 
 use memoffset::offset_of;
 use ash::vk;
@@ -59,37 +103,186 @@ use gpu_allocator as gpu_all;
 pub struct GPUDrawPushConstants {
     world_matrix: Matrix4<f32>,
     vertex_buffer: vk::DeviceAddress,
+    // Points at the object's entry in the frame's object data.
+    object_data: vk::DeviceAddress,
+    material_buffer: u32,
+    material_index: u32,
+}
+
+// One per object of the draw context, padded to the 16 bytes buffer references are aligned to.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GPUObjectData {
+    // Clip matrix from the previous frame, for the motion vectors.
+    previous_matrix: Matrix4<f32>,
+    world_matrix: Matrix4<f32>,
+    surface: u32,
+    padding: [u32; 3],
+}
+
+// What the geometry pass draws to, resolved images as the graph hands them to the pass.
+pub struct GeometryTargets {
+    pub image: ResolvedImage,
+    // Set when `image` is multisampled, it is resolved into this one.
+    pub resolve: Option<ResolvedImage>,
+    pub depth: ResolvedImage,
+    // Without it the motion output of the pipelines is dropped.
+    pub motion: Option<ResolvedImage>,
+    // Clears `image` instead of drawing over what it holds.
+    pub clear: bool,
+}
+
+// What every object of the geometry pass is drawn with.
+pub struct GeometryInputs<'a> {
+    pub draw_context: &'a DrawContext,
+    pub view_projection: &'a na::Matrix4<f32>,
+    // One GPUObjectData per object of the draw context.
+    pub object_data: vk::DeviceAddress,
+    pub debug: DebugPipelines,
+    pub default_material: &'a MaterialInstance,
+    pub scene_descriptor: vk::DescriptorSet,
+    pub bindless_descriptor: vk::DescriptorSet,
 }
 
 
 pub struct Canvas {
     render_image: Image,
-    depth_image: Image,
+    // Same format and size as the render image, the post chain alternates between the two.
+    post_image: Image,
+    // Screen space motion of the geometry, written next to the color by the mesh pass.
+    motion_image: Image,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    // Multisampled color and depth the geometry is drawn to and the pass loading the background into the color,
+    // only there while more than one sample is used.
+    msaa_targets: Option<(Image, Image, MsaaBackground)>,
+}
+
+// Draws the background over the whole multisampled color before the geometry, built for the sample count of the targets.
+pub struct MsaaBackground {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_layout: DescriptorLayout,
+    pub signature: LayoutSignature,
 }
 
 impl Canvas {
     pub fn new(device:&mut Device, allocator:&mut Allocator, extent:vk::Extent3D) -> Result<Self, AAError> {
         
         let render_image = Image::create(device, allocator, extent, image::RENDER, None)?;
-        
-        let depth_image = Image::create(device, allocator, extent, image::DEPTH, None)?;
+        let post_image = match Image::create(device, allocator, extent, image::RENDER, Some("POST IMAGE")) {
+            Ok(image) => image,
+            Err(error) => {
+                render_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
+        let motion_image = match Image::create(device, allocator, extent, image::MOTION, None) {
+            Ok(image) => image,
+            Err(error) => {
+                render_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+                post_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
         
         Ok(Self{
             render_image,
-            depth_image,
+            post_image,
+            motion_image,
+            depth_format: image::DEPTH.format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            msaa_targets: None,
         })
     }
     
-    pub fn get_images(&mut self) -> (&mut Image, &mut Image) {
-        (&mut self.render_image, &mut self.depth_image)
-    }
-    
     pub fn get_color(&self) -> &Image {
         &self.render_image
     }
     
+    pub fn get_post(&self) -> &Image {
+        &self.post_image
+    }
+    
+    pub fn get_motion(&self) -> &Image {
+        &self.motion_image
+    }
+    
     pub fn get_formats(&self) -> (vk::Format, vk::Format) {
-        (self.render_image.format, self.depth_image.format)
+        (self.render_image.format, self.depth_format)
+    }
+    
+    pub fn get_samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+    
+    // Multisampled color, depth and the background pass, none while drawing single-sampled.
+    pub fn get_msaa(&self) -> Option<(&Image, &Image, &MsaaBackground)> {
+        let (color, depth, background) = self.msaa_targets.as_ref()?;
+        Some((color, depth, background))
+    }
+    
+    // Pipelines drawing to the canvas have to be rebuilt after this, the old targets wait in the stack until no frame uses them.
+    pub fn set_samples(&mut self, device:&mut Device, allocator:&mut Allocator, samples:vk::SampleCountFlags, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        if samples == self.samples {
+            return Ok(());
+        }
+        let targets = match samples {
+            vk::SampleCountFlags::TYPE_1 => None,
+            _ => {
+                let (color, depth) = Self::create_msaa_targets(device, allocator, self.render_image.extent, self.render_image.format, samples)?;
+                match MsaaBackground::create(device, self.render_image.format, samples) {
+                    Ok(background) => Some((color, depth, background)),
+                    Err(error) => {
+                        color.destruct(VkDestructorArguments::DevAll(device, allocator));
+                        depth.destruct(VkDestructorArguments::DevAll(device, allocator));
+                        return Err(error);
+                    }
+                }
+            }
+        };
+        if let Some((color, depth, background)) = std::mem::replace(&mut self.msaa_targets, targets) {
+            let callback = Box::new(move |mut args:VkDestructorArguments|{
+                let (device, allocator) = args.unwrap_dev_all();
+                color.destruct(VkDestructorArguments::DevAll(device, allocator));
+                depth.destruct(VkDestructorArguments::DevAll(device, allocator));
+                background.destruct(VkDestructorArguments::Dev(device));
+            });
+            destruction_stack.push(VkDynamicDestructor::new::<Image>(object_name("msaa targets"), VkDestructorType::DevAll, callback));
+        }
+        logger::various_log!("canvas",
+            (logger::Debug, "drawing geometry with {} samples", samples.as_raw())
+        );
+        self.samples = samples;
+        Ok(())
+    }
+    
+    // On failure the previous pipeline stays in use, single-sampled drawing has none to rebuild.
+    pub fn reload(&mut self, device:&mut Device, shader:&Path, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        if [msaa::BACKGROUND_VERT, msaa::BACKGROUND_FRAG].iter().all(|file|Path::new(file) != shader) {
+            return Ok(());
+        }
+        let Some((_, _, background)) = self.msaa_targets.as_mut() else {
+            return Ok(());
+        };
+        let fresh = MsaaBackground::create(device, self.render_image.format, self.samples)?;
+        let old = std::mem::replace(background, fresh);
+        let callback = Box::new(move |args:VkDestructorArguments|old.destruct(args));
+        destruction_stack.push(VkDynamicDestructor::new::<MsaaBackground>(object_name("msaa background"), VkDestructorType::Dev, callback));
+        Ok(())
+    }
+    
+    fn create_msaa_targets(device:&mut Device, allocator:&mut Allocator, extent:vk::Extent3D, format:vk::Format, samples:vk::SampleCountFlags) -> Result<(Image, Image), AAError> {
+        let color_metadata = ImageMetadata::transient(format, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR).multisampled(samples);
+        let color = Image::create(device, allocator, extent, color_metadata, Some("MSAA COLOR IMAGE"))?;
+        let depth = match Image::create(device, allocator, extent, image::DEPTH.multisampled(samples), Some("MSAA DEPTH IMAGE")) {
+            Ok(depth) => depth,
+            Err(error) => {
+                color.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
+        Ok((color, depth))
     }
     
 }
@@ -98,7 +291,82 @@ impl VkDestructor for Canvas {
     fn destruct(self, mut args:VkDestructorArguments) {
         let (device, allocator) = args.unwrap_dev_all();
         self.render_image.destruct(VkDestructorArguments::DevAll(device, allocator));
-        self.depth_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+        self.post_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+        self.motion_image.destruct(VkDestructorArguments::DevAll(device, allocator));
+        if let Some((color, depth, background)) = self.msaa_targets {
+            color.destruct(VkDestructorArguments::DevAll(device, allocator));
+            depth.destruct(VkDestructorArguments::DevAll(device, allocator));
+            background.destruct(VkDestructorArguments::Dev(device));
+        }
+    }
+}
+
+impl MsaaBackground {
+    pub fn create(device:&mut Device, format:vk::Format, samples:vk::SampleCountFlags) -> Result<Self, AAError> {
+        logger::create!("msaa_background");
+        let (vert_module, vert_interface) = pipeline::create_shader_module(device, msaa::BACKGROUND_VERT)?;
+        let (frag_module, frag_interface) = match pipeline::create_shader_module(device, msaa::BACKGROUND_FRAG) {
+            Ok(built) => built,
+            Err(error) => {
+                unsafe{device.destroy_shader_module(vert_module, None)};
+                return Err(error);
+            }
+        };
+        let built = Self::build(device, format, samples, vert_interface, &frag_interface, vert_module, frag_module);
+        unsafe{device.destroy_shader_module(vert_module, None)};
+        unsafe{device.destroy_shader_module(frag_module, None)};
+        built
+    }
+    
+    fn build(device:&mut Device, format:vk::Format, samples:vk::SampleCountFlags, vert_interface:ShaderReflection, frag_interface:&ShaderReflection, vert_module:vk::ShaderModule, frag_module:vk::ShaderModule) -> Result<Self, AAError> {
+        let interface = vert_interface.merge(frag_interface)?;
+        interface.check_binding(0, 0, vk::DescriptorType::STORAGE_IMAGE)?;
+        let signature = interface.signature(0)?;
+        let (descriptor_layout, _) = interface.descriptor_layout(device, 0)?;
+        
+        let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(from_ref(&descriptor_layout));
+        let layout = match unsafe{device.create_pipeline_layout(&layout_create_info, None)} {
+            Ok(layout) => layout,
+            Err(error) => {
+                descriptor_layout.destruct(VkDestructorArguments::Dev(device));
+                return Err(error.into());
+            }
+        };
+        
+        let mut builder = GPipelineBuilder::new();
+        builder.set_pipeline_layout(layout)
+            .set_shaders(vert_module, frag_module)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .set_multisampling(samples)
+            .set_blending_disabled()
+            .set_depthtest_none()
+            .set_color_attachment_format(format);
+        
+        match builder.build_raw(device) {
+            Ok(pipeline) => Ok(Self{
+                pipeline,
+                layout,
+                descriptor_layout,
+                signature,
+            }),
+            Err(error) => {
+                unsafe{device.destroy_pipeline_layout(layout, None)};
+                descriptor_layout.destruct(VkDestructorArguments::Dev(device));
+                Err(error)
+            }
+        }
+    }
+}
+
+impl VkDestructor for MsaaBackground {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        let device = args.unwrap_dev();
+        unsafe{device.destroy_pipeline(self.pipeline, None)};
+        unsafe{device.destroy_pipeline_layout(self.layout, None)};
+        self.descriptor_layout.destruct(VkDestructorArguments::Dev(device));
     }
 }
 
@@ -113,6 +381,9 @@ impl VInit {
         imgui: &mut Gui,
         
     ) {
+        self.reload_shaders();
+        self.apply_msaa();
+        self.apply_debug_view();
         self.frame_update();
         let cf = self.get_frame();
         
@@ -120,9 +391,14 @@ impl VInit {
             resize_required,
             compute_effects, 
             compute_effect_index, 
-            background_image_ds, 
+            post_effects,
+            color_lut,
+            auto_exposure,
+            taa,
+            bloom,
             
             canvas,
+            transient_images,
             main_draw_context,
             materials,
             
@@ -130,10 +406,17 @@ impl VInit {
             device, 
             allocator,
             
+            registry,
             mesh_assets,
             mesh_index,
+            frame_control,
             
             field_of_view,
+            depth_projection,
+            culling_frustum,
+            freeze_culling,
+            culling_stats,
+            draw_stats: last_draw_stats,
             
             frames_data,
             downscale_coheficient,
@@ -144,26 +427,33 @@ impl VInit {
             
             gpu_scene_layout,
             scene_data,
+            bindless,
             ..
         } = self;
         
+        let bindless_descriptor = bindless.set();
+        let post_effects = &**post_effects;
         let compute_effect_index = compute_effect_index.clone();
         let cmd = frames_data.get_frame_command_buffer(cf);
         
-        let (image_avaliable_semaphore, render_finished_semaphore, inflight_fence) = frames_data.get_frame_sync(cf);
+        let (image_avaliable_semaphore, render_finished_semaphore, frame_value) = frames_data.get_frame_sync(cf);
         
         let destruction_stack = frames_data.get_destruction_stack(cf);
         
-        unsafe{device.wait_for_fences(from_ref(&inflight_fence), true, u64::MAX)}.expect(SIMPLE_VK_FN);
+        device.timeline.wait(device, frame_value);
         
         destruction_stack.dispatch(device, allocator);
+        auto_exposure.update_readout(device, cf, frame_value);
+        let completed = device.timeline.completed(device);
+        registry.collect(device, allocator, completed);
         main_draw_context.clear();
         
-        let mut gpu_scene_buffer = Buffer::create(device, allocator, Some("per_frame_buffer"), GPUSceneData::size_u64(), vk::BufferUsageFlags::UNIFORM_BUFFER, gpu_all::MemoryLocation::CpuToGpu).unwrap();//TODO:changet this unwrap
-        {
-            let mut align = gpu_scene_buffer.get_align::<GPUSceneData>(0, GPUSceneData::size_u64()).expect(CPU_ACCESIBLE);
-            align.copy_from_slice(from_ref(&scene_data));
-        }
+        let extent = Self::calculate_extent(canvas.get_color().extent_2d, swapchain.extent, *downscale_coheficient);
+        let jitter = taa.begin_frame(extent, field_of_view, canvas.get_samples() == vk::SampleCountFlags::TYPE_1);
+        scene_data.set_jitter(jitter);
+        
+        let mut gpu_scene_buffer = TypedBuffer::<GPUSceneData>::create(device, allocator, Some("per_frame_buffer"), 1, vk::BufferUsageFlags::UNIFORM_BUFFER, gpu_all::MemoryLocation::CpuToGpu).unwrap();//TODO:changet this unwrap
+        gpu_scene_buffer.write_at(0, &scene_data).expect(CPU_ACCESIBLE);
         destruction_stack.push(gpu_scene_buffer.defered_destruct());
         
         let descriptor_allocator = frames_data.get_descriptor_allocator(cf);
@@ -171,8 +461,17 @@ impl VInit {
         let scene_descriptor = descriptor_allocator.allocate(device, gpu_scene_layout).unwrap();
         
         let mut writer = DescriptorWriter::default();
-        writer.write_buffer(0, gpu_scene_buffer.underlying(), GPUSceneData::size_u64(), 0, vk::DescriptorType::UNIFORM_BUFFER);
-        writer.update_set(device, scene_descriptor);
+        writer.write_buffer(0, gpu_scene_buffer.underlying(), gpu_scene_buffer.size(), 0, vk::DescriptorType::UNIFORM_BUFFER);
+        // Without the scene set nothing can be drawn, the frame goes on without its geometry.
+        let scene_descriptor = match writer.update_set(device, scene_descriptor) {
+            Ok(()) => Some(scene_descriptor),
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "skipping geometry, scene descriptor not written: {}", error)
+                );
+                None
+            }
+        };
         
         
         let (p_image_handle, p_image_view, image_index) = match swapchain.get_next_image(image_avaliable_semaphore){
@@ -183,7 +482,6 @@ impl VInit {
             }
         };
         
-        unsafe{device.reset_fences(from_ref(&inflight_fence))}.expect(SIMPLE_VK_FN);
         unsafe{device.reset_command_buffer(cmd, vk::CommandBufferResetFlags::empty())}.expect(SIMPLE_VK_FN);
         
         let begin_info = vk::CommandBufferBeginInfo::builder()
@@ -192,42 +490,419 @@ impl VInit {
         unsafe{device.begin_command_buffer(cmd, &begin_info)}.expect(SIMPLE_VK_FN);
         
         
-        let (render_image, depth_image) = canvas.get_images();
-        
-        let r_image_handle = render_image.underlying();
-        let d_image_handle = depth_image.underlying();
-        
+        let render_image = canvas.get_color();
+        let post_image = canvas.get_post();
+        let (_, depth_format) = canvas.get_formats();
         
-        let extent = Self::calculate_extent(render_image.extent_2d, swapchain.extent, *downscale_coheficient);
-        
-        Image::transition_image(device, cmd, r_image_handle, vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL);
+        let descriptor_allocator = frames_data.get_descriptor_allocator(cf);
+        let background_targets = EffectTargets{
+            draw: render_image.view,
+            input: render_image.view,
+            lut: color_lut.view,
+            exposure: auto_exposure.state().view,
+            bloom: bloom.view(),
+        };
+        let effect_descriptor = match compute_effects.allocate_descriptor(device, descriptor_allocator, compute_effect_index, &background_targets) {
+            Ok(descriptor) => Some(descriptor),
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "skipping the background effect: {}", error)
+                );
+                None
+            }
+        };
         
-        Self::draw_background(device, cmd, render_image, *background_image_ds, &compute_effects.pipelines[compute_effect_index], &compute_effects.push_constants[compute_effect_index]);
+        // Multisampled geometry is drawn over a copy of the background and resolves straight back into the render image.
+        let msaa = canvas.get_msaa().map(|(msaa_color, msaa_depth, background)|{
+            let descriptor = descriptor_allocator.allocate(device, &background.descriptor_layout).and_then(|descriptor|{
+                let mut writer = DescriptorWriter::validated(background.signature.clone());
+                writer.write_image(0, render_image.view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+                writer.update_set(device, descriptor)?;
+                Ok(descriptor)
+            });
+            let descriptor = match descriptor {
+                Ok(descriptor) => Some(descriptor),
+                Err(error) => {
+                    logger::various_log!("vulkan",
+                        (logger::Error, "clearing the multisampled color instead of loading the background: {}", error)
+                    );
+                    None
+                }
+            };
+            (msaa_color, msaa_depth, background, descriptor)
+        });
+        
+        // Passes that read their input write to the other image, the rest work in place on the current one.
+        let mut post_chain = Vec::new();
+        let mut on_post_image = false;
+        for index in (0..post_effects.len()).filter(|index|post_effects.enabled[*index]) {
+            // Without its pipelines the bloom chain is never filled, effects adding it are left out.
+            let reads_bloom = post_effects.binds(index, EffectImage::Bloom);
+            if reads_bloom && bloom.pipelines().is_none() {
+                continue;
+            }
+            // Effects reading their input can't work in place, they get the other image to write.
+            let reads_input = post_effects.binds(index, EffectImage::Input);
+            let output_on_post_image = on_post_image != reads_input;
+            let (input, output) = match on_post_image {
+                true => (post_image.view, render_image.view),
+                false => (render_image.view, post_image.view),
+            };
+            let targets = EffectTargets{
+                draw: if reads_input {output} else {input},
+                input,
+                lut: color_lut.view,
+                exposure: auto_exposure.state().view,
+                bloom: bloom.view(),
+            };
+            // The chain is blurred from what the effect gets as input, right before it runs.
+            let descriptors = post_effects.allocate_descriptor(device, descriptor_allocator, index, &targets).and_then(|descriptor|{
+                match reads_bloom {
+                    true => Ok((descriptor, bloom.allocate_descriptors(device, descriptor_allocator, input)?)),
+                    false => Ok((descriptor, None)),
+                }
+            });
+            let (descriptor, bloom_descriptors) = match descriptors {
+                Ok(descriptors) => descriptors,
+                Err(error) => {
+                    logger::various_log!("vulkan",
+                        (logger::Error, "skipping post effect {}: {}", post_effects.names[index], error)
+                    );
+                    continue;
+                }
+            };
+            post_chain.push((index, descriptor, bloom_descriptors, output_on_post_image));
+            on_post_image = output_on_post_image;
+        }
         
-        Image::transition_image(device, cmd, d_image_handle, vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL);
-        Image::transition_image(device, cmd, r_image_handle, vk::ImageLayout::GENERAL, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let swapchain_extent = swapchain.extent;
+        
+        // Only the region the geometry was drawn to is metered.
+        // Exposure that can't be metered this frame keeps the last value, as it does without pipelines.
+        let exposure_descriptors = match auto_exposure.allocate_descriptors(device, descriptor_allocator, render_image) {
+            Ok(descriptors) => descriptors,
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "skipping exposure adaptation: {}", error)
+                );
+                None
+            }
+        };
+        let exposure_constants = auto_exposure.push_constants(extent);
+        if exposure_descriptors.is_some() {
+            auto_exposure.mark_written(cf);
+        }
+        let exposure_control = &**auto_exposure;
+        let bloom_control = &**bloom;
+        
+        let motion_image = canvas.get_motion();
+        let taa_descriptor = match taa.allocate_descriptor(device, descriptor_allocator, render_image, motion_image) {
+            Ok(descriptor) => descriptor,
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "drawing without temporal anti-aliasing: {}", error)
+                );
+                taa.skip_frame();
+                None
+            }
+        };
+        let taa_constants = taa.push_constants(extent);
         
-        let default_material = materials.get_default();
-        mesh_assets[*mesh_index].draw(&na::Matrix4::<f32>::identity(), main_draw_context);
-        mesh_assets[2].draw(&(na::Matrix4::<f32>::identity().append_translation(&na::Vector3::new(1.3,0.4,0.0))), main_draw_context);
+        let default_material = registry.materials.get(materials.default_instance).expect(GRANTED);
+        if let Some(mesh_asset) = mesh_assets.get(*mesh_index).and_then(|handle|registry.meshes.get(*handle)) {
+            mesh_asset.draw(&na::Matrix4::<f32>::identity(), &registry.materials, main_draw_context);
+        }
+        if let Some(mesh_asset) = mesh_assets.get(2).and_then(|handle|registry.meshes.get(*handle)) {
+            mesh_asset.draw(&(na::Matrix4::<f32>::identity().append_translation(&na::Vector3::new(1.3,0.4,0.0))), &registry.materials, main_draw_context);
+        }
         /*
         mesh_assets[*mesh_index].draw(&(na::Matrix4::<f32>::identity().append_translation(&na::Vector3::new(-1.0,-1.0,-1.0))), main_draw_context);
         mesh_assets[*mesh_index].draw(&(na::Matrix4::<f32>::identity().append_translation(&na::Vector3::new(1.0,-1.0,-1.0))), main_draw_context);
         mesh_assets[*mesh_index].draw(&(na::Matrix4::<f32>::identity().append_translation(&na::Vector3::new(1.0,1.0,-1.0))), main_draw_context);
         */
-        //Self::draw_geometry(device, cmd, extent, canvas, mesh_assets, *mesh_index, field_of_view, main_draw_context, default_material, scene_descriptor);
-        Self::draw_geometry(device, cmd, extent, canvas, /*mesh_assets, *mesh_index, */field_of_view, main_draw_context, default_material, scene_descriptor);
         
-        Image::transition_image(device, cmd, r_image_handle, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
-        Image::transition_image(device, cmd, p_image_handle, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        taa.carry_transforms(main_draw_context);
+        let view_projection = Self::tmp_perspective_matrix(extent, field_of_view, *depth_projection, na::Vector2::zeros());
+        let previous_view_projection = taa.swap_view_projection(view_projection);
+        
+        // Culled after the transforms are carried, objects coming back into view keep their motion.
+        let frustum = match (*freeze_culling, *culling_frustum) {
+            (true, Some(frozen)) => frozen,
+            _ => Frustum::from_matrix(&view_projection),
+        };
+        *culling_frustum = Some(frustum);
+        let submitted = main_draw_context.len();
+        main_draw_context.retain(|render_object|frustum.intersects(&render_object.bounds, &render_object.transform));
+        *culling_stats = CullingStats{
+            visible: main_draw_context.len(),
+            culled: submitted - main_draw_context.len(),
+        };
+        main_draw_context.sort(&view_projection);
+        let view_projection = Self::tmp_perspective_matrix(extent, field_of_view, *depth_projection, jitter);
+        
+        // The geometry reads every object through this buffer, without it the frame goes on without its geometry.
+        let object_data_address = match TypedBuffer::<GPUObjectData>::create(device, allocator, Some("object data"), main_draw_context.len().max(1), vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS, gpu_all::MemoryLocation::CpuToGpu) {
+            Ok(mut object_data) => {
+                for (index, render_object) in main_draw_context.iter().enumerate() {
+                    let holder = GPUObjectData{
+                        previous_matrix: previous_view_projection * render_object.previous_transform,
+                        world_matrix: render_object.transform,
+                        surface: render_object.surface_id(),
+                        padding: [0; 3],
+                    };
+                    object_data.write_at(index, &holder).expect(CPU_ACCESIBLE);
+                }
+                let object_data_address = object_data.get_device_address(device);
+                frames_data.get_destruction_stack(cf).push(object_data.defered_destruct());
+                Some(object_data_address)
+            }
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "skipping geometry, object data not created: {}", error)
+                );
+                None
+            }
+        };
+        
+        let debug = materials.metalic.debug_pipelines();
+        
+        let taa_resolve = taa.pipeline().zip(taa_descriptor);
+        
+        let mut graph = RenderGraph::new();
+        
+        let color = graph.import_image("render image", ResolvedImage::from(render_image), vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, None);
+        let post = graph.import_image("post image", ResolvedImage::from(post_image), vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, None);
+        let (geometry_color, depth, resolve) = match msaa {
+            Some((msaa_color, msaa_depth, ..)) => (
+                graph.import_image("msaa color", ResolvedImage::from(msaa_color), vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, None),
+                graph.import_image("msaa depth", ResolvedImage::from(msaa_depth), vk::ImageAspectFlags::DEPTH, vk::ImageLayout::UNDEFINED, None),
+                Some(color),
+            ),
+            None => (color, graph.create_image("depth image", TransientDesc{
+                format: depth_format,
+                extent: render_image.extent,
+                aspect: vk::ImageAspectFlags::DEPTH,
+            }), None),
+        };
+        let motion = taa_resolve.map(|_|graph.import_image("motion vectors", ResolvedImage::from(motion_image), vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, None));
+        let present = graph.import_image("swapchain image", ResolvedImage{
+            image: p_image_handle,
+            view: p_image_view,
+            extent: vk::Extent3D::from(swapchain_extent),
+            format: swapchain.surface_format.format,
+        }, vk::ImageAspectFlags::COLOR, vk::ImageLayout::UNDEFINED, Some(vk::ImageLayout::PRESENT_SRC_KHR));
+        // Images living across frames start from their tracked layouts and get the final ones recorded back.
+        let lut = graph.import_tracked("color lut", color_lut, None);
+        let exposure = graph.import_tracked("exposure state", exposure_control.state(), None);
+        let history = taa_resolve.map(|_|{
+            let (read, write) = taa.history();
+            // Handed back ready for the next frame, which reads it as its history.
+            (graph.import_tracked("taa history read", read, None), graph.import_tracked("taa history write", write, Some(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)))
+        });
+        let bloom_levels:Vec<_> = bloom_control.levels().iter().map(|level|graph.import_tracked("bloom level", level, None)).collect();
+        let histogram = graph.import_buffer("luminance histogram", exposure_control.histogram().underlying(), exposure_control.histogram().size());
+        let readback = graph.import_buffer("exposure readback", exposure_control.readback(cf).underlying(), exposure_control.readback(cf).size());
+        let scene = graph.import_buffer("scene buffer", gpu_scene_buffer.underlying(), gpu_scene_buffer.size());
+        
+        let cp_pipeline = &compute_effects.pipelines[compute_effect_index];
+        let push_constants = &compute_effects.push_constants[compute_effect_index];
+        let workgroup_size = compute_effects.workgroup_sizes[compute_effect_index];
+        // Debug views that clear the background leave nothing for it to show.
+        if let (Some(effect_descriptor), false) = (effect_descriptor, debug.clears_background) {
+            graph.add_pass(Pass::new("background")
+                .write_image(color, Access::ComputeWrite)
+                .execute(move |ctx|{
+                    Self::dispatch_effect(ctx.device, ctx.cmd, &ctx.image(color), effect_descriptor, cp_pipeline, push_constants, workgroup_size);
+                })
+            );
+        }
+        
+        // Geometry with no background under it clears its color first, the multisampled one too when it can't load it.
+        let mut clears_geometry = debug.clears_background || effect_descriptor.is_none();
+        if let Some((_, _, background, descriptor)) = msaa {
+            match descriptor.filter(|_|!clears_geometry) {
+                Some(descriptor) => graph.add_pass(Pass::new("msaa background")
+                    .read_image(color, Access::FragmentRead)
+                    .write_image(geometry_color, Access::ColorAttachment)
+                    .execute(move |ctx|{
+                        Self::draw_background(ctx.device, ctx.cmd, extent, &ctx.image(geometry_color), background, descriptor);
+                    })
+                ),
+                None => clears_geometry = true,
+            }
+        }
+        
+        let draw_context = &*main_draw_context;
+        let view_projection = &view_projection;
+        let draw_stats = Cell::new(DrawStats::default());
+        let draw_stats_cell = &draw_stats;
+        let mut geometry = Pass::new("geometry")
+            .write_image(geometry_color, Access::ColorAttachment)
+            .write_image(depth, Access::DepthAttachment)
+            .read_buffer(scene, Access::Uniform);
+        if let Some(resolve) = resolve {
+            geometry = geometry.write_image(resolve, Access::ColorAttachment);
+        }
+        if let Some(motion) = motion {
+            geometry = geometry.write_image(motion, Access::ColorAttachment);
+        }
+        if let (Some(scene_descriptor), Some(object_data)) = (scene_descriptor, object_data_address) {
+            let inputs = GeometryInputs{
+                draw_context,
+                view_projection,
+                object_data,
+                debug,
+                default_material,
+                scene_descriptor,
+                bindless_descriptor,
+            };
+            graph.add_pass(geometry
+                .execute(move |ctx|{
+                    let targets = GeometryTargets{
+                        image: ctx.image(geometry_color),
+                        resolve: resolve.map(|resolve|ctx.image(resolve)),
+                        depth: ctx.image(depth),
+                        motion: motion.map(|motion|ctx.image(motion)),
+                        clear: clears_geometry,
+                    };
+                    draw_stats_cell.set(Self::draw_geometry(ctx.device, ctx.cmd, extent, &targets, &inputs));
+                })
+            );
+        }
+        
+        if let (Some(motion), Some((taa_pipeline, taa_descriptor)), Some((history_read, history_write))) = (motion, taa_resolve, history) {
+            let taa_constants = &taa_constants;
+            graph.add_pass(Pass::new("taa resolve")
+                .read_image(color, Access::ComputeRead)
+                .read_image(motion, Access::ComputeRead)
+                .read_image(history_read, Access::ComputeRead)
+                .write_image(history_write, Access::ComputeWrite)
+                .execute(move |ctx|{
+                    let region = ResolvedImage{extent: vk::Extent3D::from(extent), ..ctx.image(history_write)};
+                    Self::dispatch_effect(ctx.device, ctx.cmd, &region, taa_descriptor, taa_pipeline, taa_constants, [16, 16]);
+                })
+            );
+            graph.add_pass(Pass::new("taa copy")
+                .read_image(history_write, Access::TransferSrc)
+                .write_image(color, Access::TransferDst)
+                .execute(move |ctx|{
+                    Image::raw_copy_image_to_image(ctx.device, ctx.cmd, ctx.image(history_write).image, vk::Extent3D::from(extent), ctx.image(color).image, vk::Extent3D::from(extent));
+                })
+            );
+        }
         
-        Image::raw_copy_image_to_image(device, cmd, r_image_handle, vk::Extent3D::from(extent), p_image_handle, vk::Extent3D::from(swapchain.extent));
+        if let (Some((histogram_pipeline, average_pipeline)), Some((histogram_descriptor, average_descriptor))) = (exposure_control.pipelines(), exposure_descriptors) {
+            let exposure_constants = &exposure_constants;
+            graph.add_pass(Pass::new("luminance histogram")
+                .read_image(color, Access::ComputeRead)
+                .write_buffer(histogram, Access::StorageWrite)
+                .execute(move |ctx|{
+                    AutoExposure::record_histogram(ctx.device, ctx.cmd, histogram_pipeline, histogram_descriptor, exposure_constants, extent);
+                })
+            );
+            graph.add_pass(Pass::new("exposure adaptation")
+                .write_buffer(histogram, Access::StorageWrite)
+                .write_image(exposure, Access::ComputeWrite)
+                .execute(move |ctx|{
+                    AutoExposure::record_average(ctx.device, ctx.cmd, average_pipeline, average_descriptor, exposure_constants);
+                })
+            );
+            graph.add_pass(Pass::new("exposure readback")
+                .read_image(exposure, Access::TransferSrc)
+                .write_buffer(readback, Access::TransferDst)
+                .side_effects()
+                .execute(move |ctx|{
+                    AutoExposure::record_readback(ctx.device, ctx.cmd, ctx.image(exposure).image, exposure_control.readback(cf).underlying());
+                })
+            );
+        }
         
-        Image::transition_image(device, cmd, p_image_handle, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let mut current = color;
+        for (index, descriptor, bloom_descriptors, output_on_post_image) in post_chain {
+            let output = if output_on_post_image {post} else {color};
+            if let (Some(BloomDescriptors{downsample, upsample}), Some((downsample_pipeline, upsample_pipeline))) = (bloom_descriptors, bloom_control.pipelines()) {
+                // The effect lays its threshold and radius over the first row.
+                let parameters = post_effects.push_constants[index];
+                let mut source = current;
+                for (level, (&target, descriptor)) in bloom_levels.iter().zip(downsample).enumerate() {
+                    let constants = Bloom::downsample_constants(parameters[0].x, level == 0);
+                    graph.add_pass(Pass::new("bloom downsample")
+                        .read_image(source, Access::ComputeRead)
+                        .write_image(target, Access::ComputeWrite)
+                        .execute(move |ctx|{
+                            Self::dispatch_effect(ctx.device, ctx.cmd, &ctx.image(target), descriptor, downsample_pipeline, &constants, [16, 16]);
+                        })
+                    );
+                    source = target;
+                }
+                // Each level adds the blurred one below it, the first ends up holding the whole chain.
+                let constants = Bloom::upsample_constants(parameters[0].z);
+                for (pair, descriptor) in bloom_levels.windows(2).zip(upsample).rev() {
+                    let (target, source) = (pair[0], pair[1]);
+                    graph.add_pass(Pass::new("bloom upsample")
+                        .read_image(source, Access::ComputeRead)
+                        .write_image(target, Access::ComputeWrite)
+                        .execute(move |ctx|{
+                            Self::dispatch_effect(ctx.device, ctx.cmd, &ctx.image(target), descriptor, upsample_pipeline, &constants, [16, 16]);
+                        })
+                    );
+                }
+            }
+            let mut pass = Pass::new("post");
+            if post_effects.binds(index, EffectImage::Input) {
+                pass = pass.read_image(current, Access::ComputeRead);
+            }
+            if post_effects.binds(index, EffectImage::Lut) {
+                pass = pass.read_image(lut, Access::ComputeRead);
+            }
+            if post_effects.binds(index, EffectImage::Exposure) {
+                pass = pass.read_image(exposure, Access::ComputeRead);
+            }
+            if let (true, Some(&first)) = (post_effects.binds(index, EffectImage::Bloom), bloom_levels.first()) {
+                pass = pass.read_image(first, Access::ComputeRead);
+            }
+            let cp_pipeline = &post_effects.pipelines[index];
+            let push_constants = &post_effects.push_constants[index];
+            let workgroup_size = post_effects.workgroup_sizes[index];
+            graph.add_pass(pass
+                .write_image(output, Access::ComputeWrite)
+                .execute(move |ctx|{
+                    Self::dispatch_effect(ctx.device, ctx.cmd, &ctx.image(output), descriptor, cp_pipeline, push_constants, workgroup_size);
+                })
+            );
+            current = output;
+        }
         
-        imgui.render(device, cmd, swapchain.extent, p_image_view);
+        graph.add_pass(Pass::new("blit")
+            .read_image(current, Access::TransferSrc)
+            .write_image(present, Access::TransferDst)
+            .execute(move |ctx|{
+                Image::raw_copy_image_to_image(ctx.device, ctx.cmd, ctx.image(current).image, vk::Extent3D::from(extent), ctx.image(present).image, vk::Extent3D::from(swapchain_extent));
+            })
+        );
         
-        Image::transition_image(device, cmd, p_image_handle, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL, vk::ImageLayout::PRESENT_SRC_KHR);
+        graph.add_pass(Pass::new("imgui")
+            .write_image(present, Access::ColorAttachment)
+            .side_effects()
+            .execute(move |ctx|{
+                imgui.render(ctx.device, ctx.cmd, swapchain_extent, ctx.image(present).view);
+            })
+        );
+        
+        let destruction_stack = frames_data.get_destruction_stack(cf);
+        let final_states = graph.execute(device, allocator, transient_images, destruction_stack, frame_control.get_frame_count(), cmd).expect(VK_UNRECOVERABLE);
+        final_states.record(lut, color_lut);
+        final_states.record(exposure, auto_exposure.state_mut());
+        for (&id, level) in bloom_levels.iter().zip(bloom.levels_mut()) {
+            final_states.record(id, level);
+        }
+        if let Some((history_read, history_write)) = history {
+            let (read, write) = taa.history_mut();
+            final_states.record(history_read, read);
+            final_states.record(history_write, write);
+        }
+        taa.end_frame();
+        *last_draw_stats = draw_stats.get();
         
         unsafe{device.end_command_buffer(cmd)}.expect(SIMPLE_VK_FN);
         
@@ -235,9 +910,14 @@ impl VInit {
             .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
             .semaphore(image_avaliable_semaphore);
         
-        let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo::builder()
-            .stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
-            .semaphore(render_finished_semaphore);
+        let submit_value = device.timeline.reserve();
+        let signal_semaphore_submit_infos = [
+            vk::SemaphoreSubmitInfo::builder()
+                .stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
+                .semaphore(render_finished_semaphore)
+                .build(),
+            device.timeline.signal_info(submit_value, vk::PipelineStageFlags2::ALL_COMMANDS),
+        ];
         
         let command_submit_info = vk::CommandBufferSubmitInfo::builder()
             .command_buffer(cmd);
@@ -245,10 +925,11 @@ impl VInit {
         let submit_info = vk::SubmitInfo2::builder()
             .command_buffer_infos(from_ref(&command_submit_info))
             .wait_semaphore_infos(from_ref(&wait_semaphore_submit_info))
-            .signal_semaphore_infos(from_ref(&signal_semaphore_submit_info));
+            .signal_semaphore_infos(&signal_semaphore_submit_infos);
         
         
-        unsafe{device.queue_submit2(device.queue_handles.graphics, from_ref(&submit_info), inflight_fence)}.expect(SIMPLE_VK_FN);
+        unsafe{device.queue_submit2(device.queue_handles.graphics, from_ref(&submit_info), vk::Fence::null())}.expect(SIMPLE_VK_FN);
+        frames_data.set_frame_timeline_value(cf, submit_value);
         
         let present_info = vk::PresentInfoKHR::builder()
             .swapchains(from_ref(&swapchain.swapchain))
@@ -264,54 +945,68 @@ impl VInit {
     }
     
 //----
-    pub fn draw_background(device:&mut Device, cmd:vk::CommandBuffer, image:&Image, background_image_ds:vk::DescriptorSet, cp_pipeline:&CPipeline, push_constants:&ComputePushConstants) {
+    // Runs a compute effect over the whole image, backgrounds and post passes alike.
+    pub fn dispatch_effect(device:&Device, cmd:vk::CommandBuffer, image:&ResolvedImage, effect_descriptor:vk::DescriptorSet, cp_pipeline:&CPipeline, push_constants:&ComputePushConstants, workgroup_size:[u32; 2]) {
         
         unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, cp_pipeline.pipeline)};
-        unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::COMPUTE, cp_pipeline.layout, 0, from_ref(&background_image_ds), &[])};
+        unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::COMPUTE, cp_pipeline.layout, 0, from_ref(&effect_descriptor), &[])};
         
         let push_constants_slice = unsafe{crate::any_as_u8_slice(push_constants)};
         unsafe{device.cmd_push_constants(cmd, cp_pipeline.layout, vk::ShaderStageFlags::COMPUTE, 0, push_constants_slice)};
         
-        unsafe{device.cmd_dispatch(cmd, image.extent.width/16+1, image.extent.height/16+1, 1)};
+        unsafe{device.cmd_dispatch(cmd, image.extent.width.div_ceil(workgroup_size[0]), image.extent.height.div_ceil(workgroup_size[1]), 1)};
         
     }
 
 
+//----
+    // Fills the drawn region of the multisampled `image` with the background, every sample of a pixel gets its texel.
+    pub fn draw_background(device:&Device, cmd:vk::CommandBuffer, extent:vk::Extent2D, image:&ResolvedImage, background:&MsaaBackground, descriptor:vk::DescriptorSet) {
+        let color_attachment_info = pipeline::rendering_attachment_info(image.view, None, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let rendering_info = pipeline::rendering_info(extent, from_ref(&color_attachment_info), None);
+        
+        let viewport = vk::Viewport::builder()
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0f32)
+            .max_depth(1f32);
+        let scissor = vk::Rect2D::from(extent);
+        
+        unsafe{device.cmd_begin_rendering(cmd, &rendering_info)};
+        unsafe{device.cmd_set_viewport(cmd, 0, from_ref(&viewport))};
+        unsafe{device.cmd_set_scissor(cmd, 0, from_ref(&scissor))};
+        unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, background.pipeline)};
+        unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::GRAPHICS, background.layout, 0, from_ref(&descriptor), &[])};
+        // A single triangle covering the screen, its corners come from the vertex index.
+        unsafe{device.cmd_draw(cmd, 3, 1, 0, 0)};
+        unsafe{device.cmd_end_rendering(cmd)};
+    }
+    
+    
 //----
     pub fn draw_geometry(
-        device: &mut Device, 
+        device: &Device, 
         cmd: vk::CommandBuffer, 
         extent: vk::Extent2D, 
-        canvas: &mut Canvas,
-        /*
-        image: &Image, 
-        depth: &Image, 
-        */
-        
-        //mesh_pipeline: &GPipeline, 
-        
-        /*
-        meshes: &VkMeshAssets, 
-        mesh_selector: usize, 
-        */
-        
-        field_of_view: &na::Vector3<f32>,
-        draw_context: &mut DrawContext,
+        targets: &GeometryTargets,
+        inputs: &GeometryInputs,
+    ) -> DrawStats {
         
-        default_material: &MaterialInstance,
-        scene_descriptor: vk::DescriptorSet,
-        /*
-        texture_descriptor_layout: &DescriptorLayout,
-        descriptor_allocator: &mut GDescriptorAllocator,
-        texture: &Image,
-        sampler: &Sampler
-        */
-    ) {
+        let GeometryTargets{image, resolve, depth, motion, clear} = *targets;
+        let GeometryInputs{draw_context, view_projection, object_data, debug, default_material, scene_descriptor, bindless_descriptor} = *inputs;
         
-        let (image, depth) = canvas.get_images();
-        let color_attachment_info = pipeline::rendering_attachment_info(image.view, None, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let clear = clear.then(vk::ClearValue::default);
+        let color_attachment_info = match resolve {
+            Some(resolve) => pipeline::resolving_attachment_info(image.view, resolve.view, clear, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            None => pipeline::rendering_attachment_info(image.view, clear, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        };
+        let motion_attachment_info = match motion {
+            Some(motion) => pipeline::rendering_attachment_info(motion.view, Some(vk::ClearValue::default()), vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            None => vk::RenderingAttachmentInfo::default(),
+        };
+        let color_attachments = [color_attachment_info, motion_attachment_info];
         let depth_attachment_info = pipeline::depth_attachment_info(depth.view, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL);
-        let rendering_info = pipeline::rendering_info(extent, &color_attachment_info, Some(&depth_attachment_info));
+        let rendering_info = pipeline::rendering_info(extent, &color_attachments, Some(&depth_attachment_info));
         
         
         let viewport = vk::Viewport::builder()
@@ -327,27 +1022,58 @@ impl VInit {
         unsafe{device.cmd_set_viewport(cmd, 0, from_ref(&viewport))};
         unsafe{device.cmd_set_scissor(cmd, 0, from_ref(&scissor))};
         
-        for render_object in draw_context.iter() {
+        // The draw context is sorted, so consecutive draws mostly share what is bound.
+        let mut stats = DrawStats::default();
+        let mut bound_pipeline = vk::Pipeline::null();
+        let mut bound_layout = vk::PipelineLayout::null();
+        let mut bound_index_buffer = vk::Buffer::null();
+        for (index, render_object) in draw_context.iter().enumerate() {
             
             let material = match render_object.material.as_ref() {
                 Some(material) => {material}
                 None => {default_material}
             };
             
-            unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, material.pipeline.underlying())};
-            //TODO add cmd_descriptor_1
-            unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::GRAPHICS, material.pipeline.layout, 0, from_ref(&scene_descriptor), &[])};
-            unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::GRAPHICS, material.pipeline.layout, 1, from_ref(&material.descriptor_set), &[])};
-            unsafe{device.cmd_bind_index_buffer(cmd, render_object.index_buffer, 0, vk::IndexType::UINT32)};
+            let pipeline = debug.main.unwrap_or_else(||material.pipeline.get());
+            if pipeline.pipeline != bound_pipeline {
+                unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline.underlying())};
+                bound_pipeline = pipeline.pipeline;
+                stats.pipeline_binds += 1;
+            }
+            // Sets stay bound across pipelines with the same layout.
+            if pipeline.layout != bound_layout {
+                let descriptor_sets = [scene_descriptor, bindless_descriptor];
+                unsafe{device.cmd_bind_descriptor_sets(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline.layout, 0, &descriptor_sets, &[])};
+                bound_layout = pipeline.layout;
+                stats.descriptor_binds += 1;
+            }
+            if render_object.index_buffer != bound_index_buffer {
+                unsafe{device.cmd_bind_index_buffer(cmd, render_object.index_buffer, 0, vk::IndexType::UINT32)};
+                bound_index_buffer = render_object.index_buffer;
+                stats.index_buffer_binds += 1;
+            }
             
             let mut push_constant_tmp = GPUDrawPushConstants::default();
             push_constant_tmp.vertex_buffer = render_object.vertex_buffer_address;
-            push_constant_tmp.world_matrix = Self::tmp_perspective_matrix(extent, field_of_view)*render_object.transform;
+            push_constant_tmp.world_matrix = view_projection*render_object.transform;
+            push_constant_tmp.object_data = object_data + (index * size_of::<GPUObjectData>()) as vk::DeviceAddress;
+            push_constant_tmp.material_buffer = material.constants_buffer.get();
+            push_constant_tmp.material_index = material.constants_index;
             let push_constants_slice = unsafe{crate::any_as_u8_slice(&push_constant_tmp)};
             
-            unsafe{device.cmd_push_constants(cmd, material.pipeline.layout, vk::ShaderStageFlags::VERTEX, 0, push_constants_slice)};
+            unsafe{device.cmd_push_constants(cmd, pipeline.layout, vk::ShaderStageFlags::VERTEX, 0, push_constants_slice)};
             
             unsafe{device.cmd_draw_indexed(cmd, render_object.index_count, 1, render_object.first_index, 0, 0)};
+            stats.draws += 1;
+            
+            // Shares the layout of the material pipelines, the bound sets and push constants carry over.
+            if let Some(overlay) = debug.overlay {
+                unsafe{device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, overlay.underlying())};
+                bound_pipeline = overlay.pipeline;
+                stats.pipeline_binds += 1;
+                unsafe{device.cmd_draw_indexed(cmd, render_object.index_count, 1, render_object.first_index, 0, 0)};
+                stats.draws += 1;
+            }
         }
         
         /*
@@ -378,6 +1104,7 @@ impl VInit {
         */
         */
         unsafe{device.cmd_end_rendering(cmd)};
+        stats
     }
     
 //----
@@ -385,12 +1112,12 @@ impl VInit {
         device: &mut Device, 
         cmd: vk::CommandBuffer, 
         extent: vk::Extent2D, 
-        canvas: &mut Canvas,
+        image: &ResolvedImage,
+        depth: &ResolvedImage,
     ) -> Result<(), ()> {
-        let (image, depth) = canvas.get_images();
         let color_attachment_info = pipeline::rendering_attachment_info(image.view, None, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
         let depth_attachment_info = pipeline::depth_attachment_info(depth.view, vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL);
-        let rendering_info = pipeline::rendering_info(extent, &color_attachment_info, Some(&depth_attachment_info));
+        let rendering_info = pipeline::rendering_info(extent, from_ref(&color_attachment_info), Some(&depth_attachment_info));
         
         
         let viewport = vk::Viewport::builder()
@@ -406,13 +1133,21 @@ impl VInit {
     }
     
 //----
-    pub fn tmp_perspective_matrix(extent:vk::Extent2D, field_of_view:&na::Vector3<f32>) -> na::Matrix4<f32> {
+    // `jitter` offsets the projection inside the pixel, in NDC.
+    // `field_of_view` holds the far plane, the near plane and the vertical angle in degrees.
+    pub fn tmp_perspective_matrix(extent:vk::Extent2D, field_of_view:&na::Vector3<f32>, depth_projection:DepthProjection, jitter:na::Vector2<f32>) -> na::Matrix4<f32> {
         let mut view = Matrix4::<f32>::identity();
         view.prepend_translation_mut(&na::Vector3::new(-1.5,1.5,-5.0));
         
         //let mut projection = Matrix4::new_perspective(extent.width as f32/extent.height as f32, 70.0/180.0*std::f32::consts::PI, 10000.0, 0.1);
         
+        // The planes go in swapped, so depth is reversed.
         let mut projection = glm::perspective_zo(extent.width as f32/extent.height as f32, field_of_view[2]/180.0*std::f32::consts::PI, field_of_view[0], field_of_view[1]);
+        if depth_projection == DepthProjection::InfiniteReverse {
+            // Limit of the above with the far plane at infinity, depth is near/distance.
+            projection[(2,2)] = 0.0;
+            projection[(2,3)] = field_of_view[1];
+        }
         
         /*
         let mut projection = Matrix4::new_perspective(extent.width as f32/extent.height as f32, field_of_view[2]/180.0*std::f32::consts::PI, field_of_view[0], field_of_view[1]);
@@ -433,6 +1168,9 @@ impl VInit {
         */
         
         projection[(1,1)] *= -1.0;
+        // Shifts clip x and y by jitter*w, which is a constant offset once divided by w.
+        projection[(0,2)] -= jitter.x;
+        projection[(1,2)] -= jitter.y;
         let holder = projection*view;
         
         
@@ -682,6 +1420,9 @@ impl Default for GPUDrawPushConstants {
         Self{
             world_matrix:Matrix4::<f32>::identity(),
             vertex_buffer:vk::DeviceAddress::default(),
+            object_data:vk::DeviceAddress::default(),
+            material_buffer:0,
+            material_index:0,
         }
     }
 }
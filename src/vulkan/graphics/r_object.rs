@@ -3,6 +3,8 @@ use super::VkGeoSurface;
 use super::VkMeshBuffers;
 use super::VkMeshAsset;
 use super::MaterialInstance;
+use super::Bounds;
+use super::Pool;
 
 
 use std::rc::Rc;
@@ -11,6 +13,7 @@ use nalgebra as na;
 use arrayvec::ArrayString;
 use derivative::Derivative;
 use ash::vk;
+use ash::vk::Handle as _;
 
 #[derive(Derivative)]
 #[derivative(Debug)]
@@ -25,6 +28,31 @@ pub struct RenderObject {
     pub material: Option<MaterialInstance>,
     
     pub transform: na::Matrix4<f32>,
+    // Where the object was last frame, the same as `transform` until the temporal pass carries it over.
+    pub previous_transform: na::Matrix4<f32>,
+    // Of the surface, in the space `transform` takes to the world.
+    pub bounds: Bounds,
+}
+
+impl RenderObject {
+    // Pipeline, then material, then mesh, what the geometry pass would have to rebind between draws.
+    pub fn state_key(&self) -> (u64, u32, u32, u64, u32) {
+        let (pipeline, constants_buffer, constants_index) = match self.material.as_ref() {
+            Some(material) => (material.pipeline.get().pipeline.as_raw(), material.constants_buffer.get(), material.constants_index),
+            None => (0, 0, 0),
+        };
+        (pipeline, constants_buffer, constants_index, self.index_buffer.as_raw(), self.first_index)
+    }
+    
+    // Clip w of the center of the bounds, the distance along the view axis.
+    pub fn view_depth(&self, view_projection:&na::Matrix4<f32>) -> f32 {
+        (view_projection * self.transform * self.bounds.origin.push(1.0)).w
+    }
+    
+    // Same for every draw of the surface while its mesh stays loaded, the surface id view colors by it.
+    pub fn surface_id(&self) -> u32 {
+        (self.index_buffer.as_raw() as u32) ^ self.first_index.wrapping_mul(0x9E37_79B9)
+    }
 }
 
 
@@ -56,18 +84,18 @@ pub struct MeshAssetMetadata {
 
 // base class for a renderable dynamic object
 pub trait IRenderable {
-    fn draw(&self, top_matrix:&na::Matrix4<f32>, ctx:&mut DrawContext);
+    fn draw(&self, top_matrix:&na::Matrix4<f32>, materials:&Pool<MaterialInstance>, ctx:&mut DrawContext);
 }
 
 impl IRenderable for VkMeshAsset {
-    fn draw(&self, top_matrix:&na::Matrix4<f32>, ctx:&mut DrawContext) {
+    fn draw(&self, top_matrix:&na::Matrix4<f32>, materials:&Pool<MaterialInstance>, ctx:&mut DrawContext) {
         
         for (index, geo_surface) in self.surfaces.iter().enumerate() {
             let first_index = geo_surface.start_index;
             let index_count = geo_surface.count;
             let index_buffer = self.meshes[index].index_buffer.underlying();
             let vertex_buffer_address = self.meshes[index].vertex_buffer_address;
-            let material = geo_surface.material.clone();
+            let material = geo_surface.material.and_then(|handle|materials.get(handle)).cloned();
             
             let render_object_holder = RenderObject{
                 first_index,
@@ -76,6 +104,8 @@ impl IRenderable for VkMeshAsset {
                 vertex_buffer_address,
                 material,
                 transform: top_matrix.clone(),
+                previous_transform: *top_matrix,
+                bounds: geo_surface.bounds,
             };
             ctx.push(render_object_holder);
         }
@@ -83,21 +113,21 @@ impl IRenderable for VkMeshAsset {
 }
 
 impl IRenderable for Node {
-    fn draw(&self, top_matrix:&na::Matrix4<f32>, ctx:&mut DrawContext) {
+    fn draw(&self, top_matrix:&na::Matrix4<f32>, materials:&Pool<MaterialInstance>, ctx:&mut DrawContext) {
         let node_matrix = top_matrix * self.world_transform;
         for son in &self.sons {
-            son.draw(&node_matrix, ctx);
+            son.draw(&node_matrix, materials, ctx);
         }
     }
 }
 
 
 impl IRenderable for MeshNode {
-    fn draw(&self, top_matrix:&na::Matrix4<f32>, ctx:&mut DrawContext) {
+    fn draw(&self, top_matrix:&na::Matrix4<f32>, materials:&Pool<MaterialInstance>, ctx:&mut DrawContext) {
         let node_matrix = top_matrix * self.world_transform;
-        self.mesh.draw(&node_matrix, ctx);
+        self.mesh.draw(&node_matrix, materials, ctx);
         for son in &self.sons {
-            son.draw(&node_matrix, ctx);
+            son.draw(&node_matrix, materials, ctx);
         }
     }
 }
@@ -112,8 +142,8 @@ impl RenderableNode {
 }
 
 impl IRenderable for RenderableNode {
-    fn draw(&self, top_matrix:&na::Matrix4<f32>, ctx:&mut DrawContext) {
-        self.unwrap().draw(&top_matrix, ctx);
+    fn draw(&self, top_matrix:&na::Matrix4<f32>, materials:&Pool<MaterialInstance>, ctx:&mut DrawContext) {
+        self.unwrap().draw(&top_matrix, materials, ctx);
     }
 }
 
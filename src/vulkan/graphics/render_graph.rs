@@ -0,0 +1,665 @@
+use crate::AAError;
+use crate::logger;
+
+use super::VkDestructor;
+use super::VkDeferedDestructor;
+use super::VkDestructorArguments;
+use super::super::Device;
+use super::super::Allocator;
+use super::super::Image;
+use super::super::ImageMetadata;
+use super::super::LayoutTracker;
+use super::super::SubresourceState;
+use super::super::DestructionStack;
+
+use ash::vk;
+
+// How a pass touches a resource, every access implies a layout, a stage and an access mask.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    ComputeRead,
+    ComputeWrite,
+    // Storage image loads from fragment shaders.
+    FragmentRead,
+    FragmentSampled,
+    TransferSrc,
+    TransferDst,
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    Index,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferId(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolvedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent3D,
+    #[allow(dead_code)]
+    pub format: vk::Format,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub aspect: vk::ImageAspectFlags,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ResourceState {
+    layout: vk::ImageLayout,
+    stage: vk::PipelineStageFlags2,
+    access: vk::AccessFlags2,
+    written: bool,
+}
+
+enum ImageSource {
+    Imported(ResolvedImage),
+    Transient(TransientDesc),
+}
+
+struct GraphImage {
+    name: &'static str,
+    source: ImageSource,
+    aspect: vk::ImageAspectFlags,
+    initial: ResourceState,
+    final_layout: Option<vk::ImageLayout>,
+}
+
+// The state the graph left every image in, for the trackers of the imported ones.
+pub struct FinalStates(Vec<SubresourceState>);
+
+struct GraphBuffer {
+    name: &'static str,
+    buffer: vk::Buffer,
+    size: vk::DeviceSize,
+}
+
+pub struct PassContext<'c> {
+    pub device: &'c Device,
+    pub cmd: vk::CommandBuffer,
+    images: &'c [Option<ResolvedImage>],
+}
+
+type PassCallback<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+pub struct Pass<'a> {
+    name: &'static str,
+    images: Vec<(ImageId, Access)>,
+    buffers: Vec<(BufferId, Access)>,
+    side_effects: bool,
+    execute: Option<PassCallback<'a>>,
+}
+
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<Pass<'a>>,
+}
+
+struct TransientImage {
+    desc: TransientDesc,
+    usage: vk::ImageUsageFlags,
+    image: Image,
+    last_used: usize,
+    in_use: bool,
+}
+
+// Attachments the graph creates on demand, kept alive between frames while they keep being requested.
+pub struct TransientImages {
+    images: Vec<TransientImage>,
+    frames_in_flight: usize,
+}
+
+impl Access {
+    
+//----
+    fn is_write(self) -> bool {
+        matches!(self, Access::ColorAttachment | Access::DepthAttachment | Access::ComputeWrite | Access::TransferDst | Access::StorageWrite)
+    }
+    
+//----
+    fn layout(self) -> vk::ImageLayout {
+        match self {
+            Access::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            Access::DepthAttachment => vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            Access::ComputeRead | Access::ComputeWrite | Access::FragmentRead => vk::ImageLayout::GENERAL,
+            Access::FragmentSampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            Access::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            Access::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            _ => vk::ImageLayout::UNDEFINED,
+        }
+    }
+    
+//----
+    fn stage(self) -> vk::PipelineStageFlags2 {
+        use vk::PipelineStageFlags2 as PSF;
+        match self {
+            Access::ColorAttachment => PSF::COLOR_ATTACHMENT_OUTPUT,
+            Access::DepthAttachment => PSF::EARLY_FRAGMENT_TESTS | PSF::LATE_FRAGMENT_TESTS,
+            Access::ComputeRead | Access::ComputeWrite => PSF::COMPUTE_SHADER,
+            Access::FragmentRead | Access::FragmentSampled => PSF::FRAGMENT_SHADER,
+            Access::TransferSrc | Access::TransferDst => PSF::TRANSFER,
+            Access::Uniform | Access::StorageRead | Access::StorageWrite => PSF::VERTEX_SHADER | PSF::FRAGMENT_SHADER | PSF::COMPUTE_SHADER,
+            Access::Index => PSF::INDEX_INPUT,
+        }
+    }
+    
+//----
+    fn access(self) -> vk::AccessFlags2 {
+        use vk::AccessFlags2 as AF;
+        match self {
+            Access::ColorAttachment => AF::COLOR_ATTACHMENT_READ | AF::COLOR_ATTACHMENT_WRITE,
+            Access::DepthAttachment => AF::DEPTH_STENCIL_ATTACHMENT_READ | AF::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            Access::ComputeRead | Access::FragmentRead | Access::FragmentSampled | Access::StorageRead => AF::SHADER_STORAGE_READ | AF::SHADER_SAMPLED_READ,
+            Access::ComputeWrite | Access::StorageWrite => AF::SHADER_STORAGE_READ | AF::SHADER_STORAGE_WRITE,
+            Access::TransferSrc => AF::TRANSFER_READ,
+            Access::TransferDst => AF::TRANSFER_WRITE,
+            Access::Uniform => AF::UNIFORM_READ,
+            Access::Index => AF::INDEX_READ,
+        }
+    }
+    
+//----
+    fn image_usage(self) -> vk::ImageUsageFlags {
+        use vk::ImageUsageFlags as IUF;
+        match self {
+            Access::ColorAttachment => IUF::COLOR_ATTACHMENT,
+            Access::DepthAttachment => IUF::DEPTH_STENCIL_ATTACHMENT,
+            Access::ComputeRead | Access::ComputeWrite | Access::FragmentRead | Access::StorageRead | Access::StorageWrite => IUF::STORAGE,
+            Access::FragmentSampled => IUF::SAMPLED,
+            Access::TransferSrc => IUF::TRANSFER_SRC,
+            Access::TransferDst => IUF::TRANSFER_DST,
+            Access::Uniform | Access::Index => IUF::empty(),
+        }
+    }
+}
+
+impl ResourceState {
+    
+//----
+    // Anything recorded before the graph, including previous frames on the same queue, is waited on by the first barrier.
+    fn initial(layout:vk::ImageLayout) -> Self {
+        Self{
+            layout,
+            stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+            access: vk::AccessFlags2::MEMORY_WRITE,
+            written: true,
+        }
+    }
+    
+//----
+    // Picks up where the image's tracker left it, the recorded stage and access are what the first barrier waits on.
+    fn tracked(name:&str, tracker:&LayoutTracker) -> Self {
+        let Some(state) = tracker.uniform_state() else {
+            logger::various_log!("render_graph",
+                (logger::Warn, "{} is imported with its subresources in different layouts, its contents are discarded", name)
+            );
+            return Self::initial(vk::ImageLayout::UNDEFINED);
+        };
+        Self{
+            layout: state.layout,
+            stage: state.stage,
+            access: state.access,
+            written: true,
+        }
+    }
+    
+//----
+    fn needs_barrier(&self, access:Access, layout:vk::ImageLayout) -> bool {
+        self.layout != layout || self.written || access.is_write()
+    }
+    
+//----
+    fn update(&mut self, access:Access, layout:vk::ImageLayout, barrier:bool) {
+        match barrier {
+            true => {
+                self.layout = layout;
+                self.stage = access.stage();
+                self.access = access.access();
+                self.written = access.is_write();
+            }
+            false => {
+                self.stage |= access.stage();
+                self.access |= access.access();
+            }
+        }
+    }
+}
+
+impl<'c> PassContext<'c> {
+    
+//----
+    pub fn image(&self, id:ImageId) -> ResolvedImage {
+        self.images[id.0].expect("images are resolved before passes run")
+    }
+}
+
+impl<'a> Pass<'a> {
+    
+//----
+    pub fn new(name:&'static str) -> Self {
+        Self{
+            name,
+            images: Vec::new(),
+            buffers: Vec::new(),
+            side_effects: false,
+            execute: None,
+        }
+    }
+    
+//----
+    pub fn read_image(self, id:ImageId, access:Access) -> Self {
+        debug_assert!(!access.is_write());
+        self.use_image(id, access)
+    }
+    
+//----
+    pub fn write_image(self, id:ImageId, access:Access) -> Self {
+        debug_assert!(access.is_write());
+        self.use_image(id, access)
+    }
+    
+//----
+    pub fn read_buffer(self, id:BufferId, access:Access) -> Self {
+        debug_assert!(!access.is_write());
+        self.use_buffer(id, access)
+    }
+    
+//----
+    pub fn write_buffer(self, id:BufferId, access:Access) -> Self {
+        debug_assert!(access.is_write());
+        self.use_buffer(id, access)
+    }
+    
+//----
+    // Keeps the pass even when nothing reads what it writes.
+    pub fn side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+    
+//----
+    pub fn execute(mut self, callback:impl FnOnce(&PassContext) + 'a) -> Self {
+        self.execute = Some(Box::new(callback));
+        self
+    }
+    
+//----
+    // The access decides the synchronization, the public names only check it matches what the pass says it does.
+    fn use_image(mut self, id:ImageId, access:Access) -> Self {
+        self.images.push((id, access));
+        self
+    }
+    
+//----
+    fn use_buffer(mut self, id:BufferId, access:Access) -> Self {
+        self.buffers.push((id, access));
+        self
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    
+//----
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+//----
+    pub fn import_image(
+        &mut self,
+        name: &'static str,
+        image: ResolvedImage,
+        aspect: vk::ImageAspectFlags,
+        initial_layout: vk::ImageLayout,
+        final_layout: Option<vk::ImageLayout>,
+    ) -> ImageId {
+        self.images.push(GraphImage{
+            name,
+            source: ImageSource::Imported(image),
+            aspect,
+            initial: ResourceState::initial(initial_layout),
+            final_layout,
+        });
+        ImageId(self.images.len()-1)
+    }
+    
+//----
+    // Imports an image in the layout its tracker holds, `FinalStates::record` hands the tracker back what the graph did.
+    pub fn import_tracked(&mut self, name:&'static str, image:&Image, final_layout:Option<vk::ImageLayout>) -> ImageId {
+        self.images.push(GraphImage{
+            name,
+            source: ImageSource::Imported(ResolvedImage::from(image)),
+            aspect: image.layout.aspect(),
+            initial: ResourceState::tracked(name, &image.layout),
+            final_layout,
+        });
+        ImageId(self.images.len()-1)
+    }
+    
+//----
+    pub fn create_image(&mut self, name:&'static str, desc:TransientDesc) -> ImageId {
+        self.images.push(GraphImage{
+            name,
+            source: ImageSource::Transient(desc),
+            aspect: desc.aspect,
+            initial: ResourceState::initial(vk::ImageLayout::UNDEFINED),
+            final_layout: None,
+        });
+        ImageId(self.images.len()-1)
+    }
+    
+//----
+    pub fn import_buffer(&mut self, name:&'static str, buffer:vk::Buffer, size:vk::DeviceSize) -> BufferId {
+        self.buffers.push(GraphBuffer{
+            name,
+            buffer,
+            size,
+        });
+        BufferId(self.buffers.len()-1)
+    }
+    
+//----
+    pub fn add_pass(&mut self, pass:Pass<'a>) {
+        self.passes.push(pass);
+    }
+    
+//----
+    pub fn execute(
+        self,
+        device: &mut Device,
+        allocator: &mut Allocator,
+        transient_images: &mut TransientImages,
+        destruction_stack: &mut DestructionStack,
+        frame: usize,
+        cmd: vk::CommandBuffer,
+    ) -> Result<FinalStates, AAError> {
+        let Self{
+            images,
+            buffers,
+            passes,
+        } = self;
+        
+        let live = Self::cull(&images, &passes);
+        let resolved = Self::resolve(&images, &passes, &live, device, allocator, transient_images, frame)?;
+        transient_images.retire_unused(destruction_stack, frame);
+        
+        let mut image_states:Vec<ResourceState> = images.iter().map(|image|image.initial).collect();
+        let mut buffer_states:Vec<ResourceState> = buffers.iter().map(|_|ResourceState::initial(vk::ImageLayout::UNDEFINED)).collect();
+        
+        for (pass, _) in passes.into_iter().zip(live.iter()).filter(|(_, live)|**live) {
+            let mut image_barriers = Vec::new();
+            let mut buffer_barriers = Vec::new();
+            
+            for &(ImageId(index), access) in pass.images.iter() {
+                let state = &mut image_states[index];
+                let layout = access.layout();
+                let barrier = state.needs_barrier(access, layout);
+                if barrier {
+                    logger::various_log!("render_graph",
+                        (logger::Trace, "{}: image {} {:?} -> {:?}", pass.name, images[index].name, state.layout, layout)
+                    );
+                    let image = resolved[index].expect("live images are resolved");
+                    image_barriers.push(vk::ImageMemoryBarrier2::builder()
+                        .image(image.image)
+                        .old_layout(state.layout)
+                        .new_layout(layout)
+                        .src_stage_mask(state.stage)
+                        .src_access_mask(state.access)
+                        .dst_stage_mask(access.stage())
+                        .dst_access_mask(access.access())
+                        .subresource_range(Image::subresource_range(images[index].aspect))
+                        .build()
+                    );
+                }
+                state.update(access, layout, barrier);
+            }
+            
+            for &(BufferId(index), access) in pass.buffers.iter() {
+                let state = &mut buffer_states[index];
+                let barrier = state.needs_barrier(access, vk::ImageLayout::UNDEFINED);
+                if barrier {
+                    logger::various_log!("render_graph",
+                        (logger::Trace, "{}: buffer {} {:?}", pass.name, buffers[index].name, access)
+                    );
+                    buffer_barriers.push(vk::BufferMemoryBarrier2::builder()
+                        .buffer(buffers[index].buffer)
+                        .offset(0)
+                        .size(buffers[index].size)
+                        .src_stage_mask(state.stage)
+                        .src_access_mask(state.access)
+                        .dst_stage_mask(access.stage())
+                        .dst_access_mask(access.access())
+                        .build()
+                    );
+                }
+                state.update(access, vk::ImageLayout::UNDEFINED, barrier);
+            }
+            
+            logger::various_log!("render_graph",
+                (logger::Trace, "pass {} with {} image and {} buffer barriers", pass.name, image_barriers.len(), buffer_barriers.len())
+            );
+            Self::barrier(device, cmd, &image_barriers, &buffer_barriers);
+            
+            if let Some(callback) = pass.execute {
+                let context = PassContext{
+                    device,
+                    cmd,
+                    images: &resolved,
+                };
+                callback(&context);
+            }
+        }
+        
+        let mut final_barriers = Vec::new();
+        let mut final_states = Vec::with_capacity(images.len());
+        for (index, image) in images.iter().enumerate() {
+            let state = &image_states[index];
+            let (Some(layout), Some(resolved)) = (image.final_layout, resolved[index]) else {
+                final_states.push(SubresourceState{
+                    layout: state.layout,
+                    stage: state.stage,
+                    access: state.access,
+                });
+                continue;
+            };
+            final_states.push(SubresourceState{
+                layout,
+                stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                access: vk::AccessFlags2::NONE,
+            });
+            final_barriers.push(vk::ImageMemoryBarrier2::builder()
+                .image(resolved.image)
+                .old_layout(state.layout)
+                .new_layout(layout)
+                .src_stage_mask(state.stage)
+                .src_access_mask(state.access)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::empty())
+                .subresource_range(Image::subresource_range(image.aspect))
+                .build()
+            );
+        }
+        Self::barrier(device, cmd, &final_barriers, &[]);
+        
+        Ok(FinalStates(final_states))
+    }
+    
+//----
+    // Walks the passes backwards keeping the ones whose writes reach an exported image or have side effects.
+    fn cull(images:&[GraphImage], passes:&[Pass]) -> Vec<bool> {
+        let mut needed_images:Vec<bool> = images.iter().map(|image|image.final_layout.is_some()).collect();
+        let mut needed_buffers = vec![false; passes.iter().flat_map(|pass|pass.buffers.iter()).map(|(BufferId(index), _)|index+1).max().unwrap_or(0)];
+        let mut live = vec![false; passes.len()];
+        
+        for (index, pass) in passes.iter().enumerate().rev() {
+            let writes_needed = pass.images.iter().any(|(ImageId(image), access)|access.is_write() && needed_images[*image])
+                || pass.buffers.iter().any(|(BufferId(buffer), access)|access.is_write() && needed_buffers[*buffer]);
+                
+            if !(pass.side_effects || writes_needed) {
+                logger::various_log!("render_graph",
+                    (logger::Trace, "culling pass {}", pass.name)
+                );
+                continue;
+            }
+            live[index] = true;
+            for (ImageId(image), _) in pass.images.iter() {
+                needed_images[*image] = true;
+            }
+            for (BufferId(buffer), _) in pass.buffers.iter() {
+                needed_buffers[*buffer] = true;
+            }
+        }
+        live
+    }
+    
+//----
+    fn resolve(
+        images: &[GraphImage],
+        passes: &[Pass],
+        live: &[bool],
+        device: &mut Device,
+        allocator: &mut Allocator,
+        transient_images: &mut TransientImages,
+        frame: usize,
+    ) -> Result<Vec<Option<ResolvedImage>>, AAError> {
+        let mut usages = vec![vk::ImageUsageFlags::empty(); images.len()];
+        for (pass, _) in passes.iter().zip(live.iter()).filter(|(_, live)|**live) {
+            for (ImageId(index), access) in pass.images.iter() {
+                usages[*index] |= access.image_usage();
+            }
+        }
+        
+        let mut holder = Vec::with_capacity(images.len());
+        for (index, image) in images.iter().enumerate() {
+            let resolved = match (&image.source, usages[index].is_empty()) {
+                (ImageSource::Imported(resolved), _) => Some(*resolved),
+                (ImageSource::Transient(_), true) => None,
+                (ImageSource::Transient(desc), false) => {
+                    Some(transient_images.acquire(device, allocator, image.name, *desc, usages[index], frame)?)
+                }
+            };
+            holder.push(resolved);
+        }
+        Ok(holder)
+    }
+    
+//----
+    fn barrier(device:&Device, cmd:vk::CommandBuffer, image_barriers:&[vk::ImageMemoryBarrier2], buffer_barriers:&[vk::BufferMemoryBarrier2]) {
+        if image_barriers.is_empty() && buffer_barriers.is_empty() {
+            return;
+        }
+        let dependency = vk::DependencyInfo::builder()
+            .image_memory_barriers(image_barriers)
+            .buffer_memory_barriers(buffer_barriers);
+        unsafe{device.cmd_pipeline_barrier2(cmd, &dependency)};
+    }
+}
+
+impl FinalStates {
+    
+//----
+    pub fn record(&self, id:ImageId, image:&mut Image) {
+        let range = image.layout.full_range();
+        image.layout.set(range, self.0[id.0]);
+    }
+}
+
+impl TransientImages {
+    
+//----
+    pub fn new(frames_in_flight:usize) -> Self {
+        Self{
+            images: Vec::new(),
+            frames_in_flight,
+        }
+    }
+    
+//----
+    fn acquire(
+        &mut self,
+        device: &mut Device,
+        allocator: &mut Allocator,
+        name: &str,
+        desc: TransientDesc,
+        usage: vk::ImageUsageFlags,
+        frame: usize,
+    ) -> Result<ResolvedImage, AAError> {
+        let found = self.images.iter_mut()
+            .find(|transient|!transient.in_use && transient.desc == desc && transient.usage.contains(usage));
+            
+        let transient = match found {
+            Some(transient) => transient,
+            None => {
+                logger::various_log!("render_graph",
+                    (logger::Debug, "allocating transient image {} {:?}", name, desc)
+                );
+                let metadata = ImageMetadata::transient(desc.format, usage, desc.aspect);
+                let image = Image::create(device, allocator, desc.extent, metadata, Some(name))?;
+                self.images.push(TransientImage{
+                    desc,
+                    usage,
+                    image,
+                    last_used: frame,
+                    in_use: false,
+                });
+                self.images.last_mut().expect("an image was just pushed")
+            }
+        };
+        
+        transient.in_use = true;
+        transient.last_used = frame;
+        Ok(ResolvedImage::from(&transient.image))
+    }
+    
+//----
+    // Images nobody asked for during a whole frames in flight cycle are handed to the frame destruction stack.
+    fn retire_unused(&mut self, destruction_stack:&mut DestructionStack, frame:usize) {
+        let mut index = 0;
+        while index < self.images.len() {
+            let transient = &mut self.images[index];
+            transient.in_use = false;
+            if transient.last_used + self.frames_in_flight < frame {
+                let mut transient = self.images.swap_remove(index);
+                destruction_stack.push(transient.image.defered_destruct());
+                continue;
+            }
+            index += 1;
+        }
+    }
+}
+
+
+impl From<&Image> for ResolvedImage {
+    fn from(image:&Image) -> Self {
+        Self{
+            image: image.image,
+            view: image.view,
+            extent: image.extent,
+            format: image.format,
+        }
+    }
+}
+
+
+impl VkDestructor for TransientImages {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        logger::destruct!("transient_images");
+        let (device, allocator) = args.unwrap_dev_all();
+        for transient in self.images.into_iter() {
+            transient.image.destruct(VkDestructorArguments::DevAll(device, allocator));
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::taa;
+
+use crate::graphics::ComputePushConstants;
+
+use super::VkDestructor;
+use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::DrawContext;
+use super::super::VkDynamicDestructor;
+use super::super::object_name;
+use super::super::Device;
+use super::super::Allocator;
+use super::super::Image;
+use super::super::CPipeline;
+use super::super::EffectImage;
+use super::super::DescriptorWriter;
+use super::super::GDescriptorAllocator;
+use super::super::DestructionStack;
+use super::super::image;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use ash::vk;
+use nalgebra as na;
+use na::Matrix4;
+use na::Vector2;
+use na::Vector3;
+use na::Vector4;
+
+// Jitters the geometry inside the pixel and accumulates it over a history reprojected with the motion vectors.
+pub struct TemporalAA {
+    // Missing while the resolve shader doesn't build, frames are then drawn without TAA.
+    pipeline: Option<CPipeline>,
+    // The resolve reads one and writes the other, they swap every frame.
+    history: [Image; 2],
+    // Index of the history holding the last resolved frame.
+    latest: usize,
+    pub enabled: bool,
+    active: bool,
+    // False after a cut, the next frame starts the history over.
+    valid: bool,
+    sample: usize,
+    extent: vk::Extent2D,
+    field_of_view: Vector3<f32>,
+    view_projection: Matrix4<f32>,
+    // Transforms drawn last frame, keyed by surface and by how many times it had been drawn before in that frame.
+    transforms: HashMap<(vk::Buffer, u32, usize), Matrix4<f32>>,
+}
+
+impl TemporalAA {
+
+//----
+    pub fn create(device:&mut Device, allocator:&mut Allocator, extent:vk::Extent3D) -> Result<Self, AAError> {
+        logger::create!("taa");
+        
+        let first = Image::create(device, allocator, extent, image::RENDER, Some("TAA HISTORY A"))?;
+        let second = match Image::create(device, allocator, extent, image::RENDER, Some("TAA HISTORY B")) {
+            Ok(second) => second,
+            Err(error) => {
+                first.destruct(VkDestructorArguments::DevAll(device, allocator));
+                return Err(error);
+            }
+        };
+        
+        Ok(Self{
+            pipeline: None,
+            history: [first, second],
+            latest: 0,
+            enabled: false,
+            active: false,
+            valid: false,
+            sample: 0,
+            extent: vk::Extent2D::default(),
+            field_of_view: Vector3::zeros(),
+            view_projection: Matrix4::identity(),
+            transforms: HashMap::new(),
+        })
+    }
+    
+//----
+    // On failure the previous pipeline, if any, stays in use.
+    pub fn build_pipeline(&mut self, device:&mut Device, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        let images = [EffectImage::Draw, EffectImage::Input, EffectImage::Input, EffectImage::Input];
+        let pipeline = CPipeline::create(device, taa::RESOLVE_SHADER, &images, [16, 16])?;
+        if let Some(old) = self.pipeline.replace(pipeline) {
+            let callback = Box::new(move |args:VkDestructorArguments|old.destruct(args));
+            destruction_stack.push(VkDynamicDestructor::new::<CPipeline>(object_name("taa"), VkDestructorType::Dev, callback));
+        }
+        Ok(())
+    }
+    
+//----
+    pub fn reload(&mut self, device:&mut Device, shader:&Path, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        if shader != Path::new(taa::RESOLVE_SHADER) {
+            return Ok(());
+        }
+        logger::various_log!("taa",
+            (logger::Debug, "reloading temporal resolve pipeline")
+        );
+        self.build_pipeline(device, destruction_stack)?;
+        self.reset();
+        Ok(())
+    }
+    
+//----
+    pub fn reset(&mut self) {
+        self.valid = false;
+        self.transforms.clear();
+    }
+    
+//----
+    // Decides whether this frame is accumulated and returns its projection jitter in NDC, zero when it isn't.
+    pub fn begin_frame(&mut self, extent:vk::Extent2D, field_of_view:&Vector3<f32>, single_sampled:bool) -> Vector2<f32> {
+        self.active = self.enabled && single_sampled && self.pipeline.is_some();
+        
+        // Resizes, a new render scale and projection changes are cuts, nothing from before lines up with them.
+        if !self.active || extent != self.extent || *field_of_view != self.field_of_view {
+            self.reset();
+        }
+        self.extent = extent;
+        self.field_of_view = *field_of_view;
+        
+        if !self.active {
+            return Vector2::zeros();
+        }
+        self.sample = (self.sample + 1) % taa::JITTER_SAMPLES;
+        let offset = Vector2::new(Self::halton(self.sample + 1, 2), Self::halton(self.sample + 1, 3)) - Vector2::repeat(0.5);
+        Vector2::new(offset.x * 2.0 / extent.width as f32, offset.y * 2.0 / extent.height as f32)
+    }
+    
+//----
+    // Leaves this frame out of the accumulation, the history starts over with the next one.
+    pub fn skip_frame(&mut self) {
+        self.active = false;
+        self.reset();
+    }
+    
+//----
+    // Fills the previous transform of every object drawn last frame, new ones keep their current one.
+    pub fn carry_transforms(&mut self, draw_context:&mut DrawContext) {
+        let mut drawn = HashMap::with_capacity(draw_context.len());
+        for render_object in draw_context.iter_mut() {
+            let mut key = (render_object.index_buffer, render_object.first_index, 0);
+            while drawn.contains_key(&key) {
+                key.2 += 1;
+            }
+            if let Some(previous) = self.transforms.get(&key) {
+                render_object.previous_transform = *previous;
+            }
+            drawn.insert(key, render_object.transform);
+        }
+        self.transforms = drawn;
+    }
+    
+//----
+    // Last frame's unjittered view projection, or `current` after a cut, `current` is kept for the next frame.
+    pub fn swap_view_projection(&mut self, current:Matrix4<f32>) -> Matrix4<f32> {
+        let previous = std::mem::replace(&mut self.view_projection, current);
+        match self.valid {
+            true => previous,
+            false => current,
+        }
+    }
+    
+//----
+    pub fn push_constants(&self, extent:vk::Extent2D) -> ComputePushConstants {
+        let weight = match self.valid {
+            true => taa::BLEND,
+            false => 1.0,
+        };
+        ComputePushConstants([
+            Vector4::new(weight, 0.0, 0.0, 0.0),
+            Vector4::new(0.0, 0.0, extent.width as f32, extent.height as f32),
+            Vector4::zeros(),
+            Vector4::zeros(),
+        ])
+    }
+    
+//----
+    // The history read this frame and the one written, the read one is only meaningful while the history is valid.
+    pub fn history(&self) -> (&Image, &Image) {
+        (&self.history[self.latest], &self.history[1 - self.latest])
+    }
+    
+//----
+    // Same order as `history`, for recording the layouts the frame left them in.
+    pub fn history_mut(&mut self) -> (&mut Image, &mut Image) {
+        let [first, second] = &mut self.history;
+        match self.latest {
+            0 => (first, second),
+            _ => (second, first),
+        }
+    }
+    
+//----
+    // Only there while this frame is accumulated.
+    pub fn pipeline(&self) -> Option<&CPipeline> {
+        self.pipeline.as_ref().filter(|_|self.active)
+    }
+    
+//----
+    // Binds the written history at 0, then the current frame, the read history and the motion vectors.
+    pub fn allocate_descriptor(&self, device:&mut Device, allocator:&mut GDescriptorAllocator, render_image:&Image, motion_image:&Image) -> Result<Option<vk::DescriptorSet>, AAError> {
+        let Some(pipeline) = self.pipeline() else {
+            return Ok(None);
+        };
+        let (read, write) = self.history();
+        let set = allocator.allocate(device, &pipeline.descriptor_layout)?;
+        let mut writer = DescriptorWriter::validated(pipeline.signature.clone());
+        for (binding, view) in [write.view, render_image.view, read.view, motion_image.view].into_iter().enumerate() {
+            writer.write_image(binding as u32, view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+        }
+        writer.update_set(device, set)?;
+        Ok(Some(set))
+    }
+    
+//----
+    // Called once the frame is recorded, what it wrote becomes the history of the next one.
+    pub fn end_frame(&mut self) {
+        if self.active {
+            self.latest = 1 - self.latest;
+            self.valid = true;
+        }
+    }
+    
+//----
+    fn halton(mut index:usize, base:usize) -> f32 {
+        let mut fraction = 1.0;
+        let mut result = 0.0;
+        while index > 0 {
+            fraction /= base as f32;
+            result += fraction * (index % base) as f32;
+            index /= base;
+        }
+        result
+    }
+}
+
+impl VkDestructor for TemporalAA {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        logger::destruct!("taa");
+        let (device, allocator) = args.unwrap_dev_all();
+        if let Some(pipeline) = self.pipeline {
+            pipeline.destruct(VkDestructorArguments::Dev(device));
+        }
+        let [first, second] = self.history;
+        first.destruct(VkDestructorArguments::DevAll(device, allocator));
+        second.destruct(VkDestructorArguments::DevAll(device, allocator));
+    }
+}
@@ -1,17 +1,22 @@
 use super::MaterialInstance;
+use super::MaterialPass;
 use super::GeoSurface;
+use super::Bounds;
 use super::RenderObject;
+use super::Handle;
 
 
 use derivative::Derivative;
+use nalgebra as na;
 
 #[derive(Derivative, Default)]
 #[derivative(Debug)]
 pub struct VkGeoSurface {
     pub start_index: u32,
     pub count: u32,
-    #[derivative(Debug="ignore")]
-    pub material: Option<MaterialInstance>,
+    // Holds a registry reference while the mesh is loaded.
+    pub material: Option<Handle<MaterialInstance>>,
+    pub bounds: Bounds,
 }
 
 impl From<VkGeoSurface> for GeoSurface {
@@ -19,17 +24,75 @@ impl From<VkGeoSurface> for GeoSurface {
         GeoSurface{
             start_index: base.start_index,
             count: base.count,
+            bounds: base.bounds,
         }
     }
 }
 
-/*
+// Opaque surfaces are drawn first, the blended ones over them.
 #[derive(Default, Debug)]
 pub struct DrawContext {
-    pub context: Vec<RenderObject>,
+    pub opaque_surfaces: Vec<RenderObject>,
+    pub transparent_surfaces: Vec<RenderObject>,
 }
-*/
 
-pub type DrawContext = Vec<RenderObject>;
+impl DrawContext {
+    // Surfaces without a material get the default one, which is opaque.
+    pub fn push(&mut self, render_object:RenderObject) {
+        match render_object.material.as_ref().map(|material|material.pass_type) {
+            Some(MaterialPass::Transparent) => self.transparent_surfaces.push(render_object),
+            _ => self.opaque_surfaces.push(render_object),
+        }
+    }
+    
+    pub fn clear(&mut self) {
+        self.opaque_surfaces.clear();
+        self.transparent_surfaces.clear();
+    }
+    
+    pub fn len(&self) -> usize {
+        self.opaque_surfaces.len() + self.transparent_surfaces.len()
+    }
+    
+    // In the order they are drawn.
+    pub fn iter(&self) -> impl Iterator<Item = &RenderObject> {
+        self.opaque_surfaces.iter().chain(self.transparent_surfaces.iter())
+    }
+    
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut RenderObject> {
+        self.opaque_surfaces.iter_mut().chain(self.transparent_surfaces.iter_mut())
+    }
+    
+    pub fn retain<F:FnMut(&RenderObject)->bool>(&mut self, mut keep:F) {
+        self.opaque_surfaces.retain(&mut keep);
+        self.transparent_surfaces.retain(keep);
+    }
+    
+    // Opaque surfaces are grouped so consecutive draws share state, blended ones go from the farthest to the nearest.
+    pub fn sort(&mut self, view_projection:&na::Matrix4<f32>) {
+        self.opaque_surfaces.sort_unstable_by_key(RenderObject::state_key);
+        self.transparent_surfaces.sort_by_cached_key(|render_object|{
+            std::cmp::Reverse(OrderedDepth(render_object.view_depth(view_projection)))
+        });
+    }
+}
+
+// Total order over the depths, for the sort key.
+#[derive(PartialEq)]
+struct OrderedDepth(f32);
+
+impl Eq for OrderedDepth {}
+
+impl PartialOrd for OrderedDepth {
+    fn partial_cmp(&self, other:&Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedDepth {
+    fn cmp(&self, other:&Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
 
 
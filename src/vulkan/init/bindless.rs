@@ -0,0 +1,240 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::bindless;
+use crate::errors::messages::GRANTED;
+
+use super::VkDestructor;
+use super::VkDestructorArguments;
+use super::Device;
+use super::PDevice;
+use super::Image;
+use super::Sampler;
+use super::DescriptorLayout;
+use super::DescriptorLayoutBuilder;
+use super::ShaderReflection;
+
+use std::slice::from_ref;
+
+use ash::vk;
+
+// Slot of a resource inside one of the bindless arrays, this is what shaders index with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindlessIndex(u32);
+
+struct Slots {
+    name: &'static str,
+    next: u32,
+    capacity: u32,
+}
+
+// One descriptor set shared by every material: partially bound, update after bind arrays of images, samplers and storage buffers.
+pub struct BindlessSet {
+    layout: DescriptorLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    images: Slots,
+    samplers: Slots,
+    buffers: Slots,
+}
+
+impl BindlessIndex {
+    
+//----
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl Slots {
+    
+//----
+    fn new(name:&'static str, capacity:u32) -> Self {
+        Self{
+            name,
+            next: 0,
+            capacity,
+        }
+    }
+    
+//----
+    // Slots are never handed back, everything registered lives as long as the set.
+    fn take(&mut self) -> Result<u32, AAError> {
+        if self.next == self.capacity {
+            return Err(AAError::BindlessFull(self.name));
+        }
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+}
+
+impl BindlessSet {
+    
+//----
+    pub fn create(device:&mut Device, p_device:&PDevice) -> Result<Self, AAError> {
+        logger::create!("bindless_set");
+        
+        Self::check_limits(&p_device.descriptor_indexing)?;
+        
+        let mut builder = DescriptorLayoutBuilder::create();
+        builder.add_binding(bindless::IMAGE_BINDING, vk::DescriptorType::SAMPLED_IMAGE, bindless::IMAGES);
+        builder.add_binding(bindless::SAMPLER_BINDING, vk::DescriptorType::SAMPLER, bindless::SAMPLERS);
+        builder.add_binding(bindless::BUFFER_BINDING, vk::DescriptorType::STORAGE_BUFFER, bindless::BUFFERS);
+        
+        let (layout, _counts) = builder.build_with_flags(
+            device,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE,
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+        )?;
+        
+        let pool_sizes = [
+            vk::DescriptorPoolSize{ty: vk::DescriptorType::SAMPLED_IMAGE, descriptor_count: bindless::IMAGES},
+            vk::DescriptorPoolSize{ty: vk::DescriptorType::SAMPLER, descriptor_count: bindless::SAMPLERS},
+            vk::DescriptorPoolSize{ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: bindless::BUFFERS},
+        ];
+        let pool_ci = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+            
+        let pool = match unsafe{device.create_descriptor_pool(&pool_ci, None)} {
+            Ok(pool) => pool,
+            Err(error) => {
+                layout.destruct(VkDestructorArguments::Dev(device));
+                return Err(error.into());
+            }
+        };
+        
+        let set_layout = layout.underlying();
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(from_ref(&set_layout));
+            
+        let set = match unsafe{device.allocate_descriptor_sets(&allocate_info)} {
+            Ok(mut sets) => sets.pop().expect(GRANTED),
+            Err(error) => {
+                unsafe{device.destroy_descriptor_pool(pool, None)};
+                layout.destruct(VkDestructorArguments::Dev(device));
+                return Err(error.into());
+            }
+        };
+        
+        Ok(Self{
+            layout,
+            pool,
+            set,
+            images: Slots::new("image", bindless::IMAGES),
+            samplers: Slots::new("sampler", bindless::SAMPLERS),
+            buffers: Slots::new("buffer", bindless::BUFFERS),
+        })
+    }
+    
+//----
+    // The arrays are visible to every stage, so both the set and the per stage update after bind limits apply.
+    fn check_limits(limits:&vk::PhysicalDeviceDescriptorIndexingProperties) -> Result<(), AAError> {
+        let checks = [
+            ("images", bindless::IMAGES, limits.max_descriptor_set_update_after_bind_sampled_images.min(limits.max_per_stage_descriptor_update_after_bind_sampled_images)),
+            ("samplers", bindless::SAMPLERS, limits.max_descriptor_set_update_after_bind_samplers.min(limits.max_per_stage_descriptor_update_after_bind_samplers)),
+            ("buffers", bindless::BUFFERS, limits.max_descriptor_set_update_after_bind_storage_buffers.min(limits.max_per_stage_descriptor_update_after_bind_storage_buffers)),
+            // Samplers are not counted as stage resources.
+            ("resources", bindless::IMAGES + bindless::BUFFERS, limits.max_per_stage_update_after_bind_resources),
+        ];
+        for (name, needed, allowed) in checks {
+            if needed > allowed {
+                return Err(AAError::BindlessLimit(name, needed, allowed));
+            }
+        }
+        Ok(())
+    }
+    
+//----
+    pub fn layout(&self) -> &DescriptorLayout {
+        &self.layout
+    }
+    
+//----
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+    
+//----
+    // The layout is fixed, shaders only have to declare the arrays they use with matching types.
+    pub fn check_interface(&self, interface:&ShaderReflection, set:u32) -> Result<(), AAError> {
+        let expected = [
+            (bindless::IMAGE_BINDING, vk::DescriptorType::SAMPLED_IMAGE),
+            (bindless::SAMPLER_BINDING, vk::DescriptorType::SAMPLER),
+            (bindless::BUFFER_BINDING, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+        for (binding, descriptor_type) in expected {
+            if interface.binding(set, binding).is_some() {
+                interface.check_binding(set, binding, descriptor_type)?;
+            }
+        }
+        Ok(())
+    }
+
+//----
+    // The image has to be in SHADER_READ_ONLY_OPTIMAL whenever a shader samples it.
+    pub fn register_image(&mut self, device:&Device, image:&Image) -> Result<BindlessIndex, AAError> {
+        let index = self.images.take()?;
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_view(image.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(bindless::IMAGE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(from_ref(&image_info));
+            
+        unsafe{device.update_descriptor_sets(from_ref(&write), &[])};
+        Ok(BindlessIndex(index))
+    }
+    
+//----
+    pub fn register_sampler(&mut self, device:&Device, sampler:&Sampler) -> Result<BindlessIndex, AAError> {
+        let index = self.samplers.take()?;
+        let image_info = vk::DescriptorImageInfo::builder()
+            .sampler(sampler.underlying());
+            
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(bindless::SAMPLER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(from_ref(&image_info));
+            
+        unsafe{device.update_descriptor_sets(from_ref(&write), &[])};
+        Ok(BindlessIndex(index))
+    }
+    
+//----
+    pub fn register_buffer(&mut self, device:&Device, buffer:vk::Buffer, size:vk::DeviceSize) -> Result<BindlessIndex, AAError> {
+        let index = self.buffers.take()?;
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer)
+            .offset(0)
+            .range(size);
+            
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(bindless::BUFFER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(from_ref(&buffer_info));
+            
+        unsafe{device.update_descriptor_sets(from_ref(&write), &[])};
+        Ok(BindlessIndex(index))
+    }
+}
+
+
+impl VkDestructor for BindlessSet {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        logger::destruct!("bindless_set");
+        let device = args.unwrap_dev();
+        unsafe{device.destroy_descriptor_pool(self.pool, None)};
+        self.layout.destruct(VkDestructorArguments::Dev(device));
+    }
+}
@@ -8,11 +8,16 @@ use super::VkDestructorType;
 use super::VkDeferedDestructor;
 use super::VkDynamicDestructor;
 use super::VkDestructorArguments;
+use super::Registered;
+use super::ObjectName;
+use super::object_name;
 use super::Device;
 use super::Allocator;
+use super::AllocationPolicy;
 
 use std::mem::align_of;
 use std::mem::ManuallyDrop;
+use std::panic::Location;
 
 use ash::vk;
 use gpu_allocator::vulkan as gpu_vk;
@@ -22,10 +27,13 @@ use gpu_allocator as gpu_all;
 pub struct Buffer{
     pub buffer: vk::Buffer,
     pub allocation: ManuallyDrop<gpu_vk::Allocation>,
+    pub name: ObjectName,
+    created_at: &'static Location<'static>,
 }
 macros::impl_underlying!(Buffer, vk::Buffer, buffer);
 
 impl Buffer{
+    #[track_caller]
     pub fn create(
         device:&mut Device, 
         allocator: &mut Allocator,
@@ -33,6 +41,18 @@ impl Buffer{
         size: u64, 
         usage_flags: vk::BufferUsageFlags, 
         location: gpu_all::MemoryLocation,
+    ) -> Result<Self, AAError> {
+        Self::create_with_policy(device, allocator, name_arg, size, usage_flags, AllocationPolicy::new(location))
+    }
+    
+    #[track_caller]
+    pub fn create_with_policy(
+        device:&mut Device, 
+        allocator: &mut Allocator,
+        name_arg: Option<&str>,
+        size: u64, 
+        usage_flags: vk::BufferUsageFlags, 
+        policy: AllocationPolicy,
     ) -> Result<Self, AAError> {
         logger::create!("buffer");
         let create_info = vk::BufferCreateInfo::builder()
@@ -42,20 +62,20 @@ impl Buffer{
         
         let buffer = unsafe{device.create_buffer(&create_info, None)}?;
         
-        let memory_requirements = unsafe{device.get_buffer_memory_requirements(buffer)};
-        
         let name = match name_arg {
             Some(name) => name,
             None => "",
         };
         
-        let allocation = allocator.allocate(name, memory_requirements, location);
+        let allocation = allocator.allocate_buffer(device, name, buffer, policy)?;
         
         unsafe{device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())}?;
         
         Ok(Self{
             buffer,
             allocation:ManuallyDrop::new(allocation),
+            name: object_name(name),
+            created_at: Location::caller(),
         })
     }
     
@@ -98,18 +118,28 @@ impl VkDestructor for Buffer {
         let (device, allocator) = args.unwrap_dev_all();
         self.internal_destroy(device, allocator);
     }
+    
+    fn created_at(&self) -> Option<&'static Location<'static>> {
+        Some(self.created_at)
+    }
+}
+
+
+impl Registered for Buffer {
+    const DESTRUCTOR_TYPE:VkDestructorType = VkDestructorType::DevAll;
 }
 
 
 impl VkDeferedDestructor for Buffer {
     fn defered_destruct(&mut self) -> VkDynamicDestructor {
         let target = unsafe{self.unsafe_clone()};
+        let name = target.name;
         let callback = Box::new(move |mut args:VkDestructorArguments|{
             let target:Self = target;
             let (device, allocator) = args.unwrap_dev_all();
             target.internal_destroy(device, allocator);
         });
-        (callback, VkDestructorType::DevAll)
+        VkDynamicDestructor::new::<Self>(name, VkDestructorType::DevAll, callback).created_at(Some(self.created_at))
     }
 }
 
@@ -1,22 +1,32 @@
 use crate::AAError;
-use crate::constants;
 use crate::logger;
 use crate::errors::messages::GRANTED;
 
 use super::super::graphics as vk_graphics;
 use vk_graphics::ComputePushConstants;
+use crate::graphics::ParameterSchema;
 //use vk_graphics::ComputeEffectMetadata;
 
 use super::VkDestructorArguments;
 use super::VkDestructor;
+use super::VkDestructorType;
+use super::VkDynamicDestructor;
+use super::object_name;
+use super::super::DestructionStack;
 use super::Device;
 use super::DescriptorLayout;
+use super::DescriptorWriter;
+use super::LayoutSignature;
+use super::GDescriptorAllocator;
+use super::EffectManifest;
+use super::EffectImage;
 use super::pipeline;
 
 use std::slice::from_ref;
+use std::mem::size_of;
+use std::path::Path;
 
 use ash::vk;
-use nalgebra::Vector4;
 use arrayvec::ArrayString;
 use derivative::Derivative;
 
@@ -25,84 +35,143 @@ use derivative::Derivative;
 pub struct CPipeline {
     pub layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
+    pub descriptor_layout: DescriptorLayout,
+    pub signature: LayoutSignature,
+}
+
+// Views the images of an effect resolve to for one dispatch.
+#[derive(Debug, Clone, Copy)]
+pub struct EffectTargets {
+    pub draw: vk::ImageView,
+    pub input: vk::ImageView,
+    pub lut: vk::ImageView,
+    pub exposure: vk::ImageView,
+    pub bloom: vk::ImageView,
 }
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct ComputeEffects {
     pub names: Vec<ArrayString<64>>,
+    pub shaders: Vec<String>,
+    pub workgroup_sizes: Vec<[u32; 2]>,
+    pub images: Vec<Vec<EffectImage>>,
     pub push_constants: Vec<ComputePushConstants>,
+    pub schemas: Vec<ParameterSchema>,
+    pub enabled: Vec<bool>,
     #[derivative(Debug="ignore")]
     pub pipelines: Vec<CPipeline>,
 }
 
 
-
-pub fn init_pipelines(device:&mut Device, ds_layout:&DescriptorLayout) -> ComputeEffects {
-    
-    logger::various_log!("compute_pipeline",
-        (logger::Warn, "Instancing simple compute effects pipeline")
-    );
+impl ComputeEffects {
     
-    let mut names = Vec::new();
-    let mut push_constants:Vec<ComputePushConstants> = Vec::new();
-    let mut pipelines = Vec::new();
-    let mut effect_name = ArrayString::new();
-    
-    let gradient = CPipeline::create(device, ds_layout, constants::comp::GRADIENT_SHADER).unwrap();
-    effect_name.push_str("gradient");
-    logger::various_log!("compute_pipeline",
-        (logger::Warn, "Instancing {} compute pipeline", effect_name)
-    );
-    let push_constant_holder = ComputePushConstants([
-        Vector4::new(1.0,0.4,0.4,1.0),
-        Vector4::new(0.4,1.0,0.4,1.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-    ]);
-    names.push(effect_name);
-    push_constants.push(push_constant_holder);
-    pipelines.push(gradient);
-    effect_name.clear();
+//----
+    pub fn empty() -> Self {
+        Self{
+            names: Vec::new(),
+            shaders: Vec::new(),
+            workgroup_sizes: Vec::new(),
+            images: Vec::new(),
+            push_constants: Vec::new(),
+            schemas: Vec::new(),
+            enabled: Vec::new(),
+            pipelines: Vec::new(),
+        }
+    }
     
+//----
+    // Builds every effect of the manifest, nothing is kept if one of them fails.
+    pub fn load<P:AsRef<Path>>(device:&mut Device, manifest:P) -> Result<Self, AAError> {
+        let manifest = EffectManifest::load(manifest)?;
+        
+        let mut holder = Self::empty();
+        for effect in manifest.effects.iter() {
+            logger::various_log!("compute_pipeline",
+                (logger::Debug, "Instancing {} compute pipeline", effect.name)
+            );
+            let pipeline = match CPipeline::create(device, &effect.shader, &effect.images, effect.workgroup_size) {
+                Ok(pipeline) => pipeline,
+                Err(error) => {
+                    holder.destruct(VkDestructorArguments::Dev(device));
+                    return Err(error);
+                }
+            };
+            let mut name = ArrayString::new();
+            name.push_str(&effect.name);
+            let (push_constants, schema) = effect.parameters().expect(GRANTED);
+            
+            holder.names.push(name);
+            holder.shaders.push(effect.shader.clone());
+            holder.workgroup_sizes.push(effect.workgroup_size);
+            holder.images.push(effect.images.clone());
+            holder.push_constants.push(push_constants);
+            holder.schemas.push(schema);
+            holder.enabled.push(effect.enabled);
+            holder.pipelines.push(pipeline);
+        }
+        Ok(holder)
+    }
     
-    let gradient = CPipeline::create(device, ds_layout, constants::comp::COMP_SHADER).unwrap();
-    effect_name.push_str("square fade");
-    logger::various_log!("compute_pipeline",
-        (logger::Warn, "Instancing {} compute pipeline", effect_name)
-    );
-    let push_constant_holder = ComputePushConstants([
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-    ]);
-    names.push(effect_name);
-    push_constants.push(push_constant_holder);
-    pipelines.push(gradient);
-    effect_name.clear();
+//----
+    // Swaps in a freshly loaded manifest, parameters and toggles of effects that kept their name survive the swap.
+    pub fn replace(&mut self, mut fresh:Self, destruction_stack:&mut DestructionStack) {
+        for (fresh_index, name) in fresh.names.iter().enumerate() {
+            if let Some(index) = self.names.iter().position(|held|held == name) {
+                fresh.push_constants[fresh_index] = self.push_constants[index];
+                fresh.enabled[fresh_index] = self.enabled[index];
+            }
+        }
+        let old = std::mem::replace(self, fresh);
+        let callback = Box::new(move |args:VkDestructorArguments|old.destruct(args));
+        destruction_stack.push(VkDynamicDestructor::new::<Self>(object_name("compute_effects"), VkDestructorType::Dev, callback));
+    }
     
+//----
+    // Rebuilds every effect using the shader, the replaced pipelines wait in the stack until no frame uses them.
+    pub fn reload(&mut self, device:&mut Device, shader:&Path, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        for (index, file) in self.shaders.iter().enumerate() {
+            if Path::new(file) != shader {
+                continue;
+            }
+            logger::various_log!("compute_pipeline",
+                (logger::Debug, "reloading {} compute pipeline", self.names[index])
+            );
+            let fresh = CPipeline::create(device, file, &self.images[index], self.workgroup_sizes[index])?;
+            let old = std::mem::replace(&mut self.pipelines[index], fresh);
+            let callback = Box::new(move |args:VkDestructorArguments|old.destruct(args));
+            destruction_stack.push(VkDynamicDestructor::new::<CPipeline>(object_name(&self.names[index]), VkDestructorType::Dev, callback));
+        }
+        Ok(())
+    }
     
-    effect_name.push_str("sky 2.0");
-    logger::various_log!("compute_pipeline",
-        (logger::Warn, "Instancing {} compute pipeline", effect_name)
-    );
-    let sky = CPipeline::create(device, ds_layout, constants::comp::SKY_SHADER).unwrap();
-    let push_constant_holder = ComputePushConstants([
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-        Vector4::new(0.0,0.0,0.0,0.0),
-    ]);
-    names.push(effect_name);
-    push_constants.push(push_constant_holder);
-    pipelines.push(sky);
+//----
+    // The set only lives for the frame, images are written in the order the manifest lists them.
+    pub fn allocate_descriptor(&self, device:&mut Device, allocator:&mut GDescriptorAllocator, index:usize, targets:&EffectTargets) -> Result<vk::DescriptorSet, AAError> {
+        let set = allocator.allocate(device, &self.pipelines[index].descriptor_layout)?;
+        let mut writer = DescriptorWriter::validated(self.pipelines[index].signature.clone());
+        for (binding, image) in self.images[index].iter().enumerate() {
+            let view = match image {
+                EffectImage::Draw => targets.draw,
+                EffectImage::Input => targets.input,
+                EffectImage::Lut => targets.lut,
+                EffectImage::Exposure => targets.exposure,
+                EffectImage::Bloom => targets.bloom,
+            };
+            writer.write_image(binding as u32, view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
+        }
+        writer.update_set(device, set)?;
+        Ok(set)
+    }
     
+//----
+    pub fn binds(&self, index:usize, image:EffectImage) -> bool {
+        self.images[index].contains(&image)
+    }
     
-    ComputeEffects{
-        names,
-        push_constants,
-        pipelines,
+//----
+    pub fn len(&self) -> usize {
+        self.names.len()
     }
 }
 
@@ -119,21 +188,50 @@ impl VkDestructor for ComputeEffects {
 
 
 impl CPipeline {
-    pub fn create(device:&mut Device, ds_layout:&DescriptorLayout, file:&str) -> Result<Self, AAError> {
+    pub fn create(device:&mut Device, file:&str, images:&[EffectImage], workgroup_size:[u32; 2]) -> Result<Self, AAError> {
         logger::create!("compute_pipeline");
         
-        let push_constant_description = vk::PushConstantRange::builder()
-            .size(vk_graphics::ComputePushConstants::size_u32())
-            .stage_flags(vk::ShaderStageFlags::COMPUTE);
+        let (compute_module, interface) = pipeline::create_shader_module(device, file)?;
+        
+        // Every effect gets the same push constants, shaders that use them have to declare the whole block.
+        let checked = (0..images.len() as u32).try_for_each(|binding|interface.check_binding(0, binding, vk::DescriptorType::STORAGE_IMAGE))
+            .and_then(|_|interface.check_local_size(workgroup_size))
+            .and_then(|_|{
+                match interface.push_constant_range() {
+                    Some(_) => interface.check_push_constants(size_of::<ComputePushConstants>()),
+                    None => Ok(()),
+                }
+            });
+        let built = checked.and_then(|_|Ok((interface.signature(0)?, interface.descriptor_layout(device, 0)?.0)));
+        let (signature, descriptor_layout) = match built {
+            Ok(built) => built,
+            Err(error) => {
+                unsafe{device.destroy_shader_module(compute_module, None)};
+                return Err(error);
+            }
+        };
+        
+        // Shaders without the block still get the range, every dispatch pushes the constants.
+        let push_constant_description = interface.push_constant_range().unwrap_or(vk::PushConstantRange{
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: vk_graphics::ComputePushConstants::size_u32(),
+        });
         
         
         let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(from_ref(ds_layout))
+            .set_layouts(from_ref(&descriptor_layout))
             .push_constant_ranges(from_ref(&push_constant_description));
         
-        let layout = unsafe{device.create_pipeline_layout(&layout_create_info, None)}?;
+        let layout = match unsafe{device.create_pipeline_layout(&layout_create_info, None)} {
+            Ok(layout) => layout,
+            Err(error) => {
+                unsafe{device.destroy_shader_module(compute_module, None)};
+                descriptor_layout.destruct(VkDestructorArguments::Dev(device));
+                return Err(error.into());
+            }
+        };
         
-        let compute_module = pipeline::create_shader_module(device, file)?;
         let compute_shader_stage = pipeline::create_shader_stage_create_info(vk::ShaderStageFlags::COMPUTE, compute_module);
         
         let compute_pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
@@ -145,8 +243,11 @@ impl CPipeline {
             Ok(mut pipeline) => {
                 pipeline.pop().expect(GRANTED)
             }
-            Err(_) => {
-                panic!("Error creating compute pipeline");
+            Err((_, error)) => {
+                unsafe{device.destroy_shader_module(compute_module, None)};
+                unsafe{device.destroy_pipeline_layout(layout, None)};
+                descriptor_layout.destruct(VkDestructorArguments::Dev(device));
+                return Err(error.into());
             }
         };
         
@@ -154,7 +255,9 @@ impl CPipeline {
         
         Ok(Self{
             layout,
-            pipeline
+            pipeline,
+            descriptor_layout,
+            signature,
         })
     }
     
@@ -168,6 +271,7 @@ impl VkDestructor for CPipeline {
         logger::destruct!("compute_pipeline");
         unsafe{device.destroy_pipeline_layout(self.layout, None)}
         unsafe{device.destroy_pipeline(self.pipeline, None)};
+        self.descriptor_layout.destruct(VkDestructorArguments::Dev(device));
     }
 }
 
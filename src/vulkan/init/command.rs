@@ -85,14 +85,18 @@ impl CommandControl {
         
         unsafe{device.end_command_buffer(self.s_u_buffer)}.expect(SIMPLE_VK_FN);
         
-        let submit_info = [
-            vk::SubmitInfo::builder()
-                .command_buffers(from_ref(&self.s_u_buffer))
-                .build(),
-        ];
+        let value = device.timeline.reserve();
+        let signal_info = device.timeline.signal_info(value, vk::PipelineStageFlags2::ALL_COMMANDS);
         
-        unsafe{device.queue_submit(device.queue_handles.graphics, &submit_info[..], vk::Fence::null())}.expect(SIMPLE_VK_FN);
-        unsafe{device.device_wait_idle()}.expect(SIMPLE_VK_FN);
+        let command_submit_info = vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(self.s_u_buffer);
+        
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(from_ref(&command_submit_info))
+            .signal_semaphore_infos(from_ref(&signal_info));
+        
+        unsafe{device.queue_submit2(device.queue_handles.graphics, from_ref(&submit_info), vk::Fence::null())}.expect(SIMPLE_VK_FN);
+        device.timeline.wait(device, value);
         
         Ok(holder)
     }
@@ -1,6 +1,7 @@
 use crate::AAError;
 use crate::macros;
 use crate::logger;
+use crate::constants;
 use crate::errors::messages::STANDARD_CONV;
 use crate::errors::messages::GRANTED;
 use crate::errors::messages::SIMPLE_VK_FN;
@@ -8,13 +9,13 @@ use crate::errors::messages::SIMPLE_VK_FN;
 use super::VkDestructor;
 use super::VkDestructorArguments;
 use super::Device;
-use super::Image;
 
 use std::slice::from_ref;
 use std::ops::Add;
 use std::ops::AddAssign;
 use std::ops::Mul;
 use std::ops::MulAssign;
+use std::ops::Range;
 
 use ash::vk;
 use arrayvec::ArrayVec;
@@ -44,13 +45,9 @@ pub struct DescriptorPoolAllocator {
     pool: vk::DescriptorPool,
 }
 
-pub fn init_descriptors(device:&mut Device, render_image:&Image) -> (GDescriptorAllocator, vk::DescriptorSet, DescriptorLayout, DescriptorLayout) {
+pub fn init_descriptors(device:&mut Device) -> (GDescriptorAllocator, DescriptorLayout) {
     //logger::init();
     
-    let mut ds_layout_builder = DescriptorLayoutBuilder::create();
-    ds_layout_builder.add_binding(0, vk::DescriptorType::STORAGE_IMAGE, 1);
-    let (background_image_descriptor_layout, _types_in_layout) = ds_layout_builder.build(device, vk::ShaderStageFlags::COMPUTE).unwrap();
-    
     let mut ds_layout_builder = DescriptorLayoutBuilder::create();
     ds_layout_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1);
     ds_layout_builder.add_binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1);
@@ -59,25 +56,13 @@ pub fn init_descriptors(device:&mut Device, render_image:&Image) -> (GDescriptor
     
     //types_in_layout += image_types_in_layout;
     
-    let mut gds_pool: GDescriptorAllocator = GDescriptorAllocator::create(device, image_types_in_layout).unwrap();
+    let gds_pool: GDescriptorAllocator = GDescriptorAllocator::create(device, image_types_in_layout).unwrap();
     
     //types_in_layout *= 10;//allocate 10 DS
     //let mut ds_pool = DescriptorPoolAllocator::create(device, types_in_layout).unwrap();
     //let background_image_ds = ds_pool.allocate(device, storage_descriptor_layout).unwrap();
     
-    let background_image_ds = gds_pool.allocate(device, &background_image_descriptor_layout).unwrap();
-    let mut writer = DescriptorWriter::default();
-    writer.write_image(0, render_image.view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
-    writer.update_set(device, background_image_ds);
-    
-    /*
-    let background_image_ds = gds_pool.allocate(device, &texture_descriptor_layout).unwrap();
-    let mut writer = DescriptorWriter::default();
-    writer.write_image(0, render_image.view, vk::Sampler::null(), vk::ImageLayout::GENERAL, vk::DescriptorType::STORAGE_IMAGE);
-    writer.update_set(device, background_image_ds);
-    */
-    
-    (gds_pool, background_image_ds, background_image_descriptor_layout, texture_descriptor_layout)
+    (gds_pool, texture_descriptor_layout)
     //(gds_pool, storage_descriptor_layout, background_image_ds, None)
 }
 
@@ -100,18 +85,40 @@ impl DescriptorLayoutBuilder {
         self.type_count.add_type_count(d_type, count);
     }
     
+    pub fn add_staged_binding(&mut self, binding:u32, d_type:vk::DescriptorType, count:u32, stages:vk::ShaderStageFlags) {
+        self.add_binding(binding, d_type, count);
+        self.bindings.last_mut().expect(GRANTED).stage_flags = stages;
+    }
+    
     pub fn reset(&mut self) {
         self.bindings.clear();
     }
     
     pub fn build(&mut self, device:&mut Device, shader_stage:vk::ShaderStageFlags) -> Result<(DescriptorLayout, DescriptorPoolCount), AAError> {
+        self.build_with_flags(device, shader_stage, vk::DescriptorBindingFlags::empty(), vk::DescriptorSetLayoutCreateFlags::empty())
+    }
+    
+    // Same binding flags for every binding, used by the descriptor indexing layouts.
+    pub fn build_with_flags(
+        &mut self, 
+        device: &mut Device, 
+        shader_stage: vk::ShaderStageFlags, 
+        binding_flags: vk::DescriptorBindingFlags, 
+        layout_flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<(DescriptorLayout, DescriptorPoolCount), AAError> {
         
         for binding in self.bindings.iter_mut(){
             binding.stage_flags |= shader_stage;
         }
         
+        let flags = vec![binding_flags; self.bindings.len()];
+        let mut flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(&flags[..]);
+        
         let create_info = vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(&self.bindings[..]);
+            .flags(layout_flags)
+            .bindings(&self.bindings[..])
+            .push_next(&mut flags_info);
         
         let holder_layout = DescriptorLayout{
             set_layout: unsafe{device.create_descriptor_set_layout(&create_info, None)}?,
@@ -124,8 +131,11 @@ impl DescriptorLayoutBuilder {
         Ok((holder_layout, holder_count))
     }
     
-    pub fn assemble(&mut self) -> DescriptorPoolCount {
-        self.type_count.clone()
+    // Call before build, build empties the builder.
+    pub fn signature(&self) -> LayoutSignature {
+        LayoutSignature{
+            bindings: self.bindings.iter().map(|binding|(binding.binding, binding.descriptor_type, binding.descriptor_count)).collect(),
+        }
     }
     
 }
@@ -340,11 +350,28 @@ impl VkDestructor for DescriptorPoolAllocator {
 
 
 
+// Counters are for the frame since the last clear_pools, the last_* fields keep the previous one for display.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DescriptorStats {
+    pub sets_allocated: u32,
+    pub pools_created: u32,
+    pub pools_filled: u32,
+    pub last_sets_allocated: u32,
+    pub last_pools_created: u32,
+    pub last_pools_filled: u32,
+    pub live_pools: u32,
+    pub sets_per_pool: u32,
+    pub resets: u64,
+}
+
+// Pools hold ratios × groups descriptors, each one is twice the previous up to UPPER_LIMIT_PER_POOL groups.
 pub struct GDescriptorAllocator<const UPPER_LIMIT_PER_POOL:u32 = 4092, const INITIAL_GROUPS:u32 = 256> {
     ratios: DescriptorPoolCount,
-    full_pools: Vec<vk::DescriptorPool>,
-    ready_pools: Vec<vk::DescriptorPool>,
+    // Every pool with the groups it was created for.
+    full_pools: Vec<(vk::DescriptorPool, u32)>,
+    ready_pools: Vec<(vk::DescriptorPool, u32)>,
     max_descriptors_groups: u32,
+    stats: DescriptorStats,
 }
 
 
@@ -363,54 +390,72 @@ impl<const UPPER_LIMIT_PER_POOL:u32, const INITIAL_GROUPS:u32> GDescriptorAlloca
             ratios,
             full_pools,
             ready_pools,
-            max_descriptors_groups: INITIAL_GROUPS,
+            max_descriptors_groups: INITIAL_GROUPS.min(UPPER_LIMIT_PER_POOL),
+            stats: DescriptorStats::default(),
         };
         let initial_pool = holder.create_pool(device);
         holder.ready_pools.push(initial_pool?);
         Ok(holder)
     }
     
-    fn get_pool(&mut self, device:&mut Device) -> Result<vk::DescriptorPool, AAError> {
-        if self.ready_pools.is_empty() {//TODO check this bug
-            if self.max_descriptors_groups < UPPER_LIMIT_PER_POOL {
-                self.max_descriptors_groups *=2;
-            } 
-            
-            let holder = self.create_pool(device);
-            holder
-        } else {
-            Ok(self.ready_pools.pop().unwrap())
+    fn get_pool(&mut self, device:&mut Device) -> Result<(vk::DescriptorPool, u32), AAError> {
+        match self.ready_pools.pop() {
+            Some(pool) => Ok(pool),
+            None => {
+                self.max_descriptors_groups = (self.max_descriptors_groups*2).min(UPPER_LIMIT_PER_POOL);
+                self.create_pool(device)
+            }
         }
     }
     
-    fn create_pool(&mut self, device:&mut Device) -> Result<vk::DescriptorPool, AAError> {
+    fn create_pool(&mut self, device:&mut Device) -> Result<(vk::DescriptorPool, u32), AAError> {
         let mut ratios = ArrayVec::new();
-        let count = self.ratios.fill_pool_sizes_array_vec(&mut ratios, 1).expect(GRANTED);
+        self.ratios.fill_pool_sizes_array_vec(&mut ratios, self.max_descriptors_groups).expect(GRANTED);
         
         let descriptor_pool_ci = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(count)
+            .max_sets(self.max_descriptors_groups)
             .pool_sizes(&ratios[..]);
             
         
-        unsafe{device.create_descriptor_pool(&descriptor_pool_ci, None)}.map_err(|error|error.into())
+        let pool = unsafe{device.create_descriptor_pool(&descriptor_pool_ci, None)}?;
+        self.stats.pools_created += 1;
+        self.stats.live_pools += 1;
+        self.stats.sets_per_pool = self.max_descriptors_groups;
+        logger::various_log!("descriptors",
+            (logger::Debug, "descriptor pool {} created for {} sets", self.stats.live_pools, self.max_descriptors_groups)
+        );
+        Ok((pool, self.max_descriptors_groups))
     } 
     
+    pub fn stats(&self) -> DescriptorStats {
+        self.stats
+    }
+    
     pub fn clear_pools(&mut self, device:&Device) {
-        for pool in &self.ready_pools {
-            unsafe{device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())}.expect(SIMPLE_VK_FN);
-        }
-        for pool in self.full_pools.iter() {
-            unsafe{device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())}.expect(SIMPLE_VK_FN);
-            self.ready_pools.push(*pool);
+        let stats = &mut self.stats;
+        stats.last_sets_allocated = std::mem::take(&mut stats.sets_allocated);
+        stats.last_pools_created = std::mem::take(&mut stats.pools_created);
+        stats.last_pools_filled = std::mem::take(&mut stats.pools_filled);
+        stats.resets += 1;
+        
+        // Pools outgrown by a bigger one are destroyed instead of reset, the last one created always stays.
+        let pools:Vec<_> = self.ready_pools.drain(..).chain(self.full_pools.drain(..)).collect();
+        for (pool, groups) in pools {
+            if groups < self.max_descriptors_groups {
+                unsafe{device.destroy_descriptor_pool(pool, None)};
+                stats.live_pools -= 1;
+                continue;
+            }
+            unsafe{device.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())}.expect(SIMPLE_VK_FN);
+            self.ready_pools.push((pool, groups));
         }
-        self.full_pools.clear();
     }
     
     pub fn allocate(&mut self, device:&mut Device, layout:&vk::DescriptorSetLayout) -> Result<vk::DescriptorSet, AAError> {
         let mut pool_to_use = self.get_pool(device)?;
         
         let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(pool_to_use)
+            .descriptor_pool(pool_to_use.0)
             .set_layouts(from_ref(layout));
         
         let holder = unsafe{device.allocate_descriptor_sets(&descriptor_set_allocate_info)};
@@ -422,10 +467,11 @@ impl<const UPPER_LIMIT_PER_POOL:u32, const INITIAL_GROUPS:u32> GDescriptorAlloca
             }
             Err(retryable_error) if (retryable_error == vk::Result::ERROR_OUT_OF_POOL_MEMORY) || (retryable_error == vk::Result::ERROR_FRAGMENTED_POOL) => {
                 self.full_pools.push(pool_to_use);
+                self.stats.pools_filled += 1;
                 
                 pool_to_use = self.get_pool(device)?;
                 let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
-                    .descriptor_pool(pool_to_use)
+                    .descriptor_pool(pool_to_use.0)
                     .set_layouts(from_ref(layout));
                 
                 match unsafe{device.allocate_descriptor_sets(&descriptor_set_allocate_info)} {
@@ -433,14 +479,19 @@ impl<const UPPER_LIMIT_PER_POOL:u32, const INITIAL_GROUPS:u32> GDescriptorAlloca
                         let holder = descriptor_set.pop().expect(GRANTED);
                         holder
                     }
-                    Err(err) => {
-                        panic!("descriptors can't be created {:?}", err);
+                    Err(error) => {
+                        self.ready_pools.push(pool_to_use);
+                        return Err(AAError::DescriptorPoolTooSmall(error));
                     }
                 }
             }
-            Err(error) => {return Err(error.into());}
+            Err(error) => {
+                self.ready_pools.push(pool_to_use);
+                return Err(error.into());
+            }
         };
         self.ready_pools.push(pool_to_use);
+        self.stats.sets_allocated += 1;
         return Ok(set);
     }
     
@@ -450,79 +501,186 @@ impl VkDestructor for GDescriptorAllocator {
     fn destruct(self, mut args:VkDestructorArguments) {
         logger::destruct!("Descriptor Allocator");
         let device = args.unwrap_dev();
-        for pool in &self.ready_pools {
-            unsafe{device.destroy_descriptor_pool(*pool, None)};
-        }
-        for pool in self.full_pools.iter() {
+        for (pool, _) in self.ready_pools.iter().chain(self.full_pools.iter()) {
             unsafe{device.destroy_descriptor_pool(*pool, None)};
         }
     }
 }
 
 
+// Bindings a layout was built from, lets the writer check its writes before they reach the driver.
+#[derive(Debug, Clone, Default)]
+pub struct LayoutSignature {
+    bindings: Vec<(u32, vk::DescriptorType, u32)>,
+}
+
+#[derive(Debug, Clone)]
+enum WriteInfo {
+    Images(Range<usize>),
+    Buffers(Range<usize>),
+    TexelBuffers(Range<usize>),
+}
+
+#[derive(Debug, Clone)]
+struct PendingWrite {
+    binding: u32,
+    array_element: u32,
+    descriptor_type: vk::DescriptorType,
+    info: WriteInfo,
+}
+
+// Infos are kept by index and only turned into pointers in update_set, pushing more writes can move them around.
 #[derive(Debug, Default)]
 pub struct DescriptorWriter {
     image_infos: Vec<vk::DescriptorImageInfo>,
     buffer_infos: Vec<vk::DescriptorBufferInfo>,
-    writes: Vec<vk::WriteDescriptorSet>,
+    texel_views: Vec<vk::BufferView>,
+    writes: Vec<PendingWrite>,
+    signature: Option<LayoutSignature>,
+}
+
+impl LayoutSignature {
+    
+    pub fn binding(&self, binding:u32) -> Option<(vk::DescriptorType, u32)> {
+        self.bindings.iter().find(|held|held.0 == binding).map(|held|(held.1, held.2))
+    }
 }
 
 impl DescriptorWriter {
-    pub fn create() -> Result<Self, AAError> {
-        Ok(Self::default())
+    // Writes are checked against the layout when constants::DESCRIPTOR_VALIDATION is set.
+    pub fn validated(signature:LayoutSignature) -> Self {
+        Self{
+            signature: Some(signature),
+            ..Self::default()
+        }
     }
     
     
     pub fn write_buffer(&mut self, binding:u32, buffer:vk::Buffer, size:u64, offset:u64, descriptor_type:vk::DescriptorType) {
-        
         let descriptor_buffer_info = vk::DescriptorBufferInfo::builder()
             .buffer(buffer)
             .offset(offset)
             .range(size);
         
-        self.buffer_infos.push(descriptor_buffer_info.build());
-        
-        let write_ds = vk::WriteDescriptorSet::builder()
-            .dst_binding(binding)
-            .dst_set(vk::DescriptorSet::null())
-            .descriptor_type(descriptor_type)
-            .buffer_info(from_ref(&self.buffer_infos.last().expect(GRANTED))); //was inserted above so should be valid
-        
-        self.writes.push(*write_ds);
-        
+        self.write_buffer_array(binding, 0, from_ref(&descriptor_buffer_info), descriptor_type);
+    }
+    
+    // Uniform, storage and their dynamic variants, dynamic offsets are added to `offset` when the set is bound.
+    pub fn write_buffer_array(&mut self, binding:u32, array_element:u32, infos:&[vk::DescriptorBufferInfo], descriptor_type:vk::DescriptorType) {
+        let start = self.buffer_infos.len();
+        self.buffer_infos.extend_from_slice(infos);
+        self.push_write(binding, array_element, descriptor_type, WriteInfo::Buffers(start..self.buffer_infos.len()));
+    }
+    
+    // Range is the size of one element, the element is picked with the dynamic offset at bind time.
+    #[allow(dead_code)]
+    pub fn write_dynamic_buffer(&mut self, binding:u32, buffer:vk::Buffer, element_size:u64, descriptor_type:vk::DescriptorType) {
+        debug_assert!(matches!(descriptor_type, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC));
+        self.write_buffer(binding, buffer, element_size, 0, descriptor_type);
+    }
+    
+    #[allow(dead_code)]
+    pub fn write_texel_buffers(&mut self, binding:u32, array_element:u32, views:&[vk::BufferView], descriptor_type:vk::DescriptorType) {
+        debug_assert!(matches!(descriptor_type, vk::DescriptorType::UNIFORM_TEXEL_BUFFER | vk::DescriptorType::STORAGE_TEXEL_BUFFER));
+        let start = self.texel_views.len();
+        self.texel_views.extend_from_slice(views);
+        self.push_write(binding, array_element, descriptor_type, WriteInfo::TexelBuffers(start..self.texel_views.len()));
     }
     
     
     pub fn write_image(&mut self, binding:u32, image:vk::ImageView, sampler:vk::Sampler, layout:vk::ImageLayout, descriptor_type:vk::DescriptorType) {
-        
         let descriptor_image_info = vk::DescriptorImageInfo::builder()
             .sampler(sampler)
             .image_view(image)
             .image_layout(layout);
         
-        self.image_infos.push(descriptor_image_info.build());
-        
-        let write_ds = vk::WriteDescriptorSet::builder()
-            .dst_binding(binding)
-            .dst_set(vk::DescriptorSet::null())
-            .descriptor_type(descriptor_type)
-            .image_info(from_ref(&self.image_infos.last().expect(GRANTED))); //was inserted above so should be valid
-        
-        self.writes.push(*write_ds);
+        self.write_image_array(binding, 0, from_ref(&descriptor_image_info), descriptor_type);
+    }
+    
+    pub fn write_image_array(&mut self, binding:u32, array_element:u32, infos:&[vk::DescriptorImageInfo], descriptor_type:vk::DescriptorType) {
+        let start = self.image_infos.len();
+        self.image_infos.extend_from_slice(infos);
+        self.push_write(binding, array_element, descriptor_type, WriteInfo::Images(start..self.image_infos.len()));
+    }
+    
+    #[allow(dead_code)]
+    pub fn write_input_attachment(&mut self, binding:u32, image:vk::ImageView, layout:vk::ImageLayout) {
+        self.write_image(binding, image, vk::Sampler::null(), layout, vk::DescriptorType::INPUT_ATTACHMENT);
+    }
+    
+    fn push_write(&mut self, binding:u32, array_element:u32, descriptor_type:vk::DescriptorType, info:WriteInfo) {
+        self.writes.push(PendingWrite{
+            binding,
+            array_element,
+            descriptor_type,
+            info,
+        });
+    }
+    
+    // Reports every write that does not fit the binding it targets, always true without a signature.
+    pub fn validate(&self) -> bool {
+        let Some(signature) = self.signature.as_ref().filter(|_|constants::DESCRIPTOR_VALIDATION) else {
+            return true;
+        };
         
+        let mut valid = true;
+        for write in self.writes.iter() {
+            let count = write.info.len() as u32;
+            match signature.binding(write.binding) {
+                None => {
+                    logger::various_log!("descriptors",
+                        (logger::Error, "write to binding {} that is not in the layout", write.binding)
+                    );
+                    valid = false;
+                }
+                Some((descriptor_type, _)) if descriptor_type != write.descriptor_type => {
+                    logger::various_log!("descriptors",
+                        (logger::Error, "binding {} is {:?} but was written as {:?}", write.binding, descriptor_type, write.descriptor_type)
+                    );
+                    valid = false;
+                }
+                Some((_, descriptor_count)) if write.array_element + count > descriptor_count => {
+                    logger::various_log!("descriptors",
+                        (logger::Error, "elements {}..{} written to binding {} that holds {}", write.array_element, write.array_element + count, write.binding, descriptor_count)
+                    );
+                    valid = false;
+                }
+                Some(_) => {}
+            }
+        }
+        valid
     }
     
-    pub fn clear(&mut self) {
-        self.image_infos.clear();
-        self.buffer_infos.clear();
-        self.writes.clear();
+    // Nothing is written when a write does not fit, the reasons are logged by validate.
+    pub fn update_set(&mut self, device:&Device, set:vk::DescriptorSet) -> Result<(), AAError> {
+        if !self.validate() {
+            return Err(AAError::InvalidDescriptorWrites);
+        }
+        
+        let writes:Vec<vk::WriteDescriptorSet> = self.writes.iter().map(|write|{
+            let holder = vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(write.binding)
+                .dst_array_element(write.array_element)
+                .descriptor_type(write.descriptor_type);
+            match write.info.clone() {
+                WriteInfo::Images(range) => holder.image_info(&self.image_infos[range]).build(),
+                WriteInfo::Buffers(range) => holder.buffer_info(&self.buffer_infos[range]).build(),
+                WriteInfo::TexelBuffers(range) => holder.texel_buffer_view(&self.texel_views[range]).build(),
+            }
+        }).collect();
+        
+        unsafe{device.update_descriptor_sets(&writes, &[])};
+        Ok(())
     }
+}
+
+impl WriteInfo {
     
-    pub fn update_set(&mut self, device:&Device, set:vk::DescriptorSet) {
-        for write in self.writes.iter_mut() {
-            write.dst_set = set;
+    fn len(&self) -> usize {
+        match self {
+            WriteInfo::Images(range) | WriteInfo::Buffers(range) | WriteInfo::TexelBuffers(range) => range.len(),
         }
-        unsafe{device.update_descriptor_sets(&self.writes, &[])};
     }
 }
 
@@ -9,6 +9,7 @@ use super::VkDestructorArguments;
 use super::instance::Instance;
 use super::p_device::PDevice;
 use super::p_device::QueueFamilyIndices;
+use super::timeline::Timeline;
 
 
 use std::collections::HashSet;
@@ -20,6 +21,7 @@ use ash::vk;
 pub struct Device {
     device: ash::Device,
     pub queue_handles: QueueHandles,
+    pub timeline: Timeline,
 }
 macros::impl_deref!(Device, ash::Device, device);
 macros::impl_underlying!(Device, ash::Device, device);
@@ -67,7 +69,16 @@ impl Device {
             .buffer_device_address_multi_device(true);
         
         
-        let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::builder();
+        let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+            .runtime_descriptor_array(true)
+            .descriptor_binding_partially_bound(true)
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .shader_storage_buffer_array_non_uniform_indexing(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_storage_buffer_update_after_bind(true);
+        
+        let mut timeline_semaphore = vk::PhysicalDeviceTimelineSemaphoreFeatures::builder()
+            .timeline_semaphore(true);
         
         
         let device_create_info = vk::DeviceCreateInfo::builder()
@@ -77,7 +88,8 @@ impl Device {
             .push_next(&mut dynamic_rendering)
             .push_next(&mut synchronization2)
             .push_next(&mut buffer_device_address)
-            .push_next(&mut descriptor_indexing);
+            .push_next(&mut descriptor_indexing)
+            .push_next(&mut timeline_semaphore);
         
         
         /*
@@ -86,11 +98,13 @@ impl Device {
         
         let device = unsafe{instance.create_device(p_device.underlying(), &device_create_info, None)}?;
         let queue_handles = Self::get_queue_handles(&device, &p_device.queues);
+        let timeline = Timeline::create(&device)?;
         
         
         Ok(Self{
             device: device,
-            queue_handles: queue_handles
+            queue_handles: queue_handles,
+            timeline,
         })
     }
     
@@ -167,6 +181,7 @@ impl VkDestructor for Device {
     fn destruct(self, mut args:VkDestructorArguments) {
         logger::destruct!("device");
         args.unwrap_none();
+        self.timeline.destroy(&self.device);
         unsafe{self.device.destroy_device(None)};
     }
 }
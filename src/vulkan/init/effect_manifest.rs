@@ -0,0 +1,126 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::comp;
+
+use super::super::graphics::ComputePushConstants;
+use crate::graphics::ParameterDescription;
+use crate::graphics::ParameterSchema;
+
+use std::fs;
+use std::path::Path;
+
+use nalgebra::Vector4;
+use serde::Deserialize;
+
+// Images an effect can bind, each one goes to the storage image binding matching its position in the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectImage {
+    // The image the effect writes, the render image for backgrounds and the current image of the post chain.
+    Draw,
+    // What the previous post pass left, a pass that reads it writes to the other image of the chain.
+    Input,
+    // The color grading LUT, rgba8.
+    Lut,
+    // 1x1 rgba32f the auto-exposure writes, y holds its exposure in EV.
+    Exposure,
+    // First level of the bloom chain, half the render image, blurred from the input right before the pass.
+    Bloom,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EffectDescription {
+    pub name: String,
+    pub shader: String,
+    #[serde(default = "default_workgroup_size")]
+    pub workgroup_size: [u32; 2],
+    // Up to four raw vec4 rows of push constants, missing rows are zero.
+    #[serde(default)]
+    pub push_constants: Vec<[f32; 4]>,
+    // Named parameters laid over the rows, their defaults win over the raw values.
+    #[serde(default, rename = "parameter")]
+    pub schema: Vec<ParameterDescription>,
+    #[serde(default = "default_images")]
+    pub images: Vec<EffectImage>,
+    // Only the post chain skips disabled effects, it is their state when the manifest is first loaded.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+// Compute effects read from a TOML file with one `[[effect]]` table each, the "Background" window and the post chain both use one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EffectManifest {
+    #[serde(rename = "effect")]
+    pub effects: Vec<EffectDescription>,
+}
+
+fn default_workgroup_size() -> [u32; 2] {
+    [16, 16]
+}
+
+fn default_images() -> Vec<EffectImage> {
+    vec![EffectImage::Draw]
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl EffectManifest {
+    
+//----
+    pub fn load<P:AsRef<Path>>(path:P) -> Result<Self, AAError> {
+        let path = path.as_ref();
+        let name = path.to_string_lossy().to_string();
+        let invalid = |reason:String|AAError::EffectManifest(name.clone(), reason);
+        
+        let contents = fs::read_to_string(path).map_err(|error|invalid(error.to_string()))?;
+        let holder:Self = toml::from_str(&contents).map_err(|error|invalid(error.to_string()))?;
+        
+        if holder.effects.is_empty() {
+            return Err(invalid("no effects declared".to_string()));
+        }
+        for (index, effect) in holder.effects.iter().enumerate() {
+            if effect.name.is_empty() || effect.name.len() > comp::MAX_EFFECT_NAME {
+                return Err(invalid(format!("effect {} needs a name of 1 to {} bytes", index, comp::MAX_EFFECT_NAME)));
+            }
+            if holder.effects[..index].iter().any(|other|other.name == effect.name) {
+                return Err(invalid(format!("effect {:?} is declared twice", effect.name)));
+            }
+            if effect.push_constants.len() > 4 {
+                return Err(invalid(format!("{:?} has {} push constant rows, there are 4", effect.name, effect.push_constants.len())));
+            }
+            effect.parameters().map_err(|reason|invalid(format!("{:?}: {}", effect.name, reason)))?;
+            if effect.images.is_empty() || effect.images.len() > comp::MAX_EFFECT_IMAGES {
+                return Err(invalid(format!("{:?} must bind 1 to {} images", effect.name, comp::MAX_EFFECT_IMAGES)));
+            }
+            if effect.workgroup_size.contains(&0) {
+                return Err(invalid(format!("{:?} has an empty workgroup", effect.name)));
+            }
+        }
+        
+        logger::various_log!("effect_manifest",
+            (logger::Debug, "{:?}: {} effects", path, holder.effects.len())
+        );
+        Ok(holder)
+    }
+}
+
+impl EffectDescription {
+    
+//----
+    // Initial push constants and the schema the GUI edits them with.
+    pub fn parameters(&self) -> Result<(ComputePushConstants, ParameterSchema), String> {
+        let mut holder = ComputePushConstants::default();
+        for (row, values) in holder.iter_mut().zip(self.push_constants.iter()) {
+            *row = Vector4::from(*values);
+        }
+        let schema = match self.schema.is_empty() {
+            true => ParameterSchema::raw_rows(),
+            false => ParameterSchema::build(&self.schema, &mut holder)?,
+        };
+        Ok((holder, schema))
+    }
+}
@@ -25,7 +25,7 @@ pub struct GPipeline {
 }
 macros::impl_underlying!(GPipeline, vk::Pipeline, pipeline);
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub struct DispatchableGPipeline {
     pub layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
@@ -38,11 +38,13 @@ pub struct GPipelineBuilder {
     input_assembly: vk::PipelineInputAssemblyStateCreateInfo,
     rasterizer: vk::PipelineRasterizationStateCreateInfo,
     color_blend_attachment: vk::PipelineColorBlendAttachmentState,
+    // Attachments after the first one, never blended.
+    extra_blend_attachments: ArrayVec<vk::PipelineColorBlendAttachmentState, 3>,
     multisampling: vk::PipelineMultisampleStateCreateInfo,
     layout: Option<vk::PipelineLayout>,
     depth_stencil: vk::PipelineDepthStencilStateCreateInfo,
     rendering_ci: vk::PipelineRenderingCreateInfo,
-    color_attachment_format: vk::Format,
+    color_attachment_formats: ArrayVec<vk::Format, 4>,
     vertex_input_state:vk::PipelineVertexInputStateCreateInfo,
 }
 
@@ -54,8 +56,8 @@ pub fn init_pipeline(device:&mut Device, render_image:&Image, depth_image:&Image
         (logger::Warn, "Instancing simple triangle graphics pipeline")
     );
     
-    let vert_module = pipeline::create_shader_module(device, constants::graph::TRIANGLE_VERT).unwrap();
-    let frag_module = pipeline::create_shader_module(device, constants::graph::TRIANGLE_FRAG).unwrap();
+    let (vert_module, _) = pipeline::create_shader_module(device, constants::graph::TRIANGLE_VERT).unwrap();
+    let (frag_module, _) = pipeline::create_shader_module(device, constants::graph::TRIANGLE_FRAG).unwrap();
     
     let layout_ci = vk::PipelineLayoutCreateInfo::builder();
     
@@ -67,7 +69,7 @@ pub fn init_pipeline(device:&mut Device, render_image:&Image, depth_image:&Image
         .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .set_polygon_mode(vk::PolygonMode::FILL)
         .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-        .set_multisampling_none()
+        .set_multisampling(vk::SampleCountFlags::TYPE_1)
         .set_blending_disabled()
         .set_color_attachment_format(render_image.format)
         .set_depth_format(depth_image.format)
@@ -89,8 +91,8 @@ pub fn init_mesh_pipeline(device:&mut Device, render_image:&Image, depth_image:&
         (logger::Warn, "Instancing mesh pipeline")
     );
     
-    let vert_module = pipeline::create_shader_module(device, constants::graph::MESH_VERT).unwrap();
-    let frag_module = pipeline::create_shader_module(device, constants::graph::MESH_FRAG).unwrap();
+    let (vert_module, _) = pipeline::create_shader_module(device, constants::graph::MESH_VERT).unwrap();
+    let (frag_module, _) = pipeline::create_shader_module(device, constants::graph::MESH_FRAG).unwrap();
     
     
     let push_constant_description = vk::PushConstantRange::builder()
@@ -110,7 +112,7 @@ pub fn init_mesh_pipeline(device:&mut Device, render_image:&Image, depth_image:&
         .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .set_polygon_mode(vk::PolygonMode::FILL)
         .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-        .set_multisampling_none()
+        .set_multisampling(vk::SampleCountFlags::TYPE_1)
         .set_color_attachment_format(render_image.format)
         .set_depth_format(depth_image.format)
         .set_depthtest_enable()
@@ -173,10 +175,17 @@ impl GPipelineBuilder {
             .viewport_count(1)
             .scissor_count(1);
         
+        let mut blend_attachments = ArrayVec::<_, 4>::new();
+        blend_attachments.push(self.color_blend_attachment);
+        blend_attachments.extend(self.extra_blend_attachments.iter().copied());
         let color_blend_sci = vk::PipelineColorBlendStateCreateInfo::builder()
             .logic_op_enable(false)
             .logic_op(vk::LogicOp::COPY)
-            .attachments(from_ref(&self.color_blend_attachment));
+            .attachments(&blend_attachments[..]);
+        
+        // Pointed to here, the builder may have moved since the formats were set.
+        self.rendering_ci.p_color_attachment_formats = self.color_attachment_formats.as_ptr();
+        self.rendering_ci.color_attachment_count = self.color_attachment_formats.len() as u32;
         
         let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
         let dynamic_sci = vk::PipelineDynamicStateCreateInfo::builder()
@@ -240,13 +249,16 @@ impl GPipelineBuilder {
     }
     
 //----
-    pub fn set_multisampling_none(&mut self) -> &mut Self {
-        let Self{ multisampling, .. } = self;
-        multisampling.sample_shading_enable = vk::FALSE;
-        multisampling.rasterization_samples = vk::SampleCountFlags::TYPE_1;
-        multisampling.min_sample_shading = 1f32;
-        multisampling.alpha_to_coverage_enable = vk::FALSE;
-        multisampling.alpha_to_one_enable = vk::FALSE;
+    // Rasterizes `samples` coverage samples per pixel, shading still runs once per pixel.
+    pub fn set_multisampling(&mut self, samples:vk::SampleCountFlags) -> &mut Self {
+        self.multisampling = vk::PipelineMultisampleStateCreateInfo{
+            sample_shading_enable: vk::FALSE,
+            rasterization_samples: samples,
+            min_sample_shading: 1f32,
+            alpha_to_coverage_enable: vk::FALSE,
+            alpha_to_one_enable: vk::FALSE,
+            ..Default::default()
+        };
         self
     }
     
@@ -259,9 +271,22 @@ impl GPipelineBuilder {
     
 //----
     pub fn set_color_attachment_format(&mut self, format:vk::Format) -> &mut Self {
-        self.color_attachment_format = format;
-        self.rendering_ci.p_color_attachment_formats = &self.color_attachment_format as *const _;
-        self.rendering_ci.color_attachment_count = 1;
+        self.color_attachment_formats.clear();
+        self.color_attachment_formats.push(format);
+        self.extra_blend_attachments.clear();
+        self
+    }
+    
+//----
+    // Appends an unblended attachment after the color one, an empty `write_mask` leaves it untouched.
+    pub fn add_color_attachment(&mut self, format:vk::Format, write_mask:vk::ColorComponentFlags) -> &mut Self {
+        let blend_attachment = vk::PipelineColorBlendAttachmentState{
+            color_write_mask: write_mask,
+            blend_enable: vk::FALSE,
+            ..Default::default()
+        };
+        self.color_attachment_formats.push(format);
+        self.extra_blend_attachments.push(blend_attachment);
         self
     }
     
@@ -286,6 +311,7 @@ impl GPipelineBuilder {
         self
     }
 
+    // Depth is reversed, see DepthProjection, nearer surfaces have the greater value.
     pub fn set_depthtest_enable(&mut self) -> &mut Self {
         let Self{ depth_stencil, .. } = self;
         depth_stencil.depth_test_enable = vk::TRUE;
@@ -300,6 +326,25 @@ impl GPipelineBuilder {
         self
     }
     
+//----
+    // Tests against the depth of what was drawn before without writing over it.
+    pub fn set_depthtest_read_only(&mut self) -> &mut Self {
+        self.set_depthtest_enable();
+        self.depth_stencil.depth_write_enable = vk::FALSE;
+        self
+    }
+    
+//----
+    // Moves fragments towards the camera, positive factors win against the reversed depth test.
+    pub fn set_depth_bias(&mut self, constant_factor:f32, slope_factor:f32) -> &mut Self {
+        let Self{ rasterizer, .. } = self;
+        rasterizer.depth_bias_enable = vk::TRUE;
+        rasterizer.depth_bias_constant_factor = constant_factor;
+        rasterizer.depth_bias_slope_factor = slope_factor;
+        rasterizer.depth_bias_clamp = 0f32;
+        self
+    }
+    
 //----
     pub fn set_vertex_input_state(&mut self, input_binding:&[vk::VertexInputBindingDescription], vertex_attribute:&[vk::VertexInputAttributeDescription]) -> &mut Self {
         let tmp = vk::PipelineVertexInputStateCreateInfo::builder()
@@ -330,6 +375,21 @@ impl GPipelineBuilder {
         self
     }
     
+//----
+    // Sums every fragment into the color, alpha only records that something was drawn.
+    pub fn set_blending_accumulate(&mut self) -> &mut Self {
+        let Self{ color_blend_attachment, .. } = self;
+        color_blend_attachment.color_write_mask = vk::ColorComponentFlags::RGBA;
+        color_blend_attachment.blend_enable = vk::TRUE;
+        color_blend_attachment.src_color_blend_factor = vk::BlendFactor::ONE;
+        color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::ONE;
+        color_blend_attachment.color_blend_op = vk::BlendOp::ADD;
+        color_blend_attachment.src_alpha_blend_factor = vk::BlendFactor::ONE;
+        color_blend_attachment.dst_alpha_blend_factor = vk::BlendFactor::ONE;
+        color_blend_attachment.alpha_blend_op = vk::BlendOp::MAX;
+        self
+    }
+    
 //----
     pub fn set_blending_alphablend(&mut self) -> &mut Self {
         let Self{ color_blend_attachment, .. } = self;
@@ -3,20 +3,29 @@ use crate::macros;
 use crate::logger;
 use crate::errors::messages::GPU_FREE;
 use crate::errors::messages::CPU_ACCESIBLE;
+use crate::constants::post;
 
 use super::VkDestructor;
 use super::VkDestructorType;
 use super::VkDeferedDestructor;
 use super::VkDynamicDestructor;
 use super::VkDestructorArguments;
+use super::Registered;
+use super::ObjectName;
+use super::object_name;
 use super::Device;
 use super::Allocator;
+use super::AllocationPolicy;
+use super::SchemePolicy;
 use super::CommandControl;
 use super::memory;
 use super::Buffer;
+use super::LayoutTracker;
+use super::SubresourceState;
 
 use std::slice::from_ref;
 use std::mem::ManuallyDrop;
+use std::panic::Location;
 
 use ash::vk;
 use gpu_allocator::vulkan as gpu_vk;
@@ -28,6 +37,9 @@ pub struct Image {
     pub extent: vk::Extent3D,
     pub extent_2d: vk::Extent2D,
     pub format: vk::Format,
+    pub name: ObjectName,
+    pub layout: LayoutTracker,
+    created_at: &'static Location<'static>,
 }
 
 macros::impl_underlying!(Image, vk::Image, image);
@@ -38,6 +50,8 @@ pub struct ImageMetadata {
     pub format: vk::Format,
     usage: ash::vk::ImageUsageFlags,
     aspect_flags: ash::vk::ImageAspectFlags,
+    scheme: SchemePolicy,
+    samples: vk::SampleCountFlags,
 }
 
 pub const RENDER:ImageMetadata = {
@@ -50,6 +64,8 @@ pub const RENDER:ImageMetadata = {
         //IUF::TRANSFER_SRC | IUF::TRANSFER_DST
         //IUF::STORAGE      | IUF::COLOR_ATTACHMENT
         aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Auto,
+        samples: vk::SampleCountFlags::TYPE_1,
     }
 };
 
@@ -62,6 +78,22 @@ pub const DEPTH:ImageMetadata = {
         usage: IUF::from_raw(0x20),
         //IUF::DEPTH_STENCIL_ATTACHMENT
         aspect_flags: IAF::DEPTH,
+        scheme: SchemePolicy::Auto,
+        samples: vk::SampleCountFlags::TYPE_1,
+    }
+};
+
+pub const MOTION:ImageMetadata = {
+    use vk::ImageUsageFlags as IUF;
+    use vk::ImageAspectFlags as IAF;
+    ImageMetadata{
+        d_name: Some("MOTION IMAGE"),
+        format: vk::Format::R16G16_SFLOAT,
+        usage: IUF::from_raw(0x18),
+        //IUF::STORAGE      | IUF::COLOR_ATTACHMENT
+        aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Auto,
+        samples: vk::SampleCountFlags::TYPE_1,
     }
 };
 
@@ -74,6 +106,51 @@ pub const TEXTURE:ImageMetadata = {
         usage: IUF::from_raw(0x06),
         //IUF::TRANSFER_DST | IUF::SAMPLED
         aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Managed,
+        samples: vk::SampleCountFlags::TYPE_1,
+    }
+};
+
+pub const LUT:ImageMetadata = {
+    use vk::ImageUsageFlags as IUF;
+    use vk::ImageAspectFlags as IAF;
+    ImageMetadata{
+        d_name: Some("COLOR LUT"),
+        format: vk::Format::R8G8B8A8_UNORM,
+        usage: IUF::from_raw(0x0a),
+        //IUF::TRANSFER_DST | IUF::STORAGE
+        aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Managed,
+        samples: vk::SampleCountFlags::TYPE_1,
+    }
+};
+
+pub const EXPOSURE:ImageMetadata = {
+    use vk::ImageUsageFlags as IUF;
+    use vk::ImageAspectFlags as IAF;
+    ImageMetadata{
+        d_name: Some("EXPOSURE STATE"),
+        format: vk::Format::R32G32B32A32_SFLOAT,
+        usage: IUF::from_raw(0x0b),
+        //IUF::TRANSFER_SRC | IUF::TRANSFER_DST
+        //IUF::STORAGE
+        aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Auto,
+        samples: vk::SampleCountFlags::TYPE_1,
+    }
+};
+
+pub const BLOOM:ImageMetadata = {
+    use vk::ImageUsageFlags as IUF;
+    use vk::ImageAspectFlags as IAF;
+    ImageMetadata{
+        d_name: Some("BLOOM LEVEL"),
+        format: vk::Format::R16G16B16A16_SFLOAT,
+        usage: IUF::from_raw(0x08),
+        //IUF::STORAGE
+        aspect_flags: IAF::COLOR,
+        scheme: SchemePolicy::Auto,
+        samples: vk::SampleCountFlags::TYPE_1,
     }
 };
 
@@ -83,6 +160,22 @@ impl ImageMetadata {
         holder.d_name = Some(name);
         holder
     }
+
+    pub fn transient(format:vk::Format, usage:vk::ImageUsageFlags, aspect_flags:vk::ImageAspectFlags) -> Self {
+        Self{
+            d_name: None,
+            format,
+            usage,
+            aspect_flags,
+            scheme: SchemePolicy::Auto,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn multisampled(mut self, samples:vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
 }
 
 impl Image {
@@ -93,6 +186,7 @@ impl Image {
     }
     
 //----
+    #[track_caller]
     pub fn create(
         device: &mut Device,
         allocator: &mut Allocator,
@@ -115,13 +209,15 @@ impl Image {
         let format = metadata.format;
         let extent = extent;
         let extent_2d = Self::extent_3d_to_extent_2d(extent);
-        let create_info = Self::create_info(format, metadata.usage, extent);
+        let create_info = Self::create_info(format, metadata.usage, metadata.samples, extent);
         
         let image = unsafe{device.create_image(&create_info, None)}?;
-        let memory_requirements = unsafe{device.get_image_memory_requirements(image)};
-        
         
-        let allocation = allocator.allocate(name, memory_requirements, memory::GpuOnly);
+        let policy = AllocationPolicy{
+            location: memory::GpuOnly,
+            scheme: metadata.scheme,
+        };
+        let allocation = allocator.allocate_image(device, name, image, create_info.tiling, policy)?;
         
         unsafe{device.bind_image_memory(image, allocation.memory(), allocation.offset())}?;
         
@@ -133,11 +229,15 @@ impl Image {
             allocation: ManuallyDrop::new(allocation), 
             extent, 
             extent_2d, 
-            format
+            format,
+            name: object_name(name),
+            layout: LayoutTracker::new(metadata.aspect_flags, create_info.mip_levels, create_info.array_layers),
+            created_at: Location::caller(),
         })
     }
     
 //----
+    #[track_caller]
     pub fn create_texture(
         device: &mut Device,
         allocator: &mut Allocator,
@@ -146,6 +246,22 @@ impl Image {
         overwrite_name: Option<&str>,
         data: &[u32],
     ) -> Result<Self, AAError> {
+        Self::upload(device, allocator, cmd_ctrl, extent, TEXTURE, overwrite_name, data, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+    
+//----
+    // Creates the image and fills it with `data`, one u32 per texel, leaving it in `final_layout`.
+    #[track_caller]
+    pub fn upload(
+        device: &mut Device,
+        allocator: &mut Allocator,
+        cmd_ctrl: &mut CommandControl,
+        extent: vk::Extent3D,
+        metadata: ImageMetadata,
+        overwrite_name: Option<&str>,
+        data: &[u32],
+        final_layout: vk::ImageLayout,
+    ) -> Result<Self, AAError> {
         
         let upload_buffer_size = u64::from(extent.depth * extent.width * extent.height * 4);
         
@@ -155,11 +271,13 @@ impl Image {
             align.copy_from_slice(data);
         }
         
-        let holder = Self::create(device, allocator, extent, TEXTURE, overwrite_name)?;
+        let mut holder = Self::create(device, allocator, extent, metadata, overwrite_name)?;
         
         let copy_state = cmd_ctrl.run_su_buffer(device, &mut |device, cmd|{
             let image_handle = holder.underlying();
-            Self::transition_image(device, cmd, image_handle, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+            // A fresh image, the barrier below discards whatever it held.
+            holder.validate_layout(vk::ImageLayout::UNDEFINED);
+            holder.transition_to(device, cmd, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
             
             let subresource = vk::ImageSubresourceLayers::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
@@ -183,7 +301,7 @@ impl Image {
                 from_ref(&image_copy)
                 
             )};
-            Self::transition_image(device, cmd, image_handle, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            holder.transition_to(device, cmd, final_layout);
             Ok(())
         })?;
         
@@ -207,13 +325,14 @@ impl Image {
     fn create_info(
         format: vk::Format, 
         usage_flags: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
         extent: vk::Extent3D,
     ) -> vk::ImageCreateInfo {
         let mut holder = vk::ImageCreateInfo::default();
         holder.image_type = vk::ImageType::TYPE_2D;
         holder.mip_levels = 1;
         holder.array_layers = 1;
-        holder.samples = vk::SampleCountFlags::TYPE_1;
+        holder.samples = samples;
         holder.tiling = vk::ImageTiling::OPTIMAL;
         holder.usage = usage_flags;
         holder.format = format;
@@ -257,6 +376,8 @@ impl Image {
 
 //----
     pub fn copy_from_image(&mut self, device:&Device, cmd:vk::CommandBuffer, src:Image) {
+        src.validate_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        self.validate_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL);
         Self::raw_copy_image_to_image(device, cmd, src.image, src.extent, self.image, self.extent);
     }
     
@@ -298,41 +419,37 @@ impl Image {
 
     
 //----
-    pub fn transition_image(
-        device: &Device,
-        command_buffer: vk::CommandBuffer,
-        image: vk::Image,
-        old_layout: vk::ImageLayout,
-        new_layout: vk::ImageLayout,
-    ) {
-        //logger::transitioning_image(old_layout, new_layout);
-        
-        let image_aspect = match new_layout {
-            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL => {
-                vk::ImageAspectFlags::DEPTH
-            }
-            _ => {
-                vk::ImageAspectFlags::COLOR
-            }
-        };
-        
-        let subresource = Image::subresource_range(image_aspect);
-        
-        let image_barrier = vk::ImageMemoryBarrier2::builder()
-            .image(image)
-            .old_layout(old_layout)
-            .new_layout(new_layout)
-            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
-            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-            .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE|vk::AccessFlags2::MEMORY_READ)
-            .subresource_range(subresource);
-        
-        let dependency = ash::vk::DependencyInfo::builder()
-            .image_memory_barriers(from_ref(&image_barrier));
+    // Moves every subresource to the new layout, the old layout and the source scope come from the tracked state.
+    pub fn transition_to(&mut self, device:&Device, cmd:vk::CommandBuffer, new_layout:vk::ImageLayout) {
+        let range = self.layout.full_range();
+        self.transition_range_to(device, cmd, range, new_layout);
+    }
+    
+//----
+    pub fn transition_range_to(&mut self, device:&Device, cmd:vk::CommandBuffer, range:vk::ImageSubresourceRange, new_layout:vk::ImageLayout) {
+        let barriers = self.layout.transition(range, self.image, new_layout);
+        if barriers.is_empty() {
+            return;
+        }
         
-        let _ = unsafe{device.cmd_pipeline_barrier2(command_buffer, &dependency)};
+        let dependency = vk::DependencyInfo::builder()
+            .image_memory_barriers(&barriers);
         
+        unsafe{device.cmd_pipeline_barrier2(cmd, &dependency)};
+    }
+    
+//----
+    // Records a layout reached through a barrier the image did not emit itself.
+    #[allow(dead_code)]
+    pub fn assume_layout(&mut self, layout:vk::ImageLayout) {
+        let range = self.layout.full_range();
+        self.layout.set(range, SubresourceState::for_layout(layout));
+    }
+    
+//----
+    // In debug builds mismatches between the tracked and the claimed layout are reported.
+    pub fn validate_layout(&self, claimed:vk::ImageLayout) -> bool {
+        self.layout.validate(&self.name, self.layout.full_range(), claimed)
     }
     
 //----
@@ -362,18 +479,28 @@ impl VkDestructor for Image {
         let (device, allocator) = args.unwrap_dev_all();
         self.internal_destroy(device, allocator);
     }
+    
+    fn created_at(&self) -> Option<&'static Location<'static>> {
+        Some(self.created_at)
+    }
+}
+
+
+impl Registered for Image {
+    const DESTRUCTOR_TYPE:VkDestructorType = VkDestructorType::DevAll;
 }
 
 
 impl VkDeferedDestructor for Image {
     fn defered_destruct(&mut self) -> VkDynamicDestructor {
         let target = unsafe{self.unsafe_clone()};
+        let name = target.name;
         let callback = Box::new(move |mut args:VkDestructorArguments|{
             let target = target;
             let (device, allocator) = args.unwrap_dev_all();
             target.internal_destroy(device, allocator);
         });
-        (callback, VkDestructorType::DevAll)
+        VkDynamicDestructor::new::<Self>(name, VkDestructorType::DevAll, callback).created_at(Some(self.created_at))
     }
 }
 
@@ -410,3 +537,41 @@ pub fn init_textures(device:&mut Device, allocator:&mut Allocator, cmd_ctrl:&mut
     (white_texture, grey_texture, black_texture, error_texture)
 }
 
+// The grading LUT of the post chain, read from disk when there is one and the identity otherwise.
+pub fn init_color_lut(device:&mut Device, allocator:&mut Allocator, cmd_ctrl:&mut CommandControl) -> Result<Image, AAError> {
+    let size = post::LUT_SIZE;
+    let extent = vk::Extent3D{width:size*size, height:size, depth:1};
+    
+    let data = match ::image::io::Reader::open(post::LUT_FILE).map(|reader|reader.decode()) {
+        Ok(Ok(decoded)) if decoded.width() == extent.width && decoded.height() == extent.height => {
+            logger::various_log!("image",
+                (logger::Debug, "grading LUT from {:?}", post::LUT_FILE)
+            );
+            decoded.into_rgba8().pixels().map(|pixel|u32::from_le_bytes(pixel.0)).collect()
+        }
+        Ok(Ok(decoded)) => {
+            logger::various_log!("image",
+                (logger::Warn, "{:?} is {}x{}, a grading LUT has to be {}x{}, using the identity", post::LUT_FILE, decoded.width(), decoded.height(), extent.width, extent.height)
+            );
+            identity_lut(size)
+        }
+        Ok(Err(_)) => {
+            logger::various_log!("image",
+                (logger::Warn, "{:?} could not be decoded, using the identity", post::LUT_FILE)
+            );
+            identity_lut(size)
+        }
+        Err(_) => identity_lut(size),
+    };
+    
+    Image::upload(device, allocator, cmd_ctrl, extent, LUT, None, &data, vk::ImageLayout::GENERAL)
+}
+
+fn identity_lut(size:u32) -> Vec<u32> {
+    let channel = |value:u32|value * 255 / (size - 1);
+    (0..size*size*size).map(|index|{
+        let (x, green) = (index % (size*size), index / (size*size));
+        let (red, blue) = (x % size, x / size);
+        channel(red) | channel(green) << 8 | channel(blue) << 16 | 0xff << 24
+    }).collect()
+}
@@ -0,0 +1,186 @@
+use crate::logger;
+use crate::constants;
+
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubresourceState {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutTracker {
+    aspect: vk::ImageAspectFlags,
+    mip_levels: u32,
+    array_layers: u32,
+    // One state per mip and layer, mip major.
+    subresources: Vec<SubresourceState>,
+}
+
+impl SubresourceState {
+    
+//----
+    pub const UNDEFINED:Self = Self{
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags2::NONE,
+        access: vk::AccessFlags2::NONE,
+    };
+    
+//----
+    // The stage and access a layout is normally used with, this is what the next barrier has to wait on.
+    pub fn for_layout(layout:vk::ImageLayout) -> Self {
+        use vk::PipelineStageFlags2 as PSF;
+        use vk::AccessFlags2 as AF;
+        let (stage, access) = match layout {
+            vk::ImageLayout::UNDEFINED => (PSF::NONE, AF::NONE),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (PSF::COLOR_ATTACHMENT_OUTPUT, AF::COLOR_ATTACHMENT_READ | AF::COLOR_ATTACHMENT_WRITE),
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => {
+                (PSF::EARLY_FRAGMENT_TESTS | PSF::LATE_FRAGMENT_TESTS, AF::DEPTH_STENCIL_ATTACHMENT_READ | AF::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            }
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (PSF::FRAGMENT_SHADER | PSF::COMPUTE_SHADER, AF::SHADER_SAMPLED_READ),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (PSF::TRANSFER, AF::TRANSFER_READ),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL => (PSF::TRANSFER, AF::TRANSFER_WRITE),
+            vk::ImageLayout::PRESENT_SRC_KHR => (PSF::NONE, AF::NONE),
+            _ => (PSF::ALL_COMMANDS, AF::MEMORY_READ | AF::MEMORY_WRITE),
+        };
+        Self{
+            layout,
+            stage,
+            access,
+        }
+    }
+    
+//----
+    fn is_read_only(&self) -> bool {
+        use vk::AccessFlags2 as AF;
+        let writes = AF::MEMORY_WRITE | AF::SHADER_WRITE | AF::SHADER_STORAGE_WRITE | AF::COLOR_ATTACHMENT_WRITE | AF::DEPTH_STENCIL_ATTACHMENT_WRITE | AF::TRANSFER_WRITE | AF::HOST_WRITE;
+        !self.access.intersects(writes)
+    }
+}
+
+impl LayoutTracker {
+    
+//----
+    pub fn new(aspect:vk::ImageAspectFlags, mip_levels:u32, array_layers:u32) -> Self {
+        let count = (mip_levels * array_layers) as usize;
+        Self{
+            aspect,
+            mip_levels,
+            array_layers,
+            subresources: vec![SubresourceState::UNDEFINED; count],
+        }
+    }
+    
+//----
+    pub fn aspect(&self) -> vk::ImageAspectFlags {
+        self.aspect
+    }
+    
+//----
+    pub fn full_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange{
+            aspect_mask: self.aspect,
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers,
+        }
+    }
+    
+//----
+    pub fn state(&self, mip_level:u32, array_layer:u32) -> SubresourceState {
+        self.subresources[self.index(mip_level, array_layer)]
+    }
+    
+//----
+    // The state shared by every subresource, None while they are in different layouts.
+    pub fn uniform_state(&self) -> Option<SubresourceState> {
+        let first = *self.subresources.first()?;
+        self.subresources.iter().all(|state|*state == first).then_some(first)
+    }
+    
+//----
+    // Records a transition done outside of the tracker.
+    pub fn set(&mut self, range:vk::ImageSubresourceRange, state:SubresourceState) {
+        for (mip_level, array_layer) in self.iter_range(range) {
+            let index = self.index(mip_level, array_layer);
+            self.subresources[index] = state;
+        }
+    }
+    
+//----
+    // Builds the barriers that move the range to the new layout and updates the tracked state.
+    pub fn transition(&mut self, range:vk::ImageSubresourceRange, image:vk::Image, new_layout:vk::ImageLayout) -> Vec<vk::ImageMemoryBarrier2> {
+        let target = SubresourceState::for_layout(new_layout);
+        let mut holder = Vec::new();
+        
+        for (mip_level, array_layer) in self.iter_range(range) {
+            let index = self.index(mip_level, array_layer);
+            let current = self.subresources[index];
+            if current.layout == new_layout && current.is_read_only() && target.is_read_only() {
+                continue;
+            }
+            
+            holder.push(vk::ImageMemoryBarrier2::builder()
+                .image(image)
+                .old_layout(current.layout)
+                .new_layout(new_layout)
+                .src_stage_mask(current.stage)
+                .src_access_mask(current.access)
+                .dst_stage_mask(target.stage)
+                .dst_access_mask(target.access)
+                .subresource_range(vk::ImageSubresourceRange{
+                    aspect_mask: range.aspect_mask,
+                    base_mip_level: mip_level,
+                    level_count: 1,
+                    base_array_layer: array_layer,
+                    layer_count: 1,
+                })
+                .build()
+            );
+            self.subresources[index] = target;
+        }
+        holder
+    }
+    
+//----
+    // Reports every subresource in the range whose tracked layout is not the one the caller claims.
+    pub fn validate(&self, name:&str, range:vk::ImageSubresourceRange, claimed:vk::ImageLayout) -> bool {
+        if !constants::LAYOUT_VALIDATION {
+            return true;
+        }
+        
+        let mut valid = true;
+        for (mip_level, array_layer) in self.iter_range(range) {
+            let tracked = self.state(mip_level, array_layer).layout;
+            if tracked != claimed {
+                logger::various_log!("image",
+                    (logger::Error, "layout mismatch on {:?} mip {} layer {}: tracked {:?} claimed {:?}", name, mip_level, array_layer, tracked, claimed)
+                );
+                valid = false;
+            }
+        }
+        valid
+    }
+    
+//----
+    fn index(&self, mip_level:u32, array_layer:u32) -> usize {
+        (mip_level * self.array_layers + array_layer) as usize
+    }
+    
+//----
+    fn iter_range(&self, range:vk::ImageSubresourceRange) -> impl Iterator<Item=(u32, u32)> {
+        let level_end = match range.level_count {
+            vk::REMAINING_MIP_LEVELS => self.mip_levels,
+            count => (range.base_mip_level + count).min(self.mip_levels),
+        };
+        let layer_end = match range.layer_count {
+            vk::REMAINING_ARRAY_LAYERS => self.array_layers,
+            count => (range.base_array_layer + count).min(self.array_layers),
+        };
+        let base_array_layer = range.base_array_layer;
+        (range.base_mip_level..level_end).flat_map(move |mip_level|(base_array_layer..layer_end).map(move |array_layer|(mip_level, array_layer)))
+    }
+}
@@ -1,7 +1,6 @@
 use crate::AAError;
 use crate::macros;
 use crate::logger;
-use crate::errors::messages::GPU_ALLOCATION;
 use crate::errors::messages::SU_COMMAND_FAIL;
 
 use super::VkDestructor;
@@ -11,6 +10,11 @@ use super::PDevice;
 use super::Device;
 use super::CommandControl;
 use super::Buffer;
+use super::memory_stats;
+use super::memory_stats::MemoryStats;
+use super::memory_stats::MemoryReport;
+use super::memory_stats::DEVICE_BLOCK_SIZE;
+use super::memory_stats::HOST_BLOCK_SIZE;
 
 use std::mem::ManuallyDrop;
 use std::slice::from_ref;
@@ -20,8 +24,32 @@ use gpu_allocator::vulkan as gpu_vk;
 use gpu_allocator as gpu_all;
 
 pub use gpu_all::MemoryLocation::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemePolicy {
+    Managed,
+    // Always dedicated, whatever the driver reports.
+    #[allow(dead_code)]
+    Dedicated,
+    // Dedicated when the driver prefers or requires it, render targets included.
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationPolicy {
+    pub location: gpu_all::MemoryLocation,
+    pub scheme: SchemePolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DedicatedHint {
+    prefers: bool,
+    requires: bool,
+}
+
 pub struct Allocator {
     allocator:ManuallyDrop<gpu_vk::Allocator>,
+    stats: MemoryStats,
 }
 
 macros::impl_deref_mut!(Allocator, gpu_vk::Allocator, allocator);
@@ -42,13 +70,14 @@ impl Allocator {
             physical_device: p_device.underlying(),
             debug_settings: Default::default(),
             buffer_device_address: true,  // Ideally, check the BufferDeviceAddressFeatures struct.
-            allocation_sizes: Default::default(),
+            allocation_sizes: gpu_all::AllocationSizes::new(DEVICE_BLOCK_SIZE, HOST_BLOCK_SIZE),
         };
         
         let allocator = gpu_vk::Allocator::new(&create_info)?;
         
         Ok(Self{
             allocator: ManuallyDrop::new(allocator),
+            stats: MemoryStats::new(&p_device.memory_properties),
         })
     }
     
@@ -56,22 +85,89 @@ impl Allocator {
         &mut self,
         name: &str,
         requirements: vk::MemoryRequirements,
+        linear: bool,
+        scheme: gpu_vk::AllocationScheme,
         location: gpu_all::MemoryLocation,
-    ) -> gpu_vk::Allocation {
+    ) -> Result<gpu_vk::Allocation, AAError> {
         
         logger::various_log!("allocator", 
-            (logger::Trace, "Allocation name: {:?}", name),
+            (logger::Trace, "Allocation name: {:?} linear: {} scheme: {:?}", name, linear, scheme),
         );
         
         let alloc_info = gpu_vk::AllocationCreateDesc{
             name: name,
             requirements: requirements,
             location: location,
-            linear: true,
-            allocation_scheme: gpu_vk::AllocationScheme::GpuAllocatorManaged,
+            linear,
+            allocation_scheme: scheme,
+        };
+        
+        let allocation = self.allocator.allocate(&alloc_info)?;
+        self.stats.record_allocation(name, &requirements, location, &allocation);
+        Ok(allocation)
+    }
+    
+    pub fn allocate_buffer(
+        &mut self,
+        device: &Device,
+        name: &str,
+        buffer: vk::Buffer,
+        policy: AllocationPolicy,
+    ) -> Result<gpu_vk::Allocation, AAError> {
+        let info = vk::BufferMemoryRequirementsInfo2::builder()
+            .buffer(buffer);
+        let mut dedicated = vk::MemoryDedicatedRequirements::default();
+        let mut requirements = vk::MemoryRequirements2::builder()
+            .push_next(&mut dedicated);
+        unsafe{device.get_buffer_memory_requirements2(&info, &mut requirements)};
+        let requirements = requirements.memory_requirements;
+        
+        let dedicated_hint = DedicatedHint::from(dedicated);
+        let scheme = match policy.resolve(name, dedicated_hint) {
+            true => gpu_vk::AllocationScheme::DedicatedBuffer(buffer),
+            false => gpu_vk::AllocationScheme::GpuAllocatorManaged,
+        };
+        
+        self.allocate(name, requirements, true, scheme, policy.location)
+    }
+    
+    pub fn allocate_image(
+        &mut self,
+        device: &Device,
+        name: &str,
+        image: vk::Image,
+        tiling: vk::ImageTiling,
+        policy: AllocationPolicy,
+    ) -> Result<gpu_vk::Allocation, AAError> {
+        let info = vk::ImageMemoryRequirementsInfo2::builder()
+            .image(image);
+        let mut dedicated = vk::MemoryDedicatedRequirements::default();
+        let mut requirements = vk::MemoryRequirements2::builder()
+            .push_next(&mut dedicated);
+        unsafe{device.get_image_memory_requirements2(&info, &mut requirements)};
+        let requirements = requirements.memory_requirements;
+        
+        let dedicated_hint = DedicatedHint::from(dedicated);
+        let scheme = match policy.resolve(name, dedicated_hint) {
+            true => gpu_vk::AllocationScheme::DedicatedImage(image),
+            false => gpu_vk::AllocationScheme::GpuAllocatorManaged,
         };
         
-        self.allocator.allocate(&alloc_info).expect(GPU_ALLOCATION)
+        let linear = tiling == vk::ImageTiling::LINEAR;
+        self.allocate(name, requirements, linear, scheme, policy.location)
+    }
+    
+    pub fn free(&mut self, allocation:gpu_vk::Allocation) -> gpu_all::Result<()> {
+        self.stats.record_free(&allocation);
+        self.allocator.free(allocation)
+    }
+    
+    pub fn report(&self) -> MemoryReport {
+        self.stats.report()
+    }
+    
+    pub fn log_report(&self) {
+        self.stats.report().log();
     }
     
     pub fn into_inner(self) -> gpu_vk::Allocator {
@@ -80,10 +176,48 @@ impl Allocator {
 }
 
 
+impl AllocationPolicy {
+    pub const fn new(location:gpu_all::MemoryLocation) -> Self {
+        Self{
+            location,
+            scheme: SchemePolicy::Auto,
+        }
+    }
+    
+    fn resolve(&self, name:&str, hint:DedicatedHint) -> bool {
+        match (self.scheme, hint) {
+            (SchemePolicy::Dedicated, _) => true,
+            (SchemePolicy::Managed, DedicatedHint{requires:true, ..}) => {
+                logger::various_log!("allocator",
+                    (logger::Warn, "{:?} requires a dedicated allocation, overriding managed policy", name)
+                );
+                true
+            }
+            (SchemePolicy::Managed, _) => false,
+            (SchemePolicy::Auto, DedicatedHint{prefers, requires}) => prefers || requires,
+        }
+    }
+}
+
+impl From<vk::MemoryDedicatedRequirements> for DedicatedHint {
+    fn from(base:vk::MemoryDedicatedRequirements) -> Self {
+        Self{
+            prefers: base.prefers_dedicated_allocation == vk::TRUE,
+            requires: base.requires_dedicated_allocation == vk::TRUE,
+        }
+    }
+}
+
+
 impl VkDestructor for Allocator {
     fn destruct(mut self, mut args:VkDestructorArguments) {
         args.unwrap_dev();
         logger::destruct!("allocator");
+        for record in self.stats.live_allocations() {
+            logger::various_log!("allocator",
+                (logger::Error, "allocation {:?} of {} is still alive", record.name, memory_stats::format_bytes(record.size))
+            );
+        }
         unsafe{ManuallyDrop::drop(&mut self.allocator)};
     }
 }
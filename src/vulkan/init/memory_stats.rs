@@ -0,0 +1,297 @@
+use crate::logger;
+use crate::errors::messages::U32_TO_USIZE;
+
+use std::collections::HashMap;
+
+use ash::vk;
+use ash::vk::Handle;
+use gpu_allocator::vulkan as gpu_vk;
+use gpu_allocator as gpu_all;
+
+pub const DEVICE_BLOCK_SIZE:u64 = 256 * 1024 * 1024;
+pub const HOST_BLOCK_SIZE:u64 = 64 * 1024 * 1024;
+
+// AllocationSizes rounds other values to 4MiB steps between 4MiB and 256MiB, the blocks would stop matching these.
+const _:() = assert!(DEVICE_BLOCK_SIZE.is_multiple_of(4 << 20) && DEVICE_BLOCK_SIZE <= 256 << 20);
+const _:() = assert!(HOST_BLOCK_SIZE.is_multiple_of(4 << 20) && HOST_BLOCK_SIZE >= 4 << 20);
+
+#[derive(Debug, Clone)]
+pub struct AllocationRecord {
+    pub name: String,
+    pub size: u64,
+    pub offset: u64,
+    pub location: gpu_all::MemoryLocation,
+    pub heap_index: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockReport {
+    pub heap_index: usize,
+    pub size: u64,
+    pub used: u64,
+    pub largest_free: u64,
+    pub fragmentation: f32,
+    pub dedicated: bool,
+    pub allocations: Vec<AllocationRecord>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HeapReport {
+    pub size: u64,
+    pub device_local: bool,
+    pub reserved: u64,
+    pub used: u64,
+    pub peak: u64,
+    pub allocation_count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    pub heaps: Vec<HeapReport>,
+    pub blocks: Vec<BlockReport>,
+    pub total_used: u64,
+    pub total_reserved: u64,
+    pub peak_used: u64,
+    pub peak_allocation_count: usize,
+    pub allocation_count: usize,
+}
+
+#[derive(Debug, Default)]
+struct BlockData {
+    heap_index: usize,
+    size: u64,
+    dedicated: bool,
+    allocations: HashMap<u64, AllocationRecord>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStats {
+    memory_types: Vec<vk::MemoryType>,
+    heaps: Vec<vk::MemoryHeap>,
+    heap_used: Vec<u64>,
+    heap_peak: Vec<u64>,
+    blocks: HashMap<u64, BlockData>,
+    total_used: u64,
+    peak_used: u64,
+    allocation_count: usize,
+    peak_allocation_count: usize,
+}
+
+impl MemoryStats {
+    
+//----
+    pub fn new(memory_properties:&vk::PhysicalDeviceMemoryProperties) -> Self {
+        let type_count = usize::try_from(memory_properties.memory_type_count).expect(U32_TO_USIZE);
+        let heap_count = usize::try_from(memory_properties.memory_heap_count).expect(U32_TO_USIZE);
+        Self{
+            memory_types: memory_properties.memory_types[..type_count].to_vec(),
+            heaps: memory_properties.memory_heaps[..heap_count].to_vec(),
+            heap_used: vec![0; heap_count],
+            heap_peak: vec![0; heap_count],
+            ..Self::default()
+        }
+    }
+    
+//----
+    pub fn record_allocation(
+        &mut self,
+        name: &str,
+        requirements: &vk::MemoryRequirements,
+        location: gpu_all::MemoryLocation,
+        allocation: &gpu_vk::Allocation,
+    ) {
+        let heap_index = self.find_heap(requirements.memory_type_bits, allocation.memory_properties());
+        let block_key = unsafe{allocation.memory()}.as_raw();
+        let dedicated = allocation.is_dedicated();
+        
+        let block_size = reserved_block_size(requirements, allocation);
+        
+        let block = self.blocks.entry(block_key).or_insert_with(||BlockData{
+            heap_index,
+            size: block_size,
+            dedicated,
+            allocations: HashMap::new(),
+        });
+        
+        block.allocations.insert(allocation.offset(), AllocationRecord{
+            name: String::from(name),
+            size: allocation.size(),
+            offset: allocation.offset(),
+            location,
+            heap_index,
+        });
+        
+        self.total_used += allocation.size();
+        self.allocation_count += 1;
+        self.peak_used = self.peak_used.max(self.total_used);
+        self.peak_allocation_count = self.peak_allocation_count.max(self.allocation_count);
+        
+        if let Some(used) = self.heap_used.get_mut(heap_index) {
+            *used += allocation.size();
+            self.heap_peak[heap_index] = self.heap_peak[heap_index].max(*used);
+        }
+    }
+    
+//----
+    pub fn record_free(&mut self, allocation:&gpu_vk::Allocation) {
+        if allocation.is_null() {
+            return;
+        }
+        let block_key = unsafe{allocation.memory()}.as_raw();
+        
+        let Some(block) = self.blocks.get_mut(&block_key) else {
+            logger::various_log!("allocator",
+                (logger::Warn, "freeing untracked allocation of {} bytes", allocation.size())
+            );
+            return;
+        };
+        
+        let Some(record) = block.allocations.remove(&allocation.offset()) else {
+            logger::various_log!("allocator",
+                (logger::Warn, "freeing untracked allocation of {} bytes", allocation.size())
+            );
+            return;
+        };
+        
+        if block.allocations.is_empty() {
+            self.blocks.remove(&block_key);
+        }
+        
+        self.total_used -= record.size;
+        self.allocation_count -= 1;
+        if let Some(used) = self.heap_used.get_mut(record.heap_index) {
+            *used -= record.size;
+        }
+    }
+    
+//----
+    pub fn live_allocations(&self) -> impl Iterator<Item=&AllocationRecord> {
+        self.blocks.values().flat_map(|block|block.allocations.values())
+    }
+    
+//----
+    pub fn report(&self) -> MemoryReport {
+        let mut heaps:Vec<HeapReport> = self.heaps.iter().enumerate().map(|(index, heap)|{
+            HeapReport{
+                size: heap.size,
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                used: self.heap_used[index],
+                peak: self.heap_peak[index],
+                ..HeapReport::default()
+            }
+        }).collect();
+        
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        let mut total_reserved = 0;
+        
+        for block in self.blocks.values() {
+            let mut allocations:Vec<AllocationRecord> = block.allocations.values().cloned().collect();
+            allocations.sort_by_key(|record|record.offset);
+            
+            let used = allocations.iter().fold(0u64, |last, current|last+current.size);
+            let mut largest_free = 0u64;
+            let mut cursor = 0u64;
+            for record in allocations.iter() {
+                largest_free = largest_free.max(record.offset.saturating_sub(cursor));
+                cursor = cursor.max(record.offset+record.size);
+            }
+            largest_free = largest_free.max(block.size.saturating_sub(cursor));
+            
+            let free = block.size.saturating_sub(used);
+            let fragmentation = match free {
+                0 => 0.0,
+                free => 1.0 - (largest_free as f32 / free as f32),
+            };
+            
+            if let Some(heap) = heaps.get_mut(block.heap_index) {
+                heap.reserved += block.size;
+                heap.allocation_count += allocations.len();
+            }
+            total_reserved += block.size;
+            
+            blocks.push(BlockReport{
+                heap_index: block.heap_index,
+                size: block.size,
+                used,
+                largest_free,
+                fragmentation,
+                dedicated: block.dedicated,
+                allocations,
+            });
+        }
+        
+        blocks.sort_by_key(|block|(block.heap_index, std::cmp::Reverse(block.size)));
+        
+        MemoryReport{
+            heaps,
+            blocks,
+            total_used: self.total_used,
+            total_reserved,
+            peak_used: self.peak_used,
+            peak_allocation_count: self.peak_allocation_count,
+            allocation_count: self.allocation_count,
+        }
+    }
+    
+//----
+    fn find_heap(&self, type_bits:u32, flags:vk::MemoryPropertyFlags) -> usize {
+        self.memory_types.iter().enumerate()
+            .find(|(index, memory_type)|{
+                (1 << index) & type_bits != 0 && memory_type.property_flags == flags
+            })
+            .map(|(_index, memory_type)|memory_type.heap_index as usize)
+            .unwrap_or(0)
+    }
+}
+
+impl MemoryReport {
+    
+//----
+    pub fn log(&self) {
+        logger::various_log!("allocator",
+            (logger::Info, "MEMORY REPORT: {} used / {} reserved in {} allocations", format_bytes(self.total_used), format_bytes(self.total_reserved), self.allocation_count),
+            (logger::Info, "peak: {} in {} allocations", format_bytes(self.peak_used), self.peak_allocation_count),
+        );
+        for (index, heap) in self.heaps.iter().enumerate() {
+            logger::various_log!("allocator",
+                (logger::Info, "heap[{}] device_local:{} used:{} reserved:{} peak:{} size:{}", index, heap.device_local, format_bytes(heap.used), format_bytes(heap.reserved), format_bytes(heap.peak), format_bytes(heap.size))
+            );
+        }
+        for block in self.blocks.iter() {
+            logger::various_log!("allocator",
+                (logger::Info, "\tblock heap[{}] {} / {} fragmentation:{:.2} dedicated:{}", block.heap_index, format_bytes(block.used), format_bytes(block.size), block.fragmentation, block.dedicated)
+            );
+            for record in block.allocations.iter() {
+                logger::various_log!("allocator",
+                    (logger::Info, "\t\t{:?} {} at {} {:?}", record.name, format_bytes(record.size), record.offset, record.location)
+                );
+            }
+        }
+    }
+}
+
+//----
+// gpu-allocator 0.25 has no report of its blocks, this follows MemoryType::allocate instead:
+// dedicated schemes and requests bigger than a block get a block of exactly their size,
+// the rest share blocks sized by whether the memory type is HOST_VISIBLE.
+fn reserved_block_size(requirements:&vk::MemoryRequirements, allocation:&gpu_vk::Allocation) -> u64 {
+    let shared_size = match allocation.memory_properties().contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+        true => HOST_BLOCK_SIZE,
+        false => DEVICE_BLOCK_SIZE,
+    };
+    match allocation.is_dedicated() || requirements.size > shared_size {
+        true => requirements.size,
+        false => shared_size,
+    }
+}
+
+pub fn format_bytes(amount:u64) -> String {
+    const SUFFIX:[&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = amount as f64;
+    let mut index = 0;
+    while value >= 1024.0 && index < SUFFIX.len()-1 {
+        value /= 1024.0;
+        index += 1;
+    }
+    format!("{:.2} {}", value, SUFFIX[index])
+}
@@ -3,9 +3,17 @@ use super::VkDestructorType;
 use super::VkDeferedDestructor;
 use super::VkDynamicDestructor;
 use super::VkDestructorArguments;
+use super::Registered;
+use super::ObjectName;
+use super::object_name;
     
 pub mod memory;
 pub use memory::Allocator;
+pub use memory::AllocationPolicy;
+pub use memory::SchemePolicy;
+
+pub mod memory_stats;
+pub use memory_stats::MemoryReport;
 
 mod instance;
 pub use instance::*;
@@ -19,6 +27,8 @@ pub use p_device::*;
 mod device;
 pub use device::*;
 
+mod timeline;
+
 mod surface;
 pub use surface::*;
 
@@ -31,15 +41,28 @@ pub use command::*;
 pub mod image;
 pub use image::*;
 
+mod image_layout;
+pub use image_layout::LayoutTracker;
+pub use image_layout::SubresourceState;
+
 pub mod descriptors;
 pub use descriptors::*;
 
+mod bindless;
+pub use bindless::BindlessSet;
+pub use bindless::BindlessIndex;
+
 mod sampler;
 pub use sampler::*;
 
 pub mod c_pipeline;
 pub use c_pipeline::CPipeline;
 pub use c_pipeline::ComputeEffects;
+pub use c_pipeline::EffectTargets;
+
+mod effect_manifest;
+pub use effect_manifest::EffectManifest;
+pub use effect_manifest::EffectImage;
 
 pub mod g_pipeline;
 //pub use g_pipeline::GPipeline;
@@ -48,6 +71,14 @@ pub use g_pipeline::DispatchableGPipeline;
 
 pub mod pipeline;
 
+mod reflection;
+pub use reflection::ShaderReflection;
+
+pub mod shader_compiler;
+
 mod buffer;
 pub use buffer::Buffer;
 
+mod typed_buffer;
+pub use typed_buffer::TypedBuffer;
+
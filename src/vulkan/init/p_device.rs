@@ -18,6 +18,8 @@ pub struct PDevice {
     pub swapchain_details: SwapchainSupportDetails,
     pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub properties: vk::PhysicalDeviceProperties,
+    // Update after bind limits, the bindless arrays are checked against them.
+    pub descriptor_indexing: vk::PhysicalDeviceDescriptorIndexingProperties,
 }
 
 macros::impl_underlying!(PDevice, vk::PhysicalDevice, p_device);
@@ -87,6 +89,15 @@ impl PDevice {
                 (logger::Trace, "{:#?}", &memory_properties)
             );
             
+            let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::builder()
+                .push_next(&mut descriptor_indexing);
+            unsafe{instance.get_physical_device_properties2(best, &mut properties2)};
+            
+            logger::various_log!("p_device", 
+                (logger::Trace, "{:#?}", &descriptor_indexing)
+            );
+            
             Ok(Self{
                 p_device: best,
                 queues: queue,
@@ -94,6 +105,7 @@ impl PDevice {
                 swapchain_details: best_sc_details,
                 memory_properties: memory_properties,
                 properties: best_properties,
+                descriptor_indexing,
             })
         } else {
             Err(AAError::NoGPU)
@@ -213,15 +225,22 @@ impl PDevice {
         vk_features13:&vk::PhysicalDeviceVulkan13Features,
     ) -> Result<vk::PhysicalDeviceFeatures, ()> {
         if features.geometry_shader == vk::TRUE && 
-            features.fill_mode_non_solid == vk::TRUE &&
             vk_features12.buffer_device_address == vk::TRUE && 
             vk_features12.descriptor_indexing == vk::TRUE &&
+            vk_features12.timeline_semaphore == vk::TRUE &&
+            vk_features12.runtime_descriptor_array == vk::TRUE &&
+            vk_features12.descriptor_binding_partially_bound == vk::TRUE &&
+            vk_features12.shader_sampled_image_array_non_uniform_indexing == vk::TRUE &&
+            vk_features12.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE &&
+            vk_features12.descriptor_binding_sampled_image_update_after_bind == vk::TRUE &&
+            vk_features12.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE &&
             vk_features13.dynamic_rendering == vk::TRUE && 
             vk_features13.synchronization2 == vk::TRUE {
             
             let holder = vk::PhysicalDeviceFeatures::builder()
                 .sampler_anisotropy(true)
-                .fill_mode_non_solid(true)
+                // Only the wireframe debug views draw lines, they are left out without it.
+                .fill_mode_non_solid(features.fill_mode_non_solid == vk::TRUE)
                 .build();
             Ok(holder)
         } else {
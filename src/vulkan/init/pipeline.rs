@@ -1,23 +1,36 @@
-use crate::errors::messages::RESOURCE_FILE;
 use crate::AAError;
 use crate::constants;
 
 use super::Device;
+use super::ShaderReflection;
+use super::shader_compiler;
+use super::shader_compiler::Defines;
 
-use std::fs::File;
+use std::path::Path;
 
 use ash::vk;
 
 
-pub fn create_shader_module(device:&mut Device, file:&str) -> Result<vk::ShaderModule, AAError> {
-    let mut spv_file = File::open(file).unwrap();
-    let spv = ash::util::read_spv(&mut spv_file).expect(RESOURCE_FILE);
+// The module comes with the interface reflected from its SPIR-V.
+pub fn create_shader_module(device:&mut Device, file:&str) -> Result<(vk::ShaderModule, ShaderReflection), AAError> {
+    create_shader_variant(device, file, &[])
+}
+
+// Same source built with extra preprocessor defines, each variant is cached on its own.
+pub fn create_shader_variant(device:&mut Device, file:&str, defines:&Defines) -> Result<(vk::ShaderModule, ShaderReflection), AAError> {
+    let spv = shader_compiler::compile(Path::new(file), defines)?;
+    let reflection = ShaderReflection::parse(file, &spv)?;
     let create_info = vk::ShaderModuleCreateInfo::builder()
         .code(&spv);
-    unsafe{device.create_shader_module(&create_info, None)}.map_err(|err|err.into())
+    let module = unsafe{device.create_shader_module(&create_info, None)}?;
+    Ok((module, reflection))
     
 }
 
+pub fn reflect_shader(file:&str) -> Result<ShaderReflection, AAError> {
+    ShaderReflection::parse(file, &shader_compiler::compile(Path::new(file), &[])?)
+}
+
 pub fn create_shader_stage_create_info(stage:vk::ShaderStageFlags, module:vk::ShaderModule) -> vk::PipelineShaderStageCreateInfo {
     vk::PipelineShaderStageCreateInfo::builder()
         .stage(stage)
@@ -47,6 +60,22 @@ pub fn rendering_attachment_info(
     holder
 }
 
+// Multisampled color averaged into `resolve_view` when rendering ends, its samples are dropped after that.
+pub fn resolving_attachment_info(
+    view: vk::ImageView,
+    resolve_view: vk::ImageView,
+    clear_value: Option<vk::ClearValue>,
+    layout: vk::ImageLayout,
+) -> vk::RenderingAttachmentInfo {
+    let mut holder = rendering_attachment_info(view, clear_value, layout);
+    holder.store_op = vk::AttachmentStoreOp::DONT_CARE;
+    holder.resolve_mode = vk::ResolveModeFlags::AVERAGE;
+    holder.resolve_image_view = resolve_view;
+    holder.resolve_image_layout = layout;
+    holder
+}
+
+// Cleared to 0, the far end of the reversed depth.
 pub fn depth_attachment_info(
     view: vk::ImageView,
     layout: vk::ImageLayout,
@@ -62,13 +91,13 @@ pub fn depth_attachment_info(
 
 pub fn rendering_info(
     extent: vk::Extent2D,
-    color_attachment: &vk::RenderingAttachmentInfo,
+    color_attachments: &[vk::RenderingAttachmentInfo],
     depth_attachment: Option<&vk::RenderingAttachmentInfo>,
 ) -> vk::RenderingInfo {
     let mut holder = vk::RenderingInfo::builder()
         .render_area(vk::Rect2D::from(extent))
         .layer_count(1)
-        .color_attachments(std::slice::from_ref(color_attachment));
+        .color_attachments(color_attachments);
     match depth_attachment {
         Some(att) => {
             holder = holder.depth_attachment(att);
@@ -0,0 +1,665 @@
+use crate::AAError;
+use crate::logger;
+
+use super::Device;
+use super::DescriptorLayout;
+use super::DescriptorLayoutBuilder;
+use super::DescriptorPoolCount;
+use super::LayoutSignature;
+
+use std::collections::HashMap;
+
+use ash::vk;
+
+const MAGIC:u32 = 0x0723_0203;
+const HEADER_WORDS:usize = 5;
+
+mod op {
+    pub const NAME:u32 = 5;
+    pub const ENTRY_POINT:u32 = 15;
+    pub const EXECUTION_MODE:u32 = 16;
+    pub const TYPE_INT:u32 = 21;
+    pub const TYPE_FLOAT:u32 = 22;
+    pub const TYPE_VECTOR:u32 = 23;
+    pub const TYPE_MATRIX:u32 = 24;
+    pub const TYPE_IMAGE:u32 = 25;
+    pub const TYPE_SAMPLER:u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE:u32 = 27;
+    pub const TYPE_ARRAY:u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY:u32 = 29;
+    pub const TYPE_STRUCT:u32 = 30;
+    pub const TYPE_POINTER:u32 = 32;
+    pub const CONSTANT:u32 = 43;
+    pub const VARIABLE:u32 = 59;
+    pub const DECORATE:u32 = 71;
+    pub const MEMBER_DECORATE:u32 = 72;
+}
+
+mod decoration {
+    pub const BUFFER_BLOCK:u32 = 3;
+    pub const ARRAY_STRIDE:u32 = 6;
+    pub const MATRIX_STRIDE:u32 = 7;
+    pub const BINDING:u32 = 33;
+    pub const DESCRIPTOR_SET:u32 = 34;
+    pub const OFFSET:u32 = 35;
+}
+
+mod execution_mode {
+    pub const LOCAL_SIZE:u32 = 17;
+}
+
+mod storage {
+    pub const UNIFORM_CONSTANT:u32 = 0;
+    pub const UNIFORM:u32 = 2;
+    pub const PUSH_CONSTANT:u32 = 9;
+    pub const STORAGE_BUFFER:u32 = 12;
+}
+
+#[derive(Debug, Clone)]
+enum SpvType {
+    Scalar{width:u32},
+    Vector{component:u32, count:u32},
+    Matrix{column:u32, count:u32},
+    Image{dim:u32, sampled:u32},
+    Sampler,
+    SampledImage,
+    Array{element:u32, length_id:u32},
+    RuntimeArray{element:u32},
+    Struct{members:Vec<u32>},
+    Pointer,
+}
+
+// A descriptor binding as the shader declares it, count 0 is an unbounded runtime array.
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub name: String,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    pub block_size: u32,
+    pub element_stride: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReflectedPushConstants {
+    pub size: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+// Interface of one or more shader stages, read straight from the SPIR-V words.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    name: String,
+    stages: vk::ShaderStageFlags,
+    bindings: Vec<ReflectedBinding>,
+    push_constants: Option<ReflectedPushConstants>,
+    local_size: Option<[u32; 3]>,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    sets: HashMap<u32, u32>,
+    bindings: HashMap<u32, u32>,
+    buffer_blocks: Vec<u32>,
+    array_strides: HashMap<u32, u32>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    matrix_strides: HashMap<(u32, u32), u32>,
+    constants: HashMap<u32, u32>,
+    types: HashMap<u32, SpvType>,
+    pointees: HashMap<u32, u32>,
+    variables: Vec<(u32, u32, u32)>,
+    stages: vk::ShaderStageFlags,
+    local_size: Option<[u32; 3]>,
+}
+
+impl ShaderReflection {
+    
+//----
+    pub fn parse(name:&str, code:&[u32]) -> Result<Self, AAError> {
+        let invalid = |reason:&'static str|AAError::InvalidSpirv(name.to_string(), reason);
+        
+        if code.len() < HEADER_WORDS || code[0] != MAGIC {
+            return Err(invalid("missing header"));
+        }
+        
+        let mut module = Module::default();
+        let mut cursor = HEADER_WORDS;
+        while cursor < code.len() {
+            let word_count = (code[cursor] >> 16) as usize;
+            let opcode = code[cursor] & 0xffff;
+            if word_count == 0 || cursor + word_count > code.len() {
+                return Err(invalid("truncated instruction"));
+            }
+            module.read_instruction(opcode, &code[cursor+1..cursor+word_count]).ok_or_else(||invalid("malformed instruction"))?;
+            cursor += word_count;
+        }
+        
+        let holder = module.reflect(name)?;
+        logger::various_log!("reflection",
+            (logger::Trace, "{} {:?}: {} bindings, push constants {:?}", holder.name, holder.stages, holder.bindings.len(), holder.push_constants)
+        );
+        Ok(holder)
+    }
+    
+//----
+    // Combines the stages of one pipeline, a binding seen by several stages has to agree on its type and count.
+    pub fn merge(mut self, other:&Self) -> Result<Self, AAError> {
+        for binding in other.bindings.iter() {
+            match self.bindings.iter_mut().find(|held|held.set == binding.set && held.binding == binding.binding) {
+                Some(held) => {
+                    if held.descriptor_type != binding.descriptor_type || held.count != binding.count {
+                        return Err(AAError::ShaderInterface(format!(
+                            "set {} binding {} is {:?}[{}] in {} but {:?}[{}] in {}",
+                            binding.set, binding.binding, held.descriptor_type, held.count, self.name, binding.descriptor_type, binding.count, other.name
+                        )));
+                    }
+                    held.stages |= binding.stages;
+                    held.block_size = held.block_size.max(binding.block_size);
+                }
+                None => self.bindings.push(binding.clone()),
+            }
+        }
+        
+        self.push_constants = match (self.push_constants, other.push_constants) {
+            (Some(held), Some(pushed)) => Some(ReflectedPushConstants{
+                size: held.size.max(pushed.size),
+                stages: held.stages | pushed.stages,
+            }),
+            (held, pushed) => held.or(pushed),
+        };
+        self.stages |= other.stages;
+        self.local_size = self.local_size.or(other.local_size);
+        self.name = format!("{}+{}", self.name, other.name);
+        Ok(self)
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn stages(&self) -> vk::ShaderStageFlags {
+        self.stages
+    }
+    
+//----
+    pub fn binding(&self, set:u32, binding:u32) -> Option<&ReflectedBinding> {
+        self.bindings.iter().find(|held|held.set == set && held.binding == binding)
+    }
+    
+//----
+    pub fn push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constants.map(|push|vk::PushConstantRange{
+            stage_flags: push.stages,
+            offset: 0,
+            size: push.size,
+        })
+    }
+    
+//----
+    // Builds the layout of one set, unbounded arrays are not handled here since they belong to the bindless set.
+    pub fn descriptor_layout(&self, device:&mut Device, set:u32) -> Result<(DescriptorLayout, DescriptorPoolCount), AAError> {
+        self.layout_builder(set)?.build(device, vk::ShaderStageFlags::empty())
+    }
+
+//----
+    pub fn signature(&self, set:u32) -> Result<LayoutSignature, AAError> {
+        Ok(self.layout_builder(set)?.signature())
+    }
+
+//----
+    fn layout_builder(&self, set:u32) -> Result<DescriptorLayoutBuilder, AAError> {
+        let mut builder = DescriptorLayoutBuilder::create();
+        for binding in self.bindings.iter().filter(|held|held.set == set) {
+            if binding.count == 0 {
+                return Err(AAError::ShaderInterface(format!(
+                    "{}: set {} binding {} ({}) is an unbounded array", self.name, set, binding.binding, binding.name
+                )));
+            }
+            builder.add_staged_binding(binding.binding, binding.descriptor_type, binding.count, binding.stages);
+        }
+        Ok(builder)
+    }
+    
+//----
+    pub fn check_binding(&self, set:u32, binding:u32, descriptor_type:vk::DescriptorType) -> Result<(), AAError> {
+        match self.binding(set, binding) {
+            Some(held) if held.descriptor_type == descriptor_type => Ok(()),
+            Some(held) => Err(AAError::ShaderInterface(format!(
+                "{}: set {} binding {} ({}) is {:?}, expected {:?}", self.name, set, binding, held.name, held.descriptor_type, descriptor_type
+            ))),
+            None => Err(AAError::ShaderInterface(format!(
+                "{}: set {} binding {} is not declared, expected {:?}", self.name, set, binding, descriptor_type
+            ))),
+        }
+    }
+    
+//----
+    // Size of the Rust struct backing a uniform or storage block.
+    pub fn check_block(&self, set:u32, binding:u32, size:usize) -> Result<(), AAError> {
+        let held = self.binding(set, binding).ok_or_else(||AAError::ShaderInterface(format!(
+            "{}: set {} binding {} is not declared", self.name, set, binding
+        )))?;
+        if held.block_size as usize != size {
+            return Err(AAError::ShaderInterface(format!(
+                "{}: block {} at set {} binding {} is {} bytes, the Rust side is {}", self.name, held.name, set, binding, held.block_size, size
+            )));
+        }
+        Ok(())
+    }
+    
+//----
+    // Size of the Rust struct backing the elements of a block ending in a runtime array.
+    pub fn check_element(&self, set:u32, binding:u32, size:usize) -> Result<(), AAError> {
+        let held = self.binding(set, binding).ok_or_else(||AAError::ShaderInterface(format!(
+            "{}: set {} binding {} is not declared", self.name, set, binding
+        )))?;
+        if held.element_stride as usize != size {
+            return Err(AAError::ShaderInterface(format!(
+                "{}: elements of {} at set {} binding {} are {} bytes, the Rust side is {}", self.name, held.name, set, binding, held.element_stride, size
+            )));
+        }
+        Ok(())
+    }
+    
+//----
+    // Only the x and y sizes, compute effects dispatch over the pixels of 2D images.
+    pub fn check_local_size(&self, size:[u32; 2]) -> Result<(), AAError> {
+        match self.local_size {
+            Some([x, y, _]) if [x, y] == size => Ok(()),
+            Some([x, y, _]) => Err(AAError::ShaderInterface(format!(
+                "{}: local size is {}x{}, expected {}x{}", self.name, x, y, size[0], size[1]
+            ))),
+            None => Err(AAError::ShaderInterface(format!(
+                "{}: no local size declared, expected {}x{}", self.name, size[0], size[1]
+            ))),
+        }
+    }
+    
+//----
+    pub fn check_push_constants(&self, size:usize) -> Result<(), AAError> {
+        match self.push_constants {
+            Some(push) if push.size as usize == size => Ok(()),
+            Some(push) => Err(AAError::ShaderInterface(format!(
+                "{}: push constant block is {} bytes, the Rust side is {}", self.name, push.size, size
+            ))),
+            None => Err(AAError::ShaderInterface(format!(
+                "{}: no push constant block, the Rust side is {} bytes", self.name, size
+            ))),
+        }
+    }
+}
+
+impl Module {
+    
+//----
+    fn read_instruction(&mut self, opcode:u32, operands:&[u32]) -> Option<()> {
+        match opcode {
+            op::NAME => {
+                self.names.insert(*operands.first()?, read_string(operands.get(1..)?));
+            }
+            op::ENTRY_POINT => {
+                self.stages |= match operands.first()? {
+                    0 => vk::ShaderStageFlags::VERTEX,
+                    1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                    2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                    3 => vk::ShaderStageFlags::GEOMETRY,
+                    4 => vk::ShaderStageFlags::FRAGMENT,
+                    5 => vk::ShaderStageFlags::COMPUTE,
+                    _ => vk::ShaderStageFlags::empty(),
+                };
+            }
+            op::EXECUTION_MODE if operands.get(1) == Some(&execution_mode::LOCAL_SIZE) => {
+                self.local_size = Some([*operands.get(2)?, *operands.get(3)?, *operands.get(4)?]);
+            }
+            op::TYPE_INT | op::TYPE_FLOAT => {
+                self.types.insert(*operands.first()?, SpvType::Scalar{width: *operands.get(1)?});
+            }
+            op::TYPE_VECTOR => {
+                self.types.insert(*operands.first()?, SpvType::Vector{component: *operands.get(1)?, count: *operands.get(2)?});
+            }
+            op::TYPE_MATRIX => {
+                self.types.insert(*operands.first()?, SpvType::Matrix{column: *operands.get(1)?, count: *operands.get(2)?});
+            }
+            op::TYPE_IMAGE => {
+                self.types.insert(*operands.first()?, SpvType::Image{dim: *operands.get(2)?, sampled: *operands.get(6)?});
+            }
+            op::TYPE_SAMPLER => {
+                self.types.insert(*operands.first()?, SpvType::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                self.types.insert(*operands.first()?, SpvType::SampledImage);
+            }
+            op::TYPE_ARRAY => {
+                self.types.insert(*operands.first()?, SpvType::Array{element: *operands.get(1)?, length_id: *operands.get(2)?});
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                self.types.insert(*operands.first()?, SpvType::RuntimeArray{element: *operands.get(1)?});
+            }
+            op::TYPE_STRUCT => {
+                self.types.insert(*operands.first()?, SpvType::Struct{members: operands.get(1..)?.to_vec()});
+            }
+            op::TYPE_POINTER => {
+                self.types.insert(*operands.first()?, SpvType::Pointer);
+                self.pointees.insert(*operands.first()?, *operands.get(2)?);
+            }
+            op::CONSTANT => {
+                self.constants.insert(*operands.get(1)?, *operands.get(2)?);
+            }
+            op::VARIABLE => {
+                self.variables.push((*operands.get(1)?, *operands.first()?, *operands.get(2)?));
+            }
+            op::DECORATE => {
+                let target = *operands.first()?;
+                match *operands.get(1)? {
+                    decoration::DESCRIPTOR_SET => {self.sets.insert(target, *operands.get(2)?);}
+                    decoration::BINDING => {self.bindings.insert(target, *operands.get(2)?);}
+                    decoration::ARRAY_STRIDE => {self.array_strides.insert(target, *operands.get(2)?);}
+                    decoration::BUFFER_BLOCK => self.buffer_blocks.push(target),
+                    _ => {}
+                }
+            }
+            op::MEMBER_DECORATE => {
+                let member = (*operands.first()?, *operands.get(1)?);
+                match *operands.get(2)? {
+                    decoration::OFFSET => {self.member_offsets.insert(member, *operands.get(3)?);}
+                    decoration::MATRIX_STRIDE => {self.matrix_strides.insert(member, *operands.get(3)?);}
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+    
+//----
+    fn reflect(&self, name:&str) -> Result<ShaderReflection, AAError> {
+        let mut bindings = Vec::new();
+        let mut push_constants = None;
+        
+        for &(id, pointer, storage_class) in self.variables.iter() {
+            let Some(&pointee) = self.pointees.get(&pointer) else {
+                continue;
+            };
+            
+            if storage_class == storage::PUSH_CONSTANT {
+                push_constants = Some(ReflectedPushConstants{
+                    size: self.size_of(pointee, None),
+                    stages: self.stages,
+                });
+                continue;
+            }
+            if ![storage::UNIFORM_CONSTANT, storage::UNIFORM, storage::STORAGE_BUFFER].contains(&storage_class) {
+                continue;
+            }
+            let (Some(&set), Some(&binding)) = (self.sets.get(&id), self.bindings.get(&id)) else {
+                continue;
+            };
+            
+            let (base, count) = match self.types.get(&pointee) {
+                Some(SpvType::Array{element, length_id}) => (*element, self.constants.get(length_id).copied().unwrap_or(1)),
+                Some(SpvType::RuntimeArray{element}) => (*element, 0),
+                _ => (pointee, 1),
+            };
+            
+            let descriptor_type = match self.types.get(&base) {
+                Some(SpvType::Sampler) => vk::DescriptorType::SAMPLER,
+                Some(SpvType::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                Some(SpvType::Image{dim: 5, sampled: 2}) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                Some(SpvType::Image{dim: 5, ..}) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                // Subpass data is declared with sampled 2 as well.
+                Some(SpvType::Image{dim: 6, ..}) => vk::DescriptorType::INPUT_ATTACHMENT,
+                Some(SpvType::Image{sampled: 2, ..}) => vk::DescriptorType::STORAGE_IMAGE,
+                Some(SpvType::Image{..}) => vk::DescriptorType::SAMPLED_IMAGE,
+                Some(SpvType::Struct{..}) if storage_class == storage::STORAGE_BUFFER || self.buffer_blocks.contains(&base) => vk::DescriptorType::STORAGE_BUFFER,
+                Some(SpvType::Struct{..}) => vk::DescriptorType::UNIFORM_BUFFER,
+                _ => return Err(AAError::InvalidSpirv(name.to_string(), "unsupported descriptor type")),
+            };
+            
+            bindings.push(ReflectedBinding{
+                set,
+                binding,
+                name: self.names.get(&id).or_else(||self.names.get(&base)).cloned().unwrap_or_default(),
+                descriptor_type,
+                count,
+                stages: self.stages,
+                block_size: self.size_of(base, None),
+                element_stride: self.trailing_stride(base),
+            });
+        }
+        
+        Ok(ShaderReflection{
+            name: name.to_string(),
+            stages: self.stages,
+            bindings,
+            push_constants,
+            local_size: self.local_size,
+        })
+    }
+    
+//----
+    // Byte size following the explicit offsets and strides, runtime arrays count as empty.
+    fn size_of(&self, id:u32, matrix_stride:Option<u32>) -> u32 {
+        match self.types.get(&id) {
+            Some(SpvType::Scalar{width}) => width / 8,
+            Some(SpvType::Vector{component, count}) => count * self.size_of(*component, None),
+            Some(SpvType::Matrix{column, count}) => count * matrix_stride.unwrap_or_else(||self.size_of(*column, None)),
+            Some(SpvType::Array{element, length_id}) => {
+                let length = self.constants.get(length_id).copied().unwrap_or(1);
+                length * self.array_strides.get(&id).copied().unwrap_or_else(||self.size_of(*element, None))
+            }
+            Some(SpvType::Struct{members}) => {
+                members.iter().enumerate().map(|(index, member)|{
+                    let key = (id, index as u32);
+                    let offset = self.member_offsets.get(&key).copied().unwrap_or(0);
+                    offset + self.size_of(*member, self.matrix_strides.get(&key).copied())
+                }).max().unwrap_or(0)
+            }
+            // Only buffer references show up inside blocks.
+            Some(SpvType::Pointer) => 8,
+            _ => 0,
+        }
+    }
+    
+//----
+    fn trailing_stride(&self, id:u32) -> u32 {
+        let Some(SpvType::Struct{members}) = self.types.get(&id) else {
+            return 0;
+        };
+        match members.last().and_then(|last|self.types.get(last).map(|held|(last, held))) {
+            Some((last, SpvType::RuntimeArray{element})) => {
+                self.array_strides.get(last).copied().unwrap_or_else(||self.size_of(*element, None))
+            }
+            _ => 0,
+        }
+    }
+}
+
+
+fn read_string(words:&[u32]) -> String {
+    let bytes:Vec<u8> = words.iter().flat_map(|word|word.to_le_bytes()).take_while(|byte|*byte != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const VARIABLE_BASE:u32 = 100;
+    
+    fn instruction(opcode:u32, operands:&[u32]) -> Vec<u32> {
+        let mut holder = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        holder.extend_from_slice(operands);
+        holder
+    }
+    
+    fn string(text:&str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(text.len() / 4 * 4 + 4, 0);
+        bytes.chunks(4).map(|chunk|u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+    }
+    
+    fn module(instructions:&[Vec<u32>]) -> Vec<u32> {
+        let mut holder = vec![MAGIC, 0x0001_0600, 0, 200, 0];
+        for instruction in instructions {
+            holder.extend_from_slice(instruction);
+        }
+        holder
+    }
+    
+    // A compute entry point with a 16x8 local size, the types every test builds on and the given declarations.
+    fn compute(declarations:&[Vec<u32>]) -> Vec<u32> {
+        let mut holder = vec![
+            instruction(op::ENTRY_POINT, &[5, 1].into_iter().chain(string("main")).collect::<Vec<_>>()),
+            instruction(op::EXECUTION_MODE, &[1, execution_mode::LOCAL_SIZE, 16, 8, 1]),
+            // 10 float, 11 vec4, 12 mat4, 13 uint, 14 vec3.
+            instruction(op::TYPE_FLOAT, &[10, 32]),
+            instruction(op::TYPE_VECTOR, &[11, 10, 4]),
+            instruction(op::TYPE_MATRIX, &[12, 11, 4]),
+            instruction(op::TYPE_INT, &[13, 32, 0]),
+            instruction(op::TYPE_VECTOR, &[14, 10, 3]),
+        ];
+        holder.extend_from_slice(declarations);
+        module(&holder)
+    }
+    
+    // Declares a variable `id` of type `pointee` at set 0, `binding` is also the id of its pointer type.
+    fn variable(id:u32, pointee:u32, storage_class:u32, binding:u32) -> Vec<Vec<u32>> {
+        let pointer = VARIABLE_BASE + 50 + binding;
+        vec![
+            instruction(op::DECORATE, &[id, decoration::DESCRIPTOR_SET, 0]),
+            instruction(op::DECORATE, &[id, decoration::BINDING, binding]),
+            instruction(op::TYPE_POINTER, &[pointer, storage_class, pointee]),
+            instruction(op::VARIABLE, &[pointer, id, storage_class]),
+        ]
+    }
+    
+    fn reflect(declarations:&[Vec<u32>]) -> ShaderReflection {
+        ShaderReflection::parse("test", &compute(declarations)).expect("the test module is valid")
+    }
+    
+    #[test]
+    fn block_size_follows_offsets_and_matrix_strides() {
+        let mut declarations = vec![
+            // struct { mat4; vec4; float; vec3; } with the vec3 packed after the float.
+            instruction(op::TYPE_STRUCT, &[20, 12, 11, 10, 14]),
+            instruction(op::MEMBER_DECORATE, &[20, 0, decoration::OFFSET, 0]),
+            instruction(op::MEMBER_DECORATE, &[20, 0, decoration::MATRIX_STRIDE, 16]),
+            instruction(op::MEMBER_DECORATE, &[20, 1, decoration::OFFSET, 64]),
+            instruction(op::MEMBER_DECORATE, &[20, 2, decoration::OFFSET, 80]),
+            instruction(op::MEMBER_DECORATE, &[20, 3, decoration::OFFSET, 84]),
+        ];
+        declarations.extend(variable(VARIABLE_BASE, 20, storage::UNIFORM, 0));
+        let reflection = reflect(&declarations);
+        
+        let binding = reflection.binding(0, 0).expect("binding 0 is declared");
+        assert_eq!(binding.descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(binding.count, 1);
+        assert_eq!(binding.block_size, 96);
+        assert!(reflection.check_block(0, 0, 96).is_ok());
+        assert!(reflection.check_block(0, 0, 80).is_err());
+    }
+    
+    #[test]
+    fn strided_arrays_and_runtime_arrays_in_storage_blocks() {
+        let mut declarations = vec![
+            instruction(op::CONSTANT, &[13, 30, 3]),
+            // float[3] with a 16 byte stride, then a runtime array of vec4 elements.
+            instruction(op::TYPE_ARRAY, &[21, 10, 30]),
+            instruction(op::DECORATE, &[21, decoration::ARRAY_STRIDE, 16]),
+            instruction(op::TYPE_RUNTIME_ARRAY, &[22, 11]),
+            instruction(op::DECORATE, &[22, decoration::ARRAY_STRIDE, 16]),
+            instruction(op::TYPE_STRUCT, &[23, 21, 22]),
+            instruction(op::MEMBER_DECORATE, &[23, 0, decoration::OFFSET, 0]),
+            instruction(op::MEMBER_DECORATE, &[23, 1, decoration::OFFSET, 48]),
+        ];
+        declarations.extend(variable(VARIABLE_BASE, 23, storage::STORAGE_BUFFER, 0));
+        let reflection = reflect(&declarations);
+        
+        let binding = reflection.binding(0, 0).expect("binding 0 is declared");
+        assert_eq!(binding.descriptor_type, vk::DescriptorType::STORAGE_BUFFER);
+        assert_eq!(binding.block_size, 48);
+        assert_eq!(binding.element_stride, 16);
+        assert!(reflection.check_element(0, 0, 16).is_ok());
+    }
+    
+    #[test]
+    fn descriptor_arrays_keep_their_count() {
+        let mut declarations = vec![
+            instruction(op::CONSTANT, &[13, 30, 8]),
+            instruction(op::TYPE_IMAGE, &[24, 10, 1, 0, 0, 0, 1, 0]),
+            instruction(op::TYPE_ARRAY, &[25, 24, 30]),
+            instruction(op::TYPE_SAMPLER, &[26]),
+            instruction(op::TYPE_RUNTIME_ARRAY, &[27, 26]),
+        ];
+        declarations.extend(variable(VARIABLE_BASE, 25, storage::UNIFORM_CONSTANT, 0));
+        declarations.extend(variable(VARIABLE_BASE + 1, 27, storage::UNIFORM_CONSTANT, 1));
+        let reflection = reflect(&declarations);
+        
+        let images = reflection.binding(0, 0).expect("binding 0 is declared");
+        assert_eq!(images.descriptor_type, vk::DescriptorType::SAMPLED_IMAGE);
+        assert_eq!(images.count, 8);
+        let samplers = reflection.binding(0, 1).expect("binding 1 is declared");
+        assert_eq!(samplers.descriptor_type, vk::DescriptorType::SAMPLER);
+        assert_eq!(samplers.count, 0);
+        // Unbounded arrays only belong in the bindless set.
+        assert!(reflection.signature(0).is_err());
+    }
+    
+    #[test]
+    fn image_descriptor_types() {
+        let mut declarations = vec![
+            // Dim 2D, buffer and subpass data, storage images are declared with sampled 2.
+            instruction(op::TYPE_IMAGE, &[31, 10, 1, 0, 0, 0, 2, 1]),
+            instruction(op::TYPE_IMAGE, &[32, 10, 5, 0, 0, 0, 2, 1]),
+            instruction(op::TYPE_IMAGE, &[33, 10, 5, 0, 0, 0, 1, 0]),
+            instruction(op::TYPE_IMAGE, &[34, 10, 6, 0, 0, 0, 2, 0]),
+            instruction(op::TYPE_IMAGE, &[35, 10, 1, 0, 0, 0, 1, 0]),
+            instruction(op::TYPE_SAMPLED_IMAGE, &[36, 35]),
+        ];
+        for (binding, image) in (31..=36).enumerate() {
+            declarations.extend(variable(VARIABLE_BASE + binding as u32, image, storage::UNIFORM_CONSTANT, binding as u32));
+        }
+        let reflection = reflect(&declarations);
+        
+        let expected = [
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            vk::DescriptorType::INPUT_ATTACHMENT,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        ];
+        for (binding, descriptor_type) in expected.into_iter().enumerate() {
+            assert!(reflection.check_binding(0, binding as u32, descriptor_type).is_ok(), "binding {} should be {:?}", binding, descriptor_type);
+        }
+    }
+    
+    #[test]
+    fn push_constants_and_local_size() {
+        let declarations = vec![
+            instruction(op::TYPE_STRUCT, &[40, 11, 11, 13]),
+            instruction(op::MEMBER_DECORATE, &[40, 0, decoration::OFFSET, 0]),
+            instruction(op::MEMBER_DECORATE, &[40, 1, decoration::OFFSET, 16]),
+            instruction(op::MEMBER_DECORATE, &[40, 2, decoration::OFFSET, 32]),
+            instruction(op::TYPE_POINTER, &[41, storage::PUSH_CONSTANT, 40]),
+            instruction(op::VARIABLE, &[41, 42, storage::PUSH_CONSTANT]),
+        ];
+        let reflection = reflect(&declarations);
+        
+        assert_eq!(reflection.stages(), vk::ShaderStageFlags::COMPUTE);
+        assert!(reflection.check_push_constants(36).is_ok());
+        assert!(reflection.check_local_size([16, 8]).is_ok());
+        assert!(reflection.check_local_size([8, 8]).is_err());
+    }
+    
+    #[test]
+    fn rejects_malformed_modules() {
+        assert!(ShaderReflection::parse("test", &[0; 5]).is_err());
+        
+        let mut truncated = compute(&[]);
+        truncated.push((4 << 16) | op::DECORATE);
+        assert!(ShaderReflection::parse("test", &truncated).is_err());
+    }
+}
@@ -5,6 +5,8 @@ use crate::macros;
 
 use super::VkDestructor;
 use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::Registered;
 use super::Device;
 
 use ash::vk;
@@ -37,3 +39,7 @@ impl VkDestructor for Sampler {
         unsafe{device.destroy_sampler(self.underlying(), None)}
     }
 }
+
+impl Registered for Sampler {
+    const DESTRUCTOR_TYPE:VkDestructorType = VkDestructorType::Dev;
+}
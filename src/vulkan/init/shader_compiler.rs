@@ -0,0 +1,223 @@
+use crate::AAError;
+use crate::logger;
+use crate::constants::shaders;
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Preprocessor definitions, `(name, value)` behaves like `#define name value`, an empty value only defines the name.
+pub type Defines<'a> = [(&'a str, &'a str)];
+
+const FNV_OFFSET:u64 = 0xcbf29ce484222325;
+const FNV_PRIME:u64 = 0x100000001b3;
+
+// Stable across runs and toolchains, unlike the std hashers, so it can name files on disk.
+struct SourceHash(u64);
+
+impl SourceHash {
+    
+//----
+    fn new() -> Self {
+        Self(FNV_OFFSET)
+    }
+    
+//----
+    fn write(&mut self, bytes:&[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+        // Separator so ("ab", "c") and ("a", "bc") do not collide.
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(FNV_PRIME);
+    }
+}
+
+
+//----
+// SPIR-V for a GLSL source, compiled only when the source, one of its includes or the defines changed since the last time.
+pub fn compile(source:&Path, defines:&Defines) -> Result<Vec<u32>, AAError> {
+    let name = source.to_string_lossy().to_string();
+    let files = dependencies(source)?;
+    
+    let mut hash = SourceHash::new();
+    hash.write(BACKEND.as_bytes());
+    for file in files.iter() {
+        let contents = fs::read(file).map_err(|error|AAError::ShaderPreprocess(name.clone(), format!("{:?}: {}", file, error)))?;
+        hash.write(file.to_string_lossy().as_bytes());
+        hash.write(&contents);
+    }
+    for (define, value) in defines {
+        hash.write(define.as_bytes());
+        hash.write(value.as_bytes());
+    }
+    
+    let file_name = source.file_name().map(|name|name.to_string_lossy().to_string()).unwrap_or_default();
+    let cached = Path::new(shaders::CACHE_DIR).join(format!("{}-{:016x}.spv", file_name, hash.0));
+    if let Ok(mut file) = fs::File::open(&cached) {
+        if let Ok(code) = ash::util::read_spv(&mut file) {
+            return Ok(code);
+        }
+    }
+    
+    logger::various_log!("shader_compiler",
+        (logger::Debug, "compiling {:?} with {:?}", source, defines)
+    );
+    
+    let code = compile_source(source, defines).map_err(|log|AAError::ShaderCompile(name, log))?;
+    
+    // A failed write only costs a recompilation next time.
+    let bytes:Vec<u8> = code.iter().flat_map(|word|word.to_le_bytes()).collect();
+    if let Err(error) = fs::create_dir_all(shaders::CACHE_DIR).and_then(|_|fs::write(&cached, bytes)) {
+        logger::various_log!("shader_compiler",
+            (logger::Warn, "could not cache {:?}: {}", cached, error)
+        );
+    }
+    Ok(code)
+}
+
+//----
+// The source followed by every file it includes, directly or not, in the order they are found.
+pub fn dependencies(source:&Path) -> Result<Vec<PathBuf>, AAError> {
+    let mut holder = Vec::new();
+    collect_includes(&source.to_string_lossy(), source, &mut Vec::new(), &mut holder)?;
+    Ok(holder)
+}
+
+//----
+// `#include "file"` is looked up next to the including file first, then in the shader source directory.
+pub fn resolve_include(requested:&str, includer:&Path) -> Option<PathBuf> {
+    let local = includer.parent().map(|dir|dir.join(requested));
+    let shared = Path::new(shaders::SOURCE_DIR).join(requested);
+    local.into_iter().chain(Some(shared)).find(|path|path.is_file())
+}
+
+//----
+fn collect_includes(root:&str, file:&Path, stack:&mut Vec<PathBuf>, found:&mut Vec<PathBuf>) -> Result<(), AAError> {
+    let error = |reason:String|AAError::ShaderPreprocess(root.to_string(), reason);
+    
+    if stack.iter().any(|open|open == file) {
+        return Err(error(format!("{:?} includes itself", file)));
+    }
+    let contents = fs::read_to_string(file).map_err(|io_error|error(format!("{:?}: {}", file, io_error)))?;
+    
+    if !found.iter().any(|known|known == file) {
+        found.push(file.to_path_buf());
+    }
+    stack.push(file.to_path_buf());
+    for (number, line) in contents.lines().enumerate() {
+        let Some(requested) = include_target(line) else {
+            continue;
+        };
+        let Some(include) = resolve_include(requested, file) else {
+            return Err(error(format!("{:?}:{} can't find {:?}", file, number+1, requested)));
+        };
+        collect_includes(root, &include, stack, found)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+//----
+fn include_target(line:&str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+    rest.strip_prefix('"').and_then(|name|name.strip_suffix('"'))
+        .or_else(||rest.strip_prefix('<').and_then(|name|name.strip_suffix('>')))
+}
+
+#[cfg(not(any(feature = "glslang", feature = "shaderc")))]
+compile_error!("shaders are compiled at runtime, enable the `glslang` or the `shaderc` feature");
+
+#[cfg(feature = "shaderc")]
+const BACKEND:&str = "shaderc";
+
+#[cfg(all(feature = "glslang", not(feature = "shaderc")))]
+const BACKEND:&str = "glslang";
+
+//----
+// Err holds the compiler log.
+#[cfg(feature = "shaderc")]
+fn compile_source(source:&Path, defines:&Defines) -> Result<Vec<u32>, String> {
+    let kind = match source.extension().and_then(|extension|extension.to_str()) {
+        Some("vert") => shaderc::ShaderKind::Vertex,
+        Some("frag") => shaderc::ShaderKind::Fragment,
+        Some("comp") => shaderc::ShaderKind::Compute,
+        Some("geom") => shaderc::ShaderKind::Geometry,
+        Some("tesc") => shaderc::ShaderKind::TessControl,
+        Some("tese") => shaderc::ShaderKind::TessEvaluation,
+        _ => return Err(format!("unknown shader stage for {:?}", source)),
+    };
+    let text = fs::read_to_string(source).map_err(|error|error.to_string())?;
+    
+    let compiler = shaderc::Compiler::new().ok_or("shaderc could not be initialized".to_string())?;
+    let mut options = shaderc::CompileOptions::new().ok_or("shaderc options could not be initialized".to_string())?;
+    options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_3 as u32);
+    for (define, value) in defines {
+        options.add_macro_definition(define, (!value.is_empty()).then_some(*value));
+    }
+    options.set_include_callback(|requested, _, includer, _|{
+        let include = resolve_include(requested, Path::new(includer)).ok_or(format!("can't find {:?}", requested))?;
+        let content = fs::read_to_string(&include).map_err(|error|error.to_string())?;
+        Ok(shaderc::ResolvedInclude{
+            resolved_name: include.to_string_lossy().to_string(),
+            content,
+        })
+    });
+    
+    let artifact = compiler.compile_into_spirv(&text, kind, &source.to_string_lossy(), "main", Some(&options))
+        .map_err(|error|error.to_string())?;
+    Ok(artifact.as_binary().to_vec())
+}
+
+// Includes for the glslang backend, the root source reaches it without a name so it is kept here.
+#[cfg(all(feature = "glslang", not(feature = "shaderc")))]
+struct Includes<'a>(&'a Path);
+
+#[cfg(all(feature = "glslang", not(feature = "shaderc")))]
+impl glslang::include::IncludeHandler for Includes<'_> {
+    fn include(&mut self, _:glslang::include::IncludeType, requested:&str, includer:&str, _:usize) -> Option<glslang::include::IncludeResult> {
+        let includer = match includer.is_empty() {
+            true => self.0,
+            false => Path::new(includer),
+        };
+        let include = resolve_include(requested, includer)?;
+        let data = fs::read_to_string(&include).ok()?;
+        Some(glslang::include::IncludeResult{
+            name: include.to_string_lossy().to_string(),
+            data,
+        })
+    }
+}
+
+//----
+#[cfg(all(feature = "glslang", not(feature = "shaderc")))]
+fn compile_source(source:&Path, defines:&Defines) -> Result<Vec<u32>, String> {
+    use glslang::ShaderStage;
+    
+    let stage = match source.extension().and_then(|extension|extension.to_str()) {
+        Some("vert") => ShaderStage::Vertex,
+        Some("frag") => ShaderStage::Fragment,
+        Some("comp") => ShaderStage::Compute,
+        Some("geom") => ShaderStage::Geometry,
+        Some("tesc") => ShaderStage::TesselationControl,
+        Some("tese") => ShaderStage::TesselationEvaluation,
+        _ => return Err(format!("unknown shader stage for {:?}", source)),
+    };
+    let text = fs::read_to_string(source).map_err(|error|error.to_string())?;
+    
+    let compiler = glslang::Compiler::acquire().ok_or("glslang could not be initialized".to_string())?;
+    let options = glslang::CompilerOptions{
+        target: glslang::Target::Vulkan{
+            version: glslang::VulkanVersion::Vulkan1_3,
+            spirv_version: glslang::SpirvVersion::SPIRV1_6,
+        },
+        ..Default::default()
+    };
+    let defines:Vec<(&str, Option<&str>)> = defines.iter().map(|(define, value)|(*define, (!value.is_empty()).then_some(*value))).collect();
+    let text = glslang::ShaderSource::from(text);
+    let mut includes = Includes(source);
+    
+    let input = glslang::ShaderInput::new(&text, stage, &options, Some(&defines[..]), Some(&mut includes)).map_err(|error|error.to_string())?;
+    compiler.create_shader(input).and_then(|shader|shader.compile()).map_err(|error|error.to_string())
+}
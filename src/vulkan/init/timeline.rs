@@ -0,0 +1,85 @@
+use crate::AAError;
+use crate::logger;
+use crate::errors::messages::SIMPLE_VK_FN;
+
+use std::cell::Cell;
+use std::slice::from_ref;
+
+use ash::vk;
+
+// A single timeline for the graphics queue, every submission signals the next value.
+pub struct Timeline {
+    semaphore: vk::Semaphore,
+    last_reserved: Cell<u64>,
+}
+
+impl Timeline {
+    
+//----
+    pub fn create(device:&ash::Device) -> Result<Self, AAError> {
+        logger::create!("timeline");
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+            
+        let create_info = vk::SemaphoreCreateInfo::builder()
+            .push_next(&mut type_info);
+            
+        let semaphore = unsafe{device.create_semaphore(&create_info, None)}?;
+        Ok(Self{
+            semaphore,
+            last_reserved: Cell::new(0),
+        })
+    }
+    
+//----
+    // Hands out the value the next submission has to signal.
+    pub fn reserve(&self) -> u64 {
+        let value = self.last_reserved.get() + 1;
+        self.last_reserved.set(value);
+        value
+    }
+    
+//----
+    // Value that will be signaled by the next submission, work recorded now is done once it is reached.
+    pub fn pending(&self) -> u64 {
+        self.last_reserved.get() + 1
+    }
+    
+//----
+    pub fn completed(&self, device:&ash::Device) -> u64 {
+        unsafe{device.get_semaphore_counter_value(self.semaphore)}.expect(SIMPLE_VK_FN)
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn reached(&self, device:&ash::Device, value:u64) -> bool {
+        self.completed(device) >= value
+    }
+    
+//----
+    pub fn wait(&self, device:&ash::Device, value:u64) {
+        if value == 0 {
+            return;
+        }
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(from_ref(&self.semaphore))
+            .values(from_ref(&value));
+        unsafe{device.wait_semaphores(&wait_info, u64::MAX)}.expect(SIMPLE_VK_FN);
+    }
+    
+//----
+    pub fn signal_info(&self, value:u64, stage:vk::PipelineStageFlags2) -> vk::SemaphoreSubmitInfo {
+        vk::SemaphoreSubmitInfo::builder()
+            .semaphore(self.semaphore)
+            .value(value)
+            .stage_mask(stage)
+            .build()
+    }
+    
+//----
+    pub fn destroy(&self, device:&ash::Device) {
+        logger::destruct!("timeline");
+        unsafe{device.destroy_semaphore(self.semaphore, None)};
+    }
+}
@@ -0,0 +1,231 @@
+use crate::AAError;
+use crate::logger;
+use crate::errors::messages::VK_CAST;
+
+use super::VkDestructor;
+use super::VkDeferedDestructor;
+use super::VkDynamicDestructor;
+use super::VkDestructorArguments;
+use super::Device;
+use super::Allocator;
+use super::AllocationPolicy;
+use super::CommandControl;
+use super::Buffer;
+use super::memory;
+
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::panic::Location;
+use std::slice::from_ref;
+
+use ash::vk;
+use gpu_allocator as gpu_all;
+
+#[derive(Debug)]
+pub struct TypedBuffer<T:Copy> {
+    buffer: Buffer,
+    len: usize,
+    usage: vk::BufferUsageFlags,
+    location: gpu_all::MemoryLocation,
+    _marker: PhantomData<T>,
+}
+
+impl<T:Copy> std::ops::Deref for TypedBuffer<T> {
+    type Target = Buffer;
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<T:Copy> TypedBuffer<T> {
+    
+//----
+    #[track_caller]
+    pub fn create(
+        device: &mut Device,
+        allocator: &mut Allocator,
+        name: Option<&str>,
+        len: usize,
+        usage: vk::BufferUsageFlags,
+        location: gpu_all::MemoryLocation,
+    ) -> Result<Self, AAError> {
+        Self::create_with_policy(device, allocator, name, len, usage, AllocationPolicy::new(location))
+    }
+    
+//----
+    #[track_caller]
+    pub fn create_with_policy(
+        device: &mut Device,
+        allocator: &mut Allocator,
+        name: Option<&str>,
+        len: usize,
+        usage: vk::BufferUsageFlags,
+        policy: AllocationPolicy,
+    ) -> Result<Self, AAError> {
+        if len == 0 || size_of::<T>() == 0 {
+            return Err(AAError::EmptyBuffer);
+        }
+        let size = Self::byte_size(len);
+        let buffer = Buffer::create_with_policy(device, allocator, name, size, usage, policy)?;
+        Ok(Self{
+            buffer,
+            len,
+            usage,
+            location: policy.location,
+            _marker: PhantomData,
+        })
+    }
+    
+//----
+    // Creates the buffer and fills it, going through a staging buffer when the memory is not mapped.
+    #[track_caller]
+    pub fn from_slice(
+        device: &mut Device,
+        allocator: &mut Allocator,
+        cmd_ctrl: &mut CommandControl,
+        name: Option<&str>,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        location: gpu_all::MemoryLocation,
+    ) -> Result<Self, AAError> {
+        let usage = match location {
+            memory::GpuOnly => usage | vk::BufferUsageFlags::TRANSFER_DST,
+            _ => usage,
+        };
+        let mut holder = Self::create(device, allocator, name, data.len(), usage, location)?;
+        
+        let upload = match holder.is_mapped() {
+            true => holder.write(data),
+            false => holder.upload(device, allocator, cmd_ctrl, data),
+        };
+        
+        match upload {
+            Ok(()) => Ok(holder),
+            Err(error) => {
+                holder.destruct(VkDestructorArguments::DevAll(device, allocator));
+                Err(error)
+            }
+        }
+    }
+    
+//----
+    pub fn write(&mut self, data:&[T]) -> Result<(), AAError> {
+        self.write_range(0, data)
+    }
+    
+//----
+    pub fn write_at(&mut self, index:usize, value:&T) -> Result<(), AAError> {
+        self.write_range(index, from_ref(value))
+    }
+    
+//----
+    pub fn upload(
+        &mut self,
+        device: &mut Device,
+        allocator: &mut Allocator,
+        cmd_ctrl: &mut CommandControl,
+        data: &[T],
+    ) -> Result<(), AAError> {
+        self.check_bounds(0, data.len())?;
+        if !self.usage.contains(vk::BufferUsageFlags::TRANSFER_DST) {
+            return Err(AAError::InvalidBufferUsage(vk::BufferUsageFlags::TRANSFER_DST));
+        }
+        
+        let mut staging = Self::create(device, allocator, Some("staging buffer"), data.len(), vk::BufferUsageFlags::TRANSFER_SRC, memory::CpuToGpu)?;
+        if let Err(error) = staging.write(data) {
+            staging.destruct(VkDestructorArguments::DevAll(device, allocator));
+            return Err(error);
+        }
+        
+        memory::copy_buffer_2_buffer(device, cmd_ctrl, &staging, 0, &mut self.buffer, 0, Self::byte_size(data.len()));
+        staging.destruct(VkDestructorArguments::DevAll(device, allocator));
+        Ok(())
+    }
+    
+//----
+    // Copies `count` elements from `index` once the timeline reaches the value of the submission that wrote the buffer.
+    pub fn read_back(&self, device:&Device, value:u64, index:usize, count:usize) -> Result<Vec<T>, AAError> {
+        if self.location != memory::GpuToCpu {
+            return Err(AAError::UnreadableBuffer(self.location));
+        }
+        // Nothing on the device could have written it otherwise.
+        if !self.usage.intersects(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::STORAGE_BUFFER) {
+            return Err(AAError::InvalidBufferUsage(vk::BufferUsageFlags::TRANSFER_DST));
+        }
+        self.check_bounds(index, count)?;
+        device.timeline.wait(device, value);
+        let ptr = self.buffer.allocation.mapped_ptr().ok_or(AAError::UnmappedBuffer)?;
+        
+        let mut holder:Vec<T> = Vec::with_capacity(count);
+        unsafe{
+            let src = ptr.as_ptr().byte_add(index * size_of::<T>()) as *const u8;
+            std::ptr::copy_nonoverlapping(src, holder.as_mut_ptr() as *mut u8, count * size_of::<T>());
+            holder.set_len(count);
+        }
+        Ok(holder)
+    }
+    
+//----
+    pub fn size(&self) -> u64 {
+        Self::byte_size(self.len)
+    }
+    
+//----
+    pub fn is_mapped(&self) -> bool {
+        self.buffer.allocation.mapped_ptr().is_some()
+    }
+    
+//----
+    pub fn into_inner(self) -> Buffer {
+        self.buffer
+    }
+    
+//----
+    fn write_range(&mut self, index:usize, data:&[T]) -> Result<(), AAError> {
+        self.check_bounds(index, data.len())?;
+        let ptr = self.buffer.allocation.mapped_ptr().ok_or(AAError::UnmappedBuffer)?;
+        unsafe{
+            let dst = ptr.as_ptr().byte_add(index * size_of::<T>()) as *mut u8;
+            std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, dst, std::mem::size_of_val(data));
+        }
+        Ok(())
+    }
+    
+//----
+    fn check_bounds(&self, index:usize, count:usize) -> Result<(), AAError> {
+        match index.checked_add(count) {
+            Some(end) if end <= self.len => Ok(()),
+            _ => {
+                logger::various_log!("buffer",
+                    (logger::Warn, "out of bounds access: {} elements at {} on buffer of {}", count, index, self.len)
+                );
+                Err(AAError::BufferOutOfBounds{index, count, len: self.len})
+            }
+        }
+    }
+    
+//----
+    fn byte_size(len:usize) -> u64 {
+        u64::try_from(len * size_of::<T>()).expect(VK_CAST)
+    }
+}
+
+
+impl<T:Copy> VkDestructor for TypedBuffer<T> {
+    fn destruct(self, args:VkDestructorArguments) {
+        self.buffer.destruct(args);
+    }
+    
+    fn created_at(&self) -> Option<&'static Location<'static>> {
+        self.buffer.created_at()
+    }
+}
+
+
+impl<T:Copy> VkDeferedDestructor for TypedBuffer<T> {
+    fn defered_destruct(&mut self) -> VkDynamicDestructor {
+        let mut holder = self.buffer.defered_destruct();
+        holder.info.type_name = std::any::type_name::<Self>();
+        holder
+    }
+}
@@ -1,33 +1,52 @@
 use super::*;
 
 use crate::logger;
+use crate::constants::bindless as bindless_slots;
+use crate::errors::messages::GRANTED;
 use crate::errors::messages::RESOURCE_REFERENCED;
 
 use super::VkDestructor;
 use super::VkDestructorArguments;
+use super::VkDestructorType;
+use super::VkDynamicDestructor;
+use super::object_name;
 
-use std::rc::Rc;
-
+// Instances share the dispatchable cells, a reload swaps the pipelines under every one of them.
 pub struct MetalicMaterial {
     opaque_pipeline: vk::Pipeline,
     transparent_pipeline: vk::Pipeline,
     pipeline_layout: vk::PipelineLayout,
-    descriptor_writer: DescriptorWriter,
-    descriptor_layout: DescriptorLayout,
     
-    dispatchable_opaque: Rc<DispatchableGPipeline>,
-    dispatchable_transparent: Rc<DispatchableGPipeline>,
+    dispatchable_opaque: Rc<Cell<DispatchableGPipeline>>,
+    dispatchable_transparent: Rc<Cell<DispatchableGPipeline>>,
+    
+    debug_view: DebugView,
+    // Variants of the pass for the debug view, both None while shading normally.
+    debug_main: Option<vk::Pipeline>,
+    debug_overlay: Option<vk::Pipeline>,
+    // Bindless image and sampler of the UV checker.
+    checker: [u32; 2],
+}
+
+// What the geometry pass draws with while a debug view is on.
+#[derive(Clone, Copy)]
+pub struct DebugPipelines {
+    // Replaces every material pipeline.
+    pub main: Option<DispatchableGPipeline>,
+    // Draws every object a second time over the material pass.
+    pub overlay: Option<DispatchableGPipeline>,
+    pub clears_background: bool,
 }
 
 
 impl MetalicMaterial {
     
+    // The instance takes a reference to the constants buffer, given back when the registry retires it.
     pub fn write_material(
-        &mut self, 
-        device: &mut Device, 
-        descriptor_allocator: &mut GDescriptorAllocator, 
+        &self, 
         material_pass: MaterialPass, 
-        material_resources: &MaterialResources
+        material_resources: &MaterialResources,
+        buffers: &mut Pool<Buffer>,
     ) -> Result<MaterialInstance, AAError> {
         
         let pipeline_holder = match material_pass {
@@ -42,89 +61,204 @@ impl MetalicMaterial {
             }
         };
         
-        
-        let descriptor_holder = descriptor_allocator.allocate(device, &self.descriptor_layout)?;
-        
-        
-        let Self{
-            descriptor_writer: writer,
-            ..
-        } = self;
-        
-        writer.clear();
-        writer.write_buffer(0, material_resources.buffer.underlying(), std::mem::size_of::<MaterialConstants>() as u64, material_resources.buffer_offset, vk::DescriptorType::UNIFORM_BUFFER);
-        writer.write_image(1, material_resources.color_image.view, material_resources.color_sampler.underlying(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
-        writer.write_image(2, material_resources.metal_image.view, material_resources.metal_sampler.underlying(), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
-        
-        
-        writer.update_set(device, descriptor_holder);
-        
-        
+        let buffer = buffers.acquire(material_resources.buffer).ok_or(AAError::StaleHandle("material constants buffer"))?;
         Ok(MaterialInstance{
             pipeline: pipeline_holder,
-            descriptor_set: descriptor_holder,
+            buffer,
+            constants_buffer: material_resources.constants_buffer,
+            constants_index: material_resources.constants_index,
             pass_type: material_pass,
         })
     }
     
-    pub fn build_pipelines(device:&mut Device, canvas:&Canvas, scene_descriptor:&DescriptorLayout) -> Result<Self, AAError> {
+    pub fn build_pipelines(device:&mut Device, canvas:&Canvas, scene_descriptor:&DescriptorLayout, bindless:&BindlessSet) -> Result<Self, AAError> {
         logger::create!("metalic_material");
-        let vert_module = pipeline::create_shader_module(device, constants::graph::MESH_VERT)?;
-        let frag_module = pipeline::create_shader_module(device, constants::graph::MESH_FRAG)?;
+        let (vert_module, vert_interface) = pipeline::create_shader_module(device, constants::graph::MESH_VERT)?;
+        let (frag_module, frag_interface) = pipeline::create_shader_module(device, constants::graph::MESH_FRAG)?;
         
-        let push_constant_description = vk::PushConstantRange::builder()
-            .size(GPUDrawPushConstants::size_u32())
-            .stage_flags(vk::ShaderStageFlags::VERTEX);
-        
-        let mut layout_builder = DescriptorLayoutBuilder::create();
-        layout_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1);
-        layout_builder.add_binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1);
-        layout_builder.add_binding(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1);
-        
-        let (material_descriptor_layout, material_types_in_layout) = layout_builder.build(device, vk::ShaderStageFlags::FRAGMENT|vk::ShaderStageFlags::VERTEX)?;
+        let push_constant_description = match vert_interface.merge(&frag_interface).and_then(|interface|Self::check_interface(&interface, bindless).map(|_|interface)) {
+            Ok(interface) => interface.push_constant_range().expect(GRANTED),
+            Err(error) => {
+                unsafe{device.destroy_shader_module(vert_module, None)};
+                unsafe{device.destroy_shader_module(frag_module, None)};
+                return Err(error);
+            }
+        };
         
-        let descriptor_layouts = [scene_descriptor.underlying(), material_descriptor_layout.underlying()];
+        let descriptor_layouts = [scene_descriptor.underlying(), bindless.layout().underlying()];
         
         let layout_ci = vk::PipelineLayoutCreateInfo::builder()
             .push_constant_ranges(from_ref(&push_constant_description))
             .set_layouts(&descriptor_layouts[..]);
         
-        let pipeline_layout = unsafe{device.create_pipeline_layout(&layout_ci, None)}.unwrap();
-        
-        let opaque_pipeline = Self::create_opaque_pipeline(device, canvas, pipeline_layout, vert_module, frag_module)?;
-        let transparent_pipeline = Self::create_transparent_pipeline(device, canvas, pipeline_layout, vert_module, frag_module)?;
-        
-        let descriptor_writer = DescriptorWriter::default();
-        
+        // The modules are only needed while the pipelines are built, whether that worked or not.
+        let built = Self::create_pipelines(device, canvas, &layout_ci, vert_module, frag_module);
         unsafe{device.destroy_shader_module(vert_module, None)};
         unsafe{device.destroy_shader_module(frag_module, None)};
+        let (pipeline_layout, opaque_pipeline, transparent_pipeline) = built?;
         
-        let dispatchable_opaque = Rc::new(DispatchableGPipeline{
+        let dispatchable_opaque = DispatchableGPipeline{
             pipeline: opaque_pipeline,
             layout: pipeline_layout,
-        });
+        };
         
-        let dispatchable_transparent = Rc::new(DispatchableGPipeline{
+        let dispatchable_transparent = DispatchableGPipeline{
             pipeline: transparent_pipeline,
             layout: pipeline_layout,
-        });
+        };
         
         Ok(Self{
             opaque_pipeline,
             transparent_pipeline,
             pipeline_layout,
-            descriptor_writer,
-            descriptor_layout: material_descriptor_layout,
             
-            dispatchable_opaque,
-            dispatchable_transparent,
+            dispatchable_opaque: Rc::new(Cell::new(dispatchable_opaque)),
+            dispatchable_transparent: Rc::new(Cell::new(dispatchable_transparent)),
+            
+            debug_view: DebugView::Shaded,
+            debug_main: None,
+            debug_overlay: None,
+            checker: [0, 0],
         })
     }
     
+    // Whatever was created before a failing step is destroyed again.
+    fn create_pipelines(device:&mut Device, canvas:&Canvas, layout_ci:&vk::PipelineLayoutCreateInfo, vert_module:vk::ShaderModule, frag_module:vk::ShaderModule) -> Result<(vk::PipelineLayout, vk::Pipeline, vk::Pipeline), AAError> {
+        let pipeline_layout = unsafe{device.create_pipeline_layout(layout_ci, None)}?;
+        
+        let opaque_pipeline = match Self::create_opaque_pipeline(device, canvas, pipeline_layout, vert_module, frag_module) {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                unsafe{device.destroy_pipeline_layout(pipeline_layout, None)};
+                return Err(error);
+            }
+        };
+        let transparent_pipeline = match Self::create_transparent_pipeline(device, canvas, pipeline_layout, vert_module, frag_module) {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                unsafe{device.destroy_pipeline(opaque_pipeline, None)};
+                unsafe{device.destroy_pipeline_layout(pipeline_layout, None)};
+                return Err(error);
+            }
+        };
+        Ok((pipeline_layout, opaque_pipeline, transparent_pipeline))
+    }
+    
+    // Builds the pipelines again from the current SPIR-V, the old ones wait in the stack until no frame uses them.
+    pub fn reload(&mut self, device:&mut Device, canvas:&Canvas, scene_descriptor:&DescriptorLayout, bindless:&BindlessSet, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        let mut fresh = Self::build_pipelines(device, canvas, scene_descriptor, bindless)?;
+        fresh.checker = self.checker;
+        let (debug_main, debug_overlay) = match fresh.build_debug_pipelines(device, canvas, self.debug_view) {
+            Ok(holder) => holder,
+            Err(error) => {
+                fresh.internal_destroy(device);
+                return Err(error);
+            }
+        };
+        self.dispatchable_opaque.set(fresh.dispatchable_opaque.get());
+        self.dispatchable_transparent.set(fresh.dispatchable_transparent.get());
+        
+        let old = Self{
+            opaque_pipeline: std::mem::replace(&mut self.opaque_pipeline, fresh.opaque_pipeline),
+            transparent_pipeline: std::mem::replace(&mut self.transparent_pipeline, fresh.transparent_pipeline),
+            pipeline_layout: std::mem::replace(&mut self.pipeline_layout, fresh.pipeline_layout),
+            dispatchable_opaque: fresh.dispatchable_opaque,
+            dispatchable_transparent: fresh.dispatchable_transparent,
+            debug_view: self.debug_view,
+            debug_main: std::mem::replace(&mut self.debug_main, debug_main),
+            debug_overlay: std::mem::replace(&mut self.debug_overlay, debug_overlay),
+            checker: self.checker,
+        };
+        let callback = Box::new(move |args:VkDestructorArguments|old.destruct(args));
+        destruction_stack.push(VkDynamicDestructor::new::<Self>(object_name("metalic_material"), VkDestructorType::Dev, callback));
+        Ok(())
+    }
+    
+    pub fn set_checker(&mut self, image:BindlessIndex, sampler:BindlessIndex) {
+        self.checker = [image.get(), sampler.get()];
+    }
+    
+    pub fn debug_view(&self) -> DebugView {
+        self.debug_view
+    }
+    
+    pub fn debug_pipelines(&self) -> DebugPipelines {
+        let dispatchable = |pipeline|DispatchableGPipeline{
+            pipeline,
+            layout: self.pipeline_layout,
+        };
+        DebugPipelines{
+            main: self.debug_main.map(dispatchable),
+            overlay: self.debug_overlay.map(dispatchable),
+            clears_background: self.debug_view.clears_background(),
+        }
+    }
+    
+    // On failure the current view stays, the variants it replaces wait in the stack until no frame uses them.
+    pub fn set_debug_view(&mut self, device:&mut Device, canvas:&Canvas, view:DebugView, destruction_stack:&mut DestructionStack) -> Result<(), AAError> {
+        let (debug_main, debug_overlay) = self.build_debug_pipelines(device, canvas, view)?;
+        let old = [
+            std::mem::replace(&mut self.debug_main, debug_main),
+            std::mem::replace(&mut self.debug_overlay, debug_overlay),
+        ];
+        self.debug_view = view;
+        let callback = Box::new(move |mut args:VkDestructorArguments|{
+            let device = args.unwrap_dev();
+            for pipeline in old.into_iter().flatten() {
+                unsafe{device.destroy_pipeline(pipeline, None)};
+            }
+        });
+        destruction_stack.push(VkDynamicDestructor::new::<Self>(object_name("metalic debug view"), VkDestructorType::Dev, callback));
+        Ok(())
+    }
+    
+    // Returns the pipeline replacing the material ones and the one drawn over them, depending on the view.
+    fn build_debug_pipelines(&self, device:&mut Device, canvas:&Canvas, view:DebugView) -> Result<(Option<vk::Pipeline>, Option<vk::Pipeline>), AAError> {
+        if view == DebugView::Shaded {
+            return Ok((None, None));
+        }
+        
+        let variant = view.shader_variant().to_string();
+        let checker_image = self.checker[0].to_string();
+        let checker_sampler = self.checker[1].to_string();
+        let defines = [
+            ("DEBUG_VIEW", variant.as_str()),
+            ("CHECKER_IMAGE", checker_image.as_str()),
+            ("CHECKER_SAMPLER", checker_sampler.as_str()),
+        ];
+        
+        let (vert_module, _) = pipeline::create_shader_module(device, constants::graph::MESH_VERT)?;
+        let (frag_module, _) = match pipeline::create_shader_variant(device, constants::graph::MESH_FRAG, &defines) {
+            Ok(holder) => holder,
+            Err(error) => {
+                unsafe{device.destroy_shader_module(vert_module, None)};
+                return Err(error);
+            }
+        };
+        
+        let pipeline = Self::create_debug_pipeline(device, canvas, self.pipeline_layout, vert_module, frag_module, view);
+        
+        unsafe{device.destroy_shader_module(vert_module, None)};
+        unsafe{device.destroy_shader_module(frag_module, None)};
+        
+        let pipeline = pipeline?;
+        match view {
+            DebugView::WireframeOverShaded => Ok((None, Some(pipeline))),
+            _ => Ok((Some(pipeline), None)),
+        }
+    }
+    
     pub fn clear_resources() {
         
     }
     
+    // The scene set is built from the same shaders by VInit, the bindless set and the Rust side structs are checked here.
+    fn check_interface(interface:&ShaderReflection, bindless:&BindlessSet) -> Result<(), AAError> {
+        bindless.check_interface(interface, 1)?;
+        interface.check_element(1, bindless_slots::BUFFER_BINDING, size_of::<MaterialConstants>())?;
+        interface.check_push_constants(size_of::<GPUDrawPushConstants>())
+    }
+    
     pub fn create_opaque_pipeline(device:&mut Device, canvas:&Canvas, pipeline_layout:vk::PipelineLayout, vert_module:vk::ShaderModule, frag_module:vk::ShaderModule) -> Result<vk::Pipeline, AAError> {
         let mut builder = GPipelineBuilder::new();
         let (color_format, depth_format) = canvas.get_formats();
@@ -134,10 +268,11 @@ impl MetalicMaterial {
             .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
             .set_polygon_mode(vk::PolygonMode::FILL)
             .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-            .set_multisampling_none()
+            .set_multisampling(canvas.get_samples())
             .set_blending_disabled()
             .set_depthtest_enable()
             .set_color_attachment_format(color_format)
+            .add_color_attachment(canvas.get_motion().format, vk::ColorComponentFlags::R | vk::ColorComponentFlags::G)
             .set_depth_format(depth_format);
         
         builder.build_raw(device)
@@ -152,33 +287,88 @@ impl MetalicMaterial {
             .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
             .set_polygon_mode(vk::PolygonMode::FILL)
             .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-            .set_multisampling_none()
+            .set_multisampling(canvas.get_samples())
             .set_blending_additive()
             .set_depthtest_none()
             .set_color_attachment_format(color_format)
+            // Blended surfaces leave the motion of what is behind them.
+            .add_color_attachment(canvas.get_motion().format, vk::ColorComponentFlags::empty())
             .set_depth_format(depth_format);
         
         builder.build_raw(device)
     }
     
 
-    fn internal_destroy(mut self, device: &mut Device) {
+    pub fn create_debug_pipeline(device:&mut Device, canvas:&Canvas, pipeline_layout:vk::PipelineLayout, vert_module:vk::ShaderModule, frag_module:vk::ShaderModule, view:DebugView) -> Result<vk::Pipeline, AAError> {
+        let mut builder = GPipelineBuilder::new();
+        let (color_format, depth_format) = canvas.get_formats();
+        
+        builder.set_pipeline_layout(pipeline_layout)
+            .set_shaders(vert_module, frag_module)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .set_multisampling(canvas.get_samples())
+            .set_color_attachment_format(color_format)
+            .set_depth_format(depth_format);
+        
+        match view {
+            DebugView::Wireframe => {
+                builder.set_polygon_mode(vk::PolygonMode::LINE)
+                    .set_blending_disabled()
+                    .set_depthtest_enable();
+            }
+            // Pulled in front of the shaded surface so the lines are not lost to it.
+            DebugView::WireframeOverShaded => {
+                builder.set_polygon_mode(vk::PolygonMode::LINE)
+                    .set_blending_disabled()
+                    .set_depthtest_read_only()
+                    .set_depth_bias(1f32, 1f32);
+            }
+            // Every layer is counted, hidden or not.
+            DebugView::Overdraw => {
+                builder.set_polygon_mode(vk::PolygonMode::FILL)
+                    .set_blending_accumulate()
+                    .set_depthtest_none();
+            }
+            _ => {
+                builder.set_polygon_mode(vk::PolygonMode::FILL)
+                    .set_blending_disabled()
+                    .set_depthtest_enable();
+            }
+        }
+        
+        // Only the views that write depth own the motion of the surface.
+        let motion_mask = match view {
+            DebugView::WireframeOverShaded | DebugView::Overdraw => vk::ColorComponentFlags::empty(),
+            _ => vk::ColorComponentFlags::R | vk::ColorComponentFlags::G,
+        };
+        builder.add_color_attachment(canvas.get_motion().format, motion_mask);
+        
+        builder.build_raw(device)
+    }
+    
+
+    fn internal_destroy(self, device: &mut Device) {
         logger::destruct!("metalic_material");
+        for pipeline in [self.debug_main, self.debug_overlay].into_iter().flatten() {
+            unsafe{device.destroy_pipeline(pipeline, None)};
+        }
         unsafe{device.destroy_pipeline(self.opaque_pipeline, None)};
         unsafe{device.destroy_pipeline(self.transparent_pipeline, None)};
         unsafe{device.destroy_pipeline_layout(self.pipeline_layout, None)};
-        self.descriptor_layout.destruct(VkDestructorArguments::Dev(device));
         
-        let count = Rc::strong_count(&self.dispatchable_opaque);
-        if count != 1 {
-            panic!("{}: {} times", RESOURCE_REFERENCED, count);
+        // Instances still holding the cells are left with destroyed pipelines, reported as the leak policy says.
+        let referenced:Vec<DestructorInfo> = [("opaque pipeline", &self.dispatchable_opaque), ("transparent pipeline", &self.dispatchable_transparent)].into_iter()
+            .filter(|(_, dispatchable)|Rc::strong_count(dispatchable) != 1)
+            .map(|(name, _)|DestructorInfo{
+                name: object_name(name),
+                type_name: std::any::type_name::<DispatchableGPipeline>(),
+                location: None,
+            })
+            .collect();
+        if !referenced.is_empty() {
+            leak("metalic_material", &referenced, RESOURCE_REFERENCED);
         }
-        
-        let count = Rc::strong_count(&self.dispatchable_transparent);
-        if count != 1 {
-            panic!("{}: {} times", RESOURCE_REFERENCED, count);
-        }
-        
     }
 }
 
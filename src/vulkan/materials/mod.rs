@@ -5,15 +5,18 @@ use crate::logger;
 
 use crate::AAError;
 use crate::constants;
+use crate::graphics::DebugView;
 use crate::errors::messages::COMPILETIME_ASSERT;
 use crate::errors::messages::CPU_ACCESIBLE;
+use crate::errors::messages::GRANTED;
 
 use super::graphics::*;
 use super::init::*;
 use super::objects::*;
 
-use std::rc::Rc;
 use std::slice::from_ref;
+use std::rc::Rc;
+use std::cell::Cell;
 use std::mem::size_of;
 
 use ash::vk;
@@ -22,26 +25,22 @@ use nalgebra as na;
 
 pub struct Materials {
     pub metalic: metalic::MetalicMaterial,
-    pub metalic_instance: MaterialInstance,
+    // Drawn with by the surfaces that have no material, lives in the registry.
+    pub default_instance: Handle<MaterialInstance>,
 }
 
 
+// Materials carry no descriptors, shaders find their constants in the bindless buffer array.
+#[derive(Clone)]
 pub struct MaterialInstance {
-    pub pipeline: Rc<DispatchableGPipeline>,
-    pub descriptor_set: vk::DescriptorSet,
+    pub pipeline: Rc<Cell<DispatchableGPipeline>>,
+    // Registry reference to the buffer holding the constants, released with the instance.
+    pub buffer: Handle<Buffer>,
+    pub constants_buffer: BindlessIndex,
+    pub constants_index: u32,
     pub pass_type: MaterialPass,
 }
 
-impl Clone for MaterialInstance {
-    fn clone(&self) -> Self {
-        Self{
-            pipeline: self.pipeline.clone(),
-            descriptor_set: self.descriptor_set,
-            pass_type: self.pass_type,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 pub enum MaterialPass {
     MainColor,
@@ -49,21 +48,23 @@ pub enum MaterialPass {
     Other,
 }
 
-pub struct MaterialResources<'a> {
-    pub color_image: &'a Image,
-    pub color_sampler: &'a Sampler,
-    pub metal_image: &'a Image,
-    pub metal_sampler: &'a Sampler,
-    pub buffer: Buffer,
-    pub buffer_offset: u64,
+pub struct MaterialResources {
+    pub buffer: Handle<Buffer>,
+    pub constants_buffer: BindlessIndex,
+    pub constants_index: u32,
 }
 
+// Texture indices point into the bindless image and sampler arrays.
 #[repr(C)]
 #[derive(Default, Clone, Copy)]
 pub struct MaterialConstants {
     pub color_factors: na::Vector4<f32>,
     pub metal_rough_factors: na::Vector4<f32>,
-    pub extra: [na::Vector4<f32>;14],
+    pub color_image: u32,
+    pub color_sampler: u32,
+    pub metal_image: u32,
+    pub metal_sampler: u32,
+    pub extra: [na::Vector4<f32>;13],
 }
 
 const _:u64 = MaterialConstants::size_u64();
@@ -83,64 +84,90 @@ pub fn init_material(
     device: &mut Device, 
     allocator:&mut Allocator, 
     canvas:&Canvas, 
-    ds_pool:&mut GDescriptorAllocator, 
-    destruction_stack: &mut DestructionStack,
+    bindless:&mut BindlessSet, 
+    registry: &mut ResourceRegistry,
     scene_descriptor:&DescriptorLayout, 
-    white_texture: &Image,
-    linear_sampler: &Sampler,
+    white_texture: BindlessIndex,
+    linear_sampler: BindlessIndex,
+    // The checkerboard of the UV checker debug view.
+    checker_texture: BindlessIndex,
+    checker_sampler: BindlessIndex,
     
 ) -> Result<Materials, AAError> {
     
     logger::create!("materials");
     
-    let mut metalic = MetalicMaterial::build_pipelines(device, canvas, scene_descriptor)?;
-    let mut buffer = Buffer::create(device, allocator, Some("Metalic material"), MaterialConstants::size_u64(), vk::BufferUsageFlags::UNIFORM_BUFFER, gpu_all::MemoryLocation::CpuToGpu)?;
+    let mut metalic = MetalicMaterial::build_pipelines(device, canvas, scene_descriptor, bindless)?;
+    metalic.set_checker(checker_texture, checker_sampler);
+    let mut buffer = match TypedBuffer::<MaterialConstants>::create(device, allocator, Some("Metalic material"), 1, vk::BufferUsageFlags::STORAGE_BUFFER, gpu_all::MemoryLocation::CpuToGpu) {
+        Ok(buffer) => buffer,
+        Err(error) => {
+            metalic.destruct(VkDestructorArguments::Dev(device));
+            return Err(error);
+        }
+    };
     let holder = MaterialConstants{
         color_factors: na::Vector4::new(1f32,1f32,1f32,1f32),
         metal_rough_factors: na::Vector4::new(1f32,0.5f32,0f32,0f32),
+        color_image: white_texture.get(),
+        color_sampler: linear_sampler.get(),
+        metal_image: white_texture.get(),
+        metal_sampler: linear_sampler.get(),
         ..MaterialConstants::default()
     };
-    destruction_stack.push(buffer.defered_destruct());
-    {
-        let mut align = buffer.get_align::<MaterialConstants>(0, MaterialConstants::size_u64()).expect(CPU_ACCESIBLE);
-        align.copy_from_slice(from_ref(&holder));
-    }
+    buffer.write_at(0, &holder).expect(CPU_ACCESIBLE);
+    let size = buffer.size();
+    let buffer = registry.buffers.insert("Metalic material", buffer.into_inner());
     
+    let constants_buffer = match bindless.register_buffer(device, registry.buffers.get(buffer).expect(GRANTED).underlying(), size) {
+        Ok(index) => index,
+        Err(error) => {
+            registry.release(|registry|&mut registry.buffers, buffer, 0);
+            metalic.destruct(VkDestructorArguments::Dev(device));
+            return Err(error);
+        }
+    };
     let material_resources = MaterialResources{
         buffer,
-        buffer_offset: 0,
-        metal_image: white_texture,
-        metal_sampler: linear_sampler,
-        color_image: white_texture,
-        color_sampler: linear_sampler,
+        constants_buffer,
+        constants_index: 0,
     };
     
-    let metalic_instance = metalic.write_material(device, ds_pool, MaterialPass::MainColor, &material_resources)?;
-    
+    let metalic_instance = metalic.write_material(MaterialPass::MainColor, &material_resources, &mut registry.buffers);
+    // The instance holds the buffer from here on.
+    registry.release(|registry|&mut registry.buffers, buffer, 0);
+    let metalic_instance = match metalic_instance {
+        Ok(instance) => instance,
+        Err(error) => {
+            metalic.destruct(VkDestructorArguments::Dev(device));
+            return Err(error);
+        }
+    };
     
     Ok(Materials{
-        metalic_instance, 
+        default_instance: registry.materials.insert("Metalic material", metalic_instance), 
         metalic,
     })
     
 }
 
-impl Materials {
-    pub fn get_default(&self) -> &MaterialInstance {
-        &self.metalic_instance
+// Descriptor sets are owned by their allocator, there is nothing to free per instance.
+impl VkDestructor for MaterialInstance {
+    fn destruct(self, mut args:VkDestructorArguments) {
+        args.unwrap_none();
     }
 }
 
+impl Registered for MaterialInstance {
+    const DESTRUCTOR_TYPE:VkDestructorType = VkDestructorType::None;
+}
+
 impl VkDestructor for Materials {
     fn destruct(self, mut args:VkDestructorArguments) {
         logger::destruct!("materials");
         let device = args.unwrap_dev();
-        let Materials{
-            metalic_instance,
-            metalic,
-        } = self;
-        drop(metalic_instance);
-        metalic.destruct(VkDestructorArguments::Dev(device));
+        // The instances went with the registry, before the pipelines they point to.
+        self.metalic.destruct(VkDestructorArguments::Dev(device));
     }
 }
 
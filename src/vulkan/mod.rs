@@ -5,18 +5,34 @@ mod graphics;
 use graphics::*;
 
 mod objects;
+pub use objects::LeakPolicy;
 
 mod helpers;
 
 mod materials;
 use materials::*;
 
+mod shader_watcher;
+use shader_watcher::ShaderWatcher;
+pub use shader_watcher::ShaderError;
+
+use crate::AAError;
 use crate::logger;
 use crate::gui::InputData;
+use crate::gui::GuiView;
+use crate::gui::GuiState;
+use crate::graphics::ParameterSchema;
+use crate::graphics::DebugView;
+use crate::graphics::DepthProjection;
+use crate::graphics::Frustum;
+use crate::graphics::CullingStats;
+use crate::graphics::DrawStats;
 use crate::errors::messages::SIMPLE_VK_FN;
 use crate::errors::messages::VK_UNRECOVERABLE;
+use crate::errors::messages::GRANTED;
 
 use super::window::Window;
+use super::config::Config;
 use super::constants;
 
 use objects::DestructionStack;
@@ -26,11 +42,18 @@ use objects::VkDestructorType;
 use objects::VkDeferedDestructor;
 use objects::VkDynamicDestructor;
 use objects::VkDestructorArguments;
+use objects::Registered;
+use objects::ResourceRegistry;
+use objects::Handle;
+use objects::Pool;
+use objects::ObjectName;
+use objects::object_name;
 
+use std::path::Path;
+use std::path::PathBuf;
 
 use ash::vk;
 use nalgebra as na;
-use na::Vector3;
 use arrayvec::ArrayString;
 
 
@@ -52,38 +75,62 @@ pub struct VInit {
     pub command_control: VkWrapper<CommandControl>,
     
     canvas: VkWrapper<graphics::Canvas>,
+    transient_images: VkWrapper<TransientImages>,
     
-    background_image_descriptor_layout: VkWrapper<DescriptorLayout>,
     texture_descriptor_layout: VkWrapper<DescriptorLayout>,
     ds_pool: VkWrapper<GDescriptorAllocator>,
-    background_image_ds: vk::DescriptorSet,
     
     compute_effects: VkWrapper<ComputeEffects>,
+    post_effects: VkWrapper<ComputeEffects>,
+    color_lut: VkWrapper<Image>,
+    auto_exposure: VkWrapper<AutoExposure>,
+    taa: VkWrapper<TemporalAA>,
+    bloom: VkWrapper<Bloom>,
     
     //mesh_pipeline: VkWrapper<GPipeline>,
     //mesh_assets: VkWrapper<MeshAssets>,
     
     materials: VkWrapper<Materials>,
-    mesh_assets: VkWrapper<VkMeshAssets>,
+    bindless: VkWrapper<BindlessSet>,
+    registry: VkWrapper<ResourceRegistry>,
+    mesh_assets: Vec<Handle<VkMeshAsset>>,
+    mesh_names: Vec<ArrayString<64>>,
     
     main_draw_context: DrawContext,
+    memory_report: MemoryReport,
+    descriptor_stats: Vec<DescriptorStats>,
+    shader_watcher: ShaderWatcher,
     
     compute_effect_index: usize,
+    // Preset names on disk for the effect they were listed for.
+    presets: (Option<usize>, Vec<String>),
     mesh_index: usize,
     field_of_view: na::Vector3<f32>,
+    depth_projection: DepthProjection,
     downscale_coheficient: f32,
+    // Sample counts the device can draw the geometry with, and the one asked for, applied between frames.
+    sample_counts: Vec<u32>,
+    msaa_samples: u32,
+    // Views the device can draw, the wireframes need fillModeNonSolid, and the one asked for, applied between frames.
+    debug_views: Vec<DebugView>,
+    debug_view: DebugView,
+    // Frustum the draw context was last culled against, kept as is while culling is frozen.
+    culling_frustum: Option<Frustum>,
+    freeze_culling: bool,
+    culling_stats: CullingStats,
+    draw_stats: DrawStats,
     
     frames_data: VkWrapper<graphics::FramesData>,
     scene_data: graphics::GPUSceneData,
     gpu_scene_layout: VkWrapper<DescriptorLayout>,
     
-    fuzzy_sampler: VkWrapper<Sampler>,
-    pixelated_sampler: VkWrapper<Sampler>,
+    fuzzy_sampler: Handle<Sampler>,
+    pixelated_sampler: Handle<Sampler>,
     
-    white_texture: VkWrapper<Image>,
-    grey_texture: VkWrapper<Image>,
-    black_texture: VkWrapper<Image>,
-    error_texture: VkWrapper<Image>,
+    white_texture: Handle<Image>,
+    grey_texture: Handle<Image>,
+    black_texture: Handle<Image>,
+    error_texture: Handle<Image>,
     
     
     destruction_stack: DestructionStack,
@@ -92,7 +139,9 @@ pub struct VInit {
 
 
 impl VInit {
-    pub fn init(window:&mut Window) -> VInit {
+    pub fn init(window:&mut Window, config:&Config) -> Result<VInit, AAError> {
+        
+        objects::set_leak_policy(config.leak_policy);
         
         //panic!("{:?}", ImageMetadata::texture("a"));
         
@@ -121,27 +170,83 @@ impl VInit {
         
         let mut destruction_stack = objects::DestructionStack::new();
         
-        let mut canvas = Canvas::new(&mut device, &mut allocator, swapchain.extent.into()).unwrap();
-        let render_image = canvas.get_color();
+        let canvas = Canvas::new(&mut device, &mut allocator, swapchain.extent.into())?;
         
-        let (mut ds_pool, background_image_ds, background_image_descriptor_layout, texture_descriptor_layout) = init_descriptors(&mut device, &render_image);
-        let compute_effects = c_pipeline::init_pipelines(&mut device, &background_image_descriptor_layout);
+        let (ds_pool, texture_descriptor_layout) = init_descriptors(&mut device);
+        let compute_effects = ComputeEffects::load(&mut device, constants::comp::EFFECT_MANIFEST)?;
         
+        // The frame still reaches the screen without a post chain, a broken one is reported and left empty.
+        let mut shader_watcher = ShaderWatcher::new(constants::shaders::SOURCE_DIR, &[constants::comp::EFFECT_MANIFEST, constants::post::MANIFEST]);
+        let post_effects = match ComputeEffects::load(&mut device, constants::post::MANIFEST) {
+            Ok(post_effects) => post_effects,
+            Err(error) => {
+                shader_watcher.report_error(Path::new(constants::post::MANIFEST), error.to_string());
+                ComputeEffects::empty()
+            }
+        };
+        let mut taa = TemporalAA::create(&mut device, &mut allocator, swapchain.extent.into())?;
+        if let Err(error) = taa.build_pipeline(&mut device, &mut destruction_stack) {
+            shader_watcher.report_error(Path::new(constants::taa::RESOLVE_SHADER), error.to_string());
+        }
+        let mut bloom = Bloom::create(&mut device, &mut allocator, swapchain.extent.into())?;
+        if let Err(error) = bloom.build_pipelines(&mut device, &mut destruction_stack) {
+            shader_watcher.report_error(Path::new(constants::bloom::DOWNSAMPLE_SHADER), error.to_string());
+        }
+        let limits = p_device.properties.limits;
+        let supported_samples = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let sample_counts = constants::msaa::SAMPLE_COUNTS.into_iter().filter(|count|supported_samples.contains(vk::SampleCountFlags::from_raw(*count))).collect();
+        let non_solid = p_device.features.fill_mode_non_solid == vk::TRUE;
+        let debug_views = DebugView::ALL.into_iter().filter(|view|non_solid || !view.is_wireframe()).collect();
+        let color_lut = init_color_lut(&mut device, &mut allocator, &mut command_control)?;
+        let mut auto_exposure = AutoExposure::create(&mut device, &mut allocator, &mut command_control, config.frames_in_flight)?;
+        if let Err(error) = auto_exposure.build_pipelines(&mut device, &mut destruction_stack) {
+            shader_watcher.report_error(Path::new(constants::exposure::HISTOGRAM_SHADER), error.to_string());
+        }
         
-        let frames_data = FramesData::create(&p_device, &mut device).unwrap();
         
         
-        let mut ds_layout_builder = DescriptorLayoutBuilder::create();
-        ds_layout_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1);
-        let (gpu_scene_layout, _types_in_layout) = ds_layout_builder.build(&mut device, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT).unwrap();
         
+        let mesh_interface = pipeline::reflect_shader(constants::graph::MESH_VERT)
+            .and_then(|vert|vert.merge(&pipeline::reflect_shader(constants::graph::MESH_FRAG)?))?;
+        mesh_interface.check_block(0, 0, size_of::<GPUSceneData>())?;
+        let (gpu_scene_layout, scene_types_in_layout) = mesh_interface.descriptor_layout(&mut device, 0)?;
         
-        let (white_texture, grey_texture, black_texture, error_texture) = init_textures(&mut device, &mut allocator, &mut command_control);
+        // Effect sets are allocated every frame next to the scene one.
+        let mut frame_ratios = scene_types_in_layout;
+        frame_ratios.add_type_count(vk::DescriptorType::STORAGE_IMAGE, constants::comp::MAX_EFFECT_IMAGES as u32);
+        frame_ratios.add_type_count(vk::DescriptorType::STORAGE_BUFFER, 1);
+        let frames_data = FramesData::create(&p_device, &mut device, config.frames_in_flight, &frame_ratios)?;
         
-        let pixelated_sampler = Sampler::create(&mut device, vk::Filter::NEAREST).unwrap();
-        let fuzzy_sampler = Sampler::create(&mut device, vk::Filter::LINEAR).unwrap();
         
-        let materials = materials::init_material(&mut device, &mut allocator, &canvas, &mut ds_pool, &mut destruction_stack, &gpu_scene_layout, &white_texture, &fuzzy_sampler).unwrap();
+        let mut registry = ResourceRegistry::new();
+        
+        let (white_texture, grey_texture, black_texture, error_texture) = init_textures(&mut device, &mut allocator, &mut command_control);
+        let white_texture = registry.images.insert("white texture", white_texture);
+        let grey_texture = registry.images.insert("grey texture", grey_texture);
+        let black_texture = registry.images.insert("black texture", black_texture);
+        let error_texture = registry.images.insert("error texture", error_texture);
+        
+        let pixelated_sampler = registry.samplers.insert("pixelated sampler", Sampler::create(&mut device, vk::Filter::NEAREST)?);
+        let fuzzy_sampler = registry.samplers.insert("fuzzy sampler", Sampler::create(&mut device, vk::Filter::LINEAR)?);
+        
+        let mut bindless = BindlessSet::create(&mut device, &p_device)?;
+        let bindless_white = bindless.register_image(&device, registry.images.get(white_texture).expect(GRANTED))?;
+        let bindless_fuzzy = bindless.register_sampler(&device, registry.samplers.get(fuzzy_sampler).expect(GRANTED))?;
+        let bindless_error = bindless.register_image(&device, registry.images.get(error_texture).expect(GRANTED))?;
+        let bindless_pixelated = bindless.register_sampler(&device, registry.samplers.get(pixelated_sampler).expect(GRANTED))?;
+        
+        let materials = materials::init_material(
+            &mut device, 
+            &mut allocator, 
+            &canvas, 
+            &mut bindless, 
+            &mut registry, 
+            &gpu_scene_layout, 
+            bindless_white, 
+            bindless_fuzzy,
+            bindless_error,
+            bindless_pixelated,
+        )?;
         
         /*
         let Materials{
@@ -153,15 +258,24 @@ impl VInit {
         */
         
         
-        let (render_image, depth_image) = canvas.get_images();
-        let mesh_assets = load_gltf(&mut device, &mut allocator, &mut command_control, "res/gltf/basicmesh.glb").expect("runtime error");
+        let mut mesh_assets = Vec::new();
+        let mut mesh_names = Vec::new();
+        for mut mesh_asset in load_gltf(&mut device, &mut allocator, &mut command_control, "res/gltf/basicmesh.glb")? {
+            let name = mesh_asset.name;
+            // The glTF materials are not loaded, every surface draws with the default one while the mesh is alive.
+            for surface in mesh_asset.surfaces.iter_mut() {
+                surface.material = registry.materials.acquire(materials.default_instance);
+            }
+            mesh_names.push(name);
+            mesh_assets.push(registry.meshes.insert(&name, mesh_asset));
+        }
         
         let main_draw_context = DrawContext::default();
         
         
         
-        VInit{
-            frame_control: FrameControl(0),
+        Ok(VInit{
+            frame_control: FrameControl::new(config.frames_in_flight),
             resize_required: false,
             
             instance: VkWrapper::new(instance),
@@ -179,46 +293,81 @@ impl VInit {
             command_control: VkWrapper::new(command_control),
             
             canvas: VkWrapper::new(canvas),
+            transient_images: VkWrapper::new(TransientImages::new(config.frames_in_flight)),
             
             texture_descriptor_layout: VkWrapper::new(texture_descriptor_layout),
-            background_image_descriptor_layout: VkWrapper::new(background_image_descriptor_layout),
             ds_pool: VkWrapper::new(ds_pool),
-            background_image_ds: background_image_ds,
             
             compute_effects: VkWrapper::new(compute_effects),
             compute_effect_index:0,
+            post_effects: VkWrapper::new(post_effects),
+            color_lut: VkWrapper::new(color_lut),
+            auto_exposure: VkWrapper::new(auto_exposure),
+            taa: VkWrapper::new(taa),
+            bloom: VkWrapper::new(bloom),
+            presets: (None, Vec::new()),
             
             //mesh_pipeline: VkWrapper::new(mesh_pipeline),
-            mesh_assets: VkWrapper::new(mesh_assets),
+            registry: VkWrapper::new(registry),
+            mesh_assets,
+            mesh_names,
             main_draw_context,
+            memory_report: MemoryReport::default(),
+            descriptor_stats: Vec::new(),
+            shader_watcher,
             
             materials: VkWrapper::new(materials),
+            bindless: VkWrapper::new(bindless),
             
             mesh_index: 0,
             
             field_of_view:na::Vector3::new(10000.0,0.01,70.0),
+            depth_projection: DepthProjection::default(),
             downscale_coheficient: 1.0,
+            sample_counts,
+            msaa_samples: 1,
+            debug_views,
+            debug_view: DebugView::Shaded,
+            culling_frustum: None,
+            freeze_culling: false,
+            culling_stats: CullingStats::default(),
+            draw_stats: DrawStats::default(),
             
             frames_data: VkWrapper::new(frames_data),
             
             scene_data: GPUSceneData::default(),
             gpu_scene_layout: VkWrapper::new(gpu_scene_layout),
             
-            white_texture: VkWrapper::new(white_texture),
-            grey_texture: VkWrapper::new(grey_texture),
-            black_texture: VkWrapper::new(black_texture),
-            error_texture: VkWrapper::new(error_texture),
+            white_texture,
+            grey_texture,
+            black_texture,
+            error_texture,
             
-            pixelated_sampler: VkWrapper::new(pixelated_sampler),
-            fuzzy_sampler: VkWrapper::new(fuzzy_sampler),
+            pixelated_sampler,
+            fuzzy_sampler,
             
             destruction_stack: destruction_stack,
-        }
+        })
         
     }
     
     pub fn gui_tick(&mut self, data:&InputData) {
         
+        if data.dump_memory_report {
+            self.allocator.log_report();
+        }
+        
+        if data.unload_mesh {
+            self.unload_mesh(self.mesh_index);
+        }
+        
+        if let Some(preset) = data.save_preset.as_deref() {
+            self.save_preset(preset);
+        }
+        if let Some(preset) = data.load_preset.as_deref() {
+            self.load_preset(preset);
+        }
+        
         //self.compute_effects.metadatas[data.background_index].data[index] = data.push_constants[index];
         /*
         self.compute_effect_index = data.background_index;
@@ -229,6 +378,76 @@ impl VInit {
     }
     
     
+    // Names become path components, only letters, digits, spaces, '-' and '_' keep them inside the preset directory.
+    fn plain_name(name:&str) -> bool {
+        !name.trim().is_empty() && name.chars().all(|c|c.is_ascii_alphanumeric() || [' ', '-', '_'].contains(&c))
+    }
+    
+    fn preset_dir(effect:&str) -> Option<PathBuf> {
+        if !Self::plain_name(effect) {
+            logger::various_log!("vulkan",
+                (logger::Warn, "effect {:?} can't have presets, its name is not plain", effect)
+            );
+            return None;
+        }
+        Some(Path::new(constants::comp::PRESET_DIR).join(effect))
+    }
+    
+    fn preset_path(&self, preset:&str) -> Option<PathBuf> {
+        if !Self::plain_name(preset) {
+            logger::various_log!("vulkan",
+                (logger::Warn, "{:?} is not a valid preset name", preset)
+            );
+            return None;
+        }
+        let effect = self.compute_effects.names[self.compute_effect_index].as_str();
+        Self::preset_dir(effect).map(|dir|dir.join(format!("{}.toml", preset)))
+    }
+    
+    fn save_preset(&mut self, preset:&str) {
+        let Some(path) = self.preset_path(preset) else {
+            return;
+        };
+        let index = self.compute_effect_index;
+        match self.compute_effects.schemas[index].save_preset(&path, &self.compute_effects.push_constants[index]) {
+            Ok(()) => self.presets.0 = None,
+            Err(error) => {
+                logger::various_log!("vulkan",
+                    (logger::Error, "{}", error)
+                );
+            }
+        }
+    }
+    
+    fn load_preset(&mut self, preset:&str) {
+        let Some(path) = self.preset_path(preset) else {
+            return;
+        };
+        let index = self.compute_effect_index;
+        let ComputeEffects{ref schemas, ref mut push_constants, ..} = *self.compute_effects;
+        if let Err(error) = schemas[index].load_preset(&path, &mut push_constants[index]) {
+            logger::various_log!("vulkan",
+                (logger::Error, "{}", error)
+            );
+        }
+    }
+    
+    // The mesh stays alive until the next submission, the last one that could be drawing it, has finished.
+    pub fn unload_mesh(&mut self, index:usize) {
+        if index >= self.mesh_assets.len() {
+            return;
+        }
+        let handle = self.mesh_assets.remove(index);
+        self.mesh_names.remove(index);
+        self.mesh_index = self.mesh_index.min(self.mesh_assets.len().saturating_sub(1));
+        let value = self.device.timeline.pending();
+        self.registry.release_mesh(handle, value);
+    }
+    
+    pub fn frames_in_flight(&self) -> usize {
+        self.frame_control.frames_in_flight
+    }
+    
     #[inline(always)]
     pub fn wait_idle(&self) {
         unsafe{self.device.device_wait_idle()}.expect(SIMPLE_VK_FN);
@@ -274,34 +493,195 @@ impl VInit {
         self.frame_control.frame_update()
     }
     
+    // Runs between frames, replaced pipelines go to the stack of the last submitted frame so they outlive its work.
+    fn reload_shaders(&mut self) {
+        if !constants::hot_reload::ENABLED {
+            return;
+        }
+        let changed = self.shader_watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+        
+        let frame = self.get_frame();
+        let VInit{
+            device,
+            canvas,
+            compute_effects,
+            post_effects,
+            auto_exposure,
+            taa,
+            bloom,
+            materials,
+            bindless,
+            gpu_scene_layout,
+            compute_effect_index,
+            presets,
+            frames_data,
+            shader_watcher,
+            ..
+        } = self;
+        let destruction_stack = frames_data.get_destruction_stack(frame);
+        
+        for source in changed {
+            logger::various_log!("vulkan",
+                (logger::Debug, "reloading pipelines using {:?}", source)
+            );
+            if source == Path::new(constants::comp::EFFECT_MANIFEST) {
+                match ComputeEffects::load(device, &source) {
+                    Ok(fresh) => {
+                        compute_effects.replace(fresh, destruction_stack);
+                        *compute_effect_index = (*compute_effect_index).min(compute_effects.len() - 1);
+                        presets.0 = None;
+                        shader_watcher.clear_error(&source);
+                    }
+                    Err(error) => shader_watcher.report_error(&source, error.to_string()),
+                }
+                continue;
+            }
+            if source == Path::new(constants::post::MANIFEST) {
+                match ComputeEffects::load(device, &source) {
+                    Ok(fresh) => {
+                        post_effects.replace(fresh, destruction_stack);
+                        shader_watcher.clear_error(&source);
+                    }
+                    Err(error) => shader_watcher.report_error(&source, error.to_string()),
+                }
+                continue;
+            }
+            let mut result = compute_effects.reload(device, &source, destruction_stack)
+                .and_then(|_|post_effects.reload(device, &source, destruction_stack))
+                .and_then(|_|auto_exposure.reload(device, &source, destruction_stack))
+                .and_then(|_|canvas.reload(device, &source, destruction_stack))
+                .and_then(|_|taa.reload(device, &source, destruction_stack))
+                .and_then(|_|bloom.reload(device, &source, destruction_stack));
+            if result.is_ok() && [constants::graph::MESH_VERT, constants::graph::MESH_FRAG].iter().any(|file|Path::new(file) == source) {
+                result = materials.metalic.reload(device, canvas, gpu_scene_layout, bindless, destruction_stack);
+            }
+            match result {
+                Ok(()) => shader_watcher.clear_error(&source),
+                Err(error) => shader_watcher.report_error(&source, error.to_string()),
+            }
+        }
+    }
+    
+    // Runs between frames like the shader reload, the geometry pipelines are rebuilt for the new targets.
+    fn apply_msaa(&mut self) {
+        // TAA needs the single-sampled motion target, it takes over from MSAA while enabled.
+        let requested = match self.taa.enabled {
+            true => 1,
+            false => self.msaa_samples,
+        };
+        let samples = vk::SampleCountFlags::from_raw(requested);
+        if samples == self.canvas.get_samples() {
+            return;
+        }
+        
+        let frame = self.get_frame();
+        let VInit{
+            device,
+            allocator,
+            canvas,
+            materials,
+            bindless,
+            gpu_scene_layout,
+            frames_data,
+            sample_counts,
+            msaa_samples,
+            ..
+        } = self;
+        let destruction_stack = frames_data.get_destruction_stack(frame);
+        
+        let previous = canvas.get_samples();
+        let mut result = match sample_counts.contains(&requested) {
+            true => canvas.set_samples(device, allocator, samples, destruction_stack),
+            false => Err(AAError::UnsupportedSampleCount(requested)),
+        };
+        if result.is_ok() {
+            result = materials.metalic.reload(device, canvas, gpu_scene_layout, bindless, destruction_stack);
+            if result.is_err() {
+                // The pipelines still draw with the old sample count, so the old targets come back.
+                canvas.set_samples(device, allocator, previous, destruction_stack).expect(VK_UNRECOVERABLE);
+            }
+        }
+        if let Err(error) = result {
+            logger::various_log!("vulkan",
+                (logger::Error, "could not switch to {}x MSAA: {}", requested, error)
+            );
+            *msaa_samples = previous.as_raw();
+        }
+    }
+    
+    // Runs between frames like the MSAA switch, a view that fails to build is logged and dropped.
+    fn apply_debug_view(&mut self) {
+        if self.debug_view == self.materials.metalic.debug_view() {
+            return;
+        }
+        
+        let frame = self.get_frame();
+        let VInit{
+            device,
+            canvas,
+            materials,
+            frames_data,
+            debug_view,
+            ..
+        } = self;
+        let destruction_stack = frames_data.get_destruction_stack(frame);
+        
+        if let Err(error) = materials.metalic.set_debug_view(device, canvas, *debug_view, destruction_stack) {
+            logger::various_log!("vulkan",
+                (logger::Error, "could not switch to the {} debug view: {}", debug_view.name(), error)
+            );
+            *debug_view = materials.metalic.debug_view();
+        }
+    }
+    
     fn get_frame(&self) -> usize {
         self.frame_control.get_frame()
     }
     
-    pub fn get_gui_data(
-        &mut self
-    ) ->  (
-        (
-            &[ArrayString<64>],
-            &[std::rc::Rc<VkMeshAsset>],
-        ), (
-            &dyn Fn(&ArrayString<64>)->&str,
-            &dyn Fn(&std::rc::Rc<VkMeshAsset>)->&str,
-        ),(
-            &mut usize,
-            &mut ComputePushConstants,
-            &mut usize, 
-            &mut Vector3<f32>,
-            &mut f32,
-        )
-    ) {
-        let ComputeEffects{ref names, ref mut push_constants, ..} = *self.compute_effects;
+    pub fn get_gui_data(&mut self) -> (GuiView<'_>, GuiState<'_>) {
+        self.memory_report = self.allocator.report();
+        self.descriptor_stats = self.frames_data.descriptor_stats();
+        let ComputeEffects{ref names, ref mut push_constants, ref schemas, ..} = *self.compute_effects;
         let index = self.compute_effect_index;
-        (
-            (names, &self.mesh_assets[..]), 
-            (&|holder|{holder}, &|holder|{&holder.name}),
-            (&mut self.compute_effect_index, &mut push_constants[index], &mut self.mesh_index, &mut self.field_of_view, &mut self.downscale_coheficient, )
-        )
+        if self.presets.0 != Some(index) {
+            self.presets = (Some(index), Self::preset_dir(names[index].as_str()).map(ParameterSchema::presets_in).unwrap_or_default());
+        }
+        let ComputeEffects{names:ref post_names, push_constants:ref mut post_push_constants, schemas:ref post_schemas, ref mut enabled, ..} = *self.post_effects;
+        let view = GuiView{
+            effect_names: names,
+            mesh_names: &self.mesh_names[..],
+            memory_report: &self.memory_report,
+            descriptor_stats: &self.descriptor_stats[..],
+            shader_errors: self.shader_watcher.errors(),
+            parameter_schema: &schemas[index],
+            presets: &self.presets.1[..],
+            post_names,
+            post_schemas,
+            exposure_readout: self.auto_exposure.readout(),
+            sample_counts: &self.sample_counts[..],
+            debug_views: &self.debug_views[..],
+            culling_stats: self.culling_stats,
+            draw_stats: self.draw_stats,
+        };
+        let state = GuiState{
+            effect_index: &mut self.compute_effect_index,
+            push_constants: &mut push_constants[index],
+            mesh_index: &mut self.mesh_index,
+            field_of_view: &mut self.field_of_view,
+            depth_projection: &mut self.depth_projection,
+            downscale_coheficient: &mut self.downscale_coheficient,
+            post_enabled: enabled,
+            post_push_constants,
+            exposure_settings: &mut self.auto_exposure.settings,
+            msaa_samples: &mut self.msaa_samples,
+            taa_enabled: &mut self.taa.enabled,
+            debug_view: &mut self.debug_view,
+            freeze_culling: &mut self.freeze_culling,
+        };
+        (view, state)
     }
     
     
@@ -332,24 +712,24 @@ impl Drop for VInit {
             command_control, 
             
             canvas,
+            transient_images,
             
             ds_pool, 
-            background_image_descriptor_layout, 
             texture_descriptor_layout, 
             compute_effects, 
-            mesh_assets,
+            post_effects,
+            color_lut,
+            auto_exposure,
+            taa,
+            bloom,
+            registry,
+            main_draw_context,
             
             materials,
+            bindless,
             
             frames_data,
             gpu_scene_layout,
-            fuzzy_sampler,
-            pixelated_sampler,
-            
-            white_texture,
-            grey_texture,
-            black_texture,
-            error_texture,
             destruction_stack,
             ..
         } = self;
@@ -359,29 +739,28 @@ impl Drop for VInit {
         
         destruction_stack.dispatch(dev, all);
         
-        fuzzy_sampler.destruct(VkDestructorArguments::Dev(dev));
-        pixelated_sampler.destruct(VkDestructorArguments::Dev(dev));
-        
-        white_texture.destruct(VkDestructorArguments::DevAll(dev, all));
-        grey_texture.destruct(VkDestructorArguments::DevAll(dev, all));
-        black_texture.destruct(VkDestructorArguments::DevAll(dev, all));
-        error_texture.destruct(VkDestructorArguments::DevAll(dev, all));
-        
         gpu_scene_layout.destruct(VkDestructorArguments::Dev(dev));
         frames_data.destruct(VkDestructorArguments::DevAll(dev, all));
         
         
-        mesh_assets.destruct(VkDestructorArguments::DevAll(dev, all));
+        // Last frame's objects still hold clones of the material instances.
+        main_draw_context.clear();
+        registry.destruct(VkDestructorArguments::DevAll(dev, all));
         
         materials.destruct(VkDestructorArguments::Dev(dev));
+        bindless.destruct(VkDestructorArguments::Dev(dev));
         
         //mesh_assets.destruct(VkDestructorArguments::DevAll(dev, all));
         //mesh_pipeline.destruct(VkDestructorArguments::Dev(dev));
         compute_effects.destruct(VkDestructorArguments::Dev(dev));
+        post_effects.destruct(VkDestructorArguments::Dev(dev));
+        color_lut.destruct(VkDestructorArguments::DevAll(dev, all));
+        auto_exposure.destruct(VkDestructorArguments::DevAll(dev, all));
+        taa.destruct(VkDestructorArguments::DevAll(dev, all));
+        bloom.destruct(VkDestructorArguments::DevAll(dev, all));
         
         
         ds_pool.destruct(VkDestructorArguments::Dev(dev));
-        background_image_descriptor_layout.destruct(VkDestructorArguments::Dev(dev));
         texture_descriptor_layout.destruct(VkDestructorArguments::Dev(dev));
         
         
@@ -391,6 +770,7 @@ impl Drop for VInit {
         */
         command_control.destruct(VkDestructorArguments::Dev(dev));
         
+        transient_images.destruct(VkDestructorArguments::DevAll(dev, all));
         canvas.destruct(VkDestructorArguments::DevAll(dev, all));
         
         swapchain.destruct(VkDestructorArguments::Dev(dev));
@@ -413,21 +793,30 @@ impl Drop for VInit {
     }
 }
 
-struct FrameControl(usize);
+struct FrameControl {
+    frame: usize,
+    frames_in_flight: usize,
+}
 
 impl FrameControl {
+    fn new(frames_in_flight:usize) -> Self {
+        Self{
+            frame: 0,
+            frames_in_flight,
+        }
+    }
+    
     fn get_frame(&self) -> usize {
-        self.0 % constants::fif::USIZE
+        self.frame % self.frames_in_flight
     }
     
-    #[allow(dead_code)]
     #[inline(always)]
     fn get_frame_count(&self) -> usize {
-        self.0
+        self.frame
     }
     #[inline(always)]
     fn frame_update(&mut self) {
-        self.0 += 1;
+        self.frame += 1;
     }
 }
 
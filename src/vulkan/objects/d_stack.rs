@@ -4,6 +4,7 @@ use crate::errors::messages::LEAKING_OBJECTS;
 use super::VkDynamicDestructor;
 use super::VkDestructorType;
 use super::VkDestructorArguments;
+use super::DestructorInfo;
 
 use super::super::Device;
 use super::super::Allocator;
@@ -42,21 +43,29 @@ impl DestructionStack {
             }
         }
         
-        for (destructor, d_type) in self.order.drain(..) {
+        for VkDynamicDestructor{callback, d_type, info} in self.order.drain(..) {
+            logger::various_log!("destruction stack",
+                (logger::Trace, "destroying {}", info)
+            );
             match d_type {
                 VkDestructorType::None => {
-                    destructor(VkDestructorArguments::None);
+                    callback(VkDestructorArguments::None);
                 }
                 VkDestructorType::Dev => {
-                    destructor(VkDestructorArguments::Dev(device));
+                    callback(VkDestructorArguments::Dev(device));
                 }
                 VkDestructorType::DevAll => {
-                    destructor(VkDestructorArguments::DevAll(device, allocator));
+                    callback(VkDestructorArguments::DevAll(device, allocator));
                 }
             }
         }
     }
     
+//----
+    pub fn pending(&self) -> Vec<DestructorInfo> {
+        self.order.iter().map(|destructor|destructor.info).collect()
+    }
+    
 //----
     pub fn destruct(&mut self, mut args:VkDestructorArguments) {
         let (device, allocator) = args.unwrap_dev_all();
@@ -67,23 +76,12 @@ impl DestructionStack {
 
 impl Drop for DestructionStack {
     fn drop(&mut self) {
-        match self.order.len() {
-            0 => {}
-            1 => {
-                logger::various_log!("destruction stack",
-                    (logger::Error, "{} object is leaking", self.order.len())
-                );
-                panic!("{}", LEAKING_OBJECTS);
-            }
-            size => {
-                logger::various_log!("destruction stack",
-                    (logger::Error, "{} objects are leaking", size)
-                );
-                panic!("{}", LEAKING_OBJECTS);
-                
-            }
-            
+        if self.order.is_empty() {
+            return;
         }
+        let leaked = self.pending();
+        // The callbacks own bitwise copies of live handles, forgetting them leaks the objects instead of running them
+        self.order.drain(..).for_each(std::mem::forget);
+        super::leak("destruction stack", &leaked, LEAKING_OBJECTS);
     }
 }
-
@@ -5,9 +5,10 @@ use crate::errors::messages::ALREADY_DESTROYED;
 use crate::errors::messages::REDUNDANT_DEREFED_DESTRUCTOR;
 use crate::errors::messages::REDUNDANT_DESTRUCTOR;
 
-use super::VkDestructorType;
 use super::VkDeferedDestructor;
+use super::VkDynamicDestructor;
 use super::VkDestructorArguments;
+use super::DestructorInfo;
 
 use std::ops::Deref;
 use std::ops::DerefMut;
@@ -39,7 +40,7 @@ impl<T:VkDeferedDestructor> VkDeferedWrapper<T> {
     
 //----
     #[allow(dead_code)]
-    pub fn defered_destruct(&mut self)  -> (Box<dyn FnOnce(VkDestructorArguments)>, VkDestructorType) {
+    pub fn defered_destruct(&mut self)  -> VkDynamicDestructor {
         self.0.defered_destruct()
     }
     
@@ -75,7 +76,7 @@ impl <T:VkDeferedDestructor> VkHolder<T> {
     }
     
 //----
-    fn defered_destruct(&mut self)  -> (Box<dyn FnOnce(VkDestructorArguments)>, VkDestructorType) {
+    fn defered_destruct(&mut self)  -> VkDynamicDestructor {
         let mut object = match self.take() {
             VkHolder::None => {
                 logger::various_log!("defered wrapper",
@@ -143,11 +144,15 @@ impl<T:VkDeferedDestructor> DerefMut for VkDeferedWrapper<T> {
 impl<T:VkDeferedDestructor> Drop for VkDeferedWrapper<T> {
     fn drop(&mut self) {
         match self.0 {
-            VkHolder::Some(_) => {
-                logger::various_log!("defered wrapper",
-                    (logger::Error, "{}", NON_DESTROYED)
-                );
-                panic!("{}", NON_DESTROYED);
+            VkHolder::Some(ref object) => {
+                let type_name = std::any::type_name::<T>();
+                let leaked = DestructorInfo{
+                    name: super::object_name(type_name.rsplit("::").next().unwrap_or(type_name)),
+                    type_name,
+                    location: object.created_at(),
+                };
+                std::mem::forget(self.0.take());
+                super::leak("defered wrapper", &[leaked], NON_DESTROYED);
             }
             _ => {}
         }
@@ -7,12 +7,27 @@ mod d_wrapper;
 mod d_stack;
 pub use d_stack::DestructionStack;
 
+mod registry;
+pub use registry::ResourceRegistry;
+pub use registry::Registered;
+pub use registry::Handle;
+pub use registry::Pool;
 
+
+use crate::logger;
+use crate::constants;
 use crate::errors::messages::BAD_DESTRUCTOR;
 
 use super::Device;
 use super::Allocator;
 
+use std::fmt;
+use std::panic::Location;
+use std::sync::OnceLock;
+
+use arrayvec::ArrayString;
+use serde::Deserialize;
+
 
 
 pub enum VkDestructorArguments<'a> {
@@ -31,13 +46,114 @@ pub enum VkDestructorType {
 
 pub trait VkDestructor {
     fn destruct(self, args:VkDestructorArguments);
+    
+    // Where the object was created, for the objects that capture it.
+    fn created_at(&self) -> Option<&'static Location<'static>> {
+        None
+    }
 }
 
 pub trait VkDeferedDestructor:VkDestructor {
-    fn defered_destruct(&mut self) -> (Box<dyn FnOnce(VkDestructorArguments)>, VkDestructorType);
+    fn defered_destruct(&mut self) -> VkDynamicDestructor;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeakPolicy {
+    Panic,
+    LogAndLeak,
+}
+
+// Leaks are found in drops, which can't be handed the config.
+static LEAK_POLICY:OnceLock<LeakPolicy> = OnceLock::new();
+
+pub type ObjectName = ArrayString<64>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DestructorInfo {
+    pub name: ObjectName,
+    pub type_name: &'static str,
+    pub location: Option<&'static Location<'static>>,
+}
+
+pub struct VkDynamicDestructor {
+    pub callback: Box<dyn FnOnce(VkDestructorArguments)>,
+    pub d_type: VkDestructorType,
+    pub info: DestructorInfo,
+}
+
+impl VkDynamicDestructor {
+    
+//----
+    pub fn new<T>(
+        name: ObjectName,
+        d_type: VkDestructorType,
+        callback: Box<dyn FnOnce(VkDestructorArguments)>,
+    ) -> Self {
+        Self{
+            callback,
+            d_type,
+            info: DestructorInfo{
+                name,
+                type_name: std::any::type_name::<T>(),
+                location: None,
+            },
+        }
+    }
+    
+//----
+    pub fn created_at(mut self, location:Option<&'static Location<'static>>) -> Self {
+        self.info.location = location;
+        self
+    }
+}
+
+impl fmt::Display for DestructorInfo {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ({})", self.name.as_str(), self.type_name)?;
+        match self.location {
+            Some(location) => write!(f, " created at {}:{}", location.file(), location.line()),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn object_name(name:&str) -> ObjectName {
+    let mut holder = ObjectName::new();
+    for character in name.chars() {
+        if holder.try_push(character).is_err() {
+            break;
+        }
+    }
+    holder
+}
+
+// Only the first call counts, later ones are ignored with a warning.
+pub fn set_leak_policy(policy:LeakPolicy) {
+    if LEAK_POLICY.set(policy).is_err() {
+        logger::various_log!("leak",
+            (logger::Warn, "leak policy already set to {:?}, ignoring {:?}", LEAK_POLICY.get(), policy)
+        );
+    }
 }
 
-pub type VkDynamicDestructor = (Box<dyn FnOnce(VkDestructorArguments)>, VkDestructorType);
+// Decides what to do with objects that reach a drop without being destroyed.
+pub fn leak(target:&str, leaked:&[DestructorInfo], message:&str) {
+    logger::various_log!(target,
+        (logger::Error, "LEAK REPORT: {} objects not destroyed", leaked.len())
+    );
+    for info in leaked.iter() {
+        logger::various_log!(target,
+            (logger::Error, "\tleaked {}", info)
+        );
+    }
+    match LEAK_POLICY.get().copied().unwrap_or(constants::DEFAULT_LEAK_POLICY) {
+        LeakPolicy::Panic => {
+            panic!("{}", message);
+        }
+        LeakPolicy::LogAndLeak => {}
+    }
+}
 
 impl VkDestructorArguments<'_> {
     
@@ -0,0 +1,308 @@
+use crate::logger;
+
+use super::VkDestructor;
+use super::VkDestructorType;
+use super::VkDynamicDestructor;
+use super::VkDestructorArguments;
+use super::DestructionStack;
+use super::ObjectName;
+use super::object_name;
+
+use super::super::Device;
+use super::super::Allocator;
+use super::super::Image;
+use super::super::Buffer;
+use super::super::Sampler;
+use super::super::VkMeshAsset;
+use super::super::MaterialInstance;
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// Objects that can live inside a registry pool and be destroyed once no frame uses them.
+pub trait Registered: VkDestructor + 'static {
+    const DESTRUCTOR_TYPE: VkDestructorType;
+}
+
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+struct Entry<T> {
+    value: T,
+    name: ObjectName,
+    references: u32,
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Option<Entry<T>>,
+}
+
+pub struct Pool<T:Registered> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+#[derive(Default)]
+pub struct ResourceRegistry {
+    pub meshes: Pool<VkMeshAsset>,
+    pub images: Pool<Image>,
+    pub buffers: Pool<Buffer>,
+    pub samplers: Pool<Sampler>,
+    pub materials: Pool<MaterialInstance>,
+    retired: VecDeque<(u64, VkDynamicDestructor)>,
+}
+
+impl<T:Registered> Pool<T> {
+    
+//----
+    pub fn insert(&mut self, name:&str, value:T) -> Handle<T> {
+        let entry = Entry{
+            value,
+            name: object_name(name),
+            references: 1,
+        };
+        
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].entry = Some(entry);
+                index
+            }
+            None => {
+                self.slots.push(Slot{
+                    generation: 0,
+                    entry: Some(entry),
+                });
+                u32::try_from(self.slots.len()-1).expect("registry pools should not hold more than u32::MAX objects")
+            }
+        };
+        
+        Handle{
+            index,
+            generation: self.slots[index as usize].generation,
+            _marker: PhantomData,
+        }
+    }
+    
+//----
+    pub fn get(&self, handle:Handle<T>) -> Option<&T> {
+        self.entry(handle).map(|entry|&entry.value)
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn get_mut(&mut self, handle:Handle<T>) -> Option<&mut T> {
+        self.entry_mut(handle).map(|entry|&mut entry.value)
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn name(&self, handle:Handle<T>) -> Option<&str> {
+        self.entry(handle).map(|entry|entry.name.as_str())
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn contains(&self, handle:Handle<T>) -> bool {
+        self.entry(handle).is_some()
+    }
+    
+//----
+    pub fn acquire(&mut self, handle:Handle<T>) -> Option<Handle<T>> {
+        let entry = self.entry_mut(handle)?;
+        entry.references += 1;
+        Some(handle)
+    }
+    
+//----
+    // Drops one reference, the object is handed back once nobody references it anymore.
+    pub fn release(&mut self, handle:Handle<T>) -> Option<(ObjectName, T)> {
+        let entry = self.entry_mut(handle)?;
+        entry.references -= 1;
+        if entry.references != 0 {
+            return None;
+        }
+        
+        let slot = &mut self.slots[handle.index as usize];
+        let Entry{value, name, ..} = slot.entry.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        Some((name, value))
+    }
+    
+//----
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+    
+//----
+    fn drain(&mut self) -> Vec<(ObjectName, T)> {
+        self.free.clear();
+        self.slots.drain(..)
+            .filter_map(|slot|slot.entry)
+            .map(|entry|(entry.name, entry.value))
+            .collect()
+    }
+    
+//----
+    fn entry(&self, handle:Handle<T>) -> Option<&Entry<T>> {
+        let slot = self.slots.get(handle.index as usize)?;
+        match slot.generation == handle.generation {
+            true => slot.entry.as_ref(),
+            false => None,
+        }
+    }
+    
+//----
+    fn entry_mut(&mut self, handle:Handle<T>) -> Option<&mut Entry<T>> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        match slot.generation == handle.generation {
+            true => slot.entry.as_mut(),
+            false => None,
+        }
+    }
+}
+
+impl ResourceRegistry {
+    
+//----
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+//----
+    // Releases a reference; the last one schedules the destruction once the timeline reaches the given value and returns true.
+    pub fn release<T:Registered>(&mut self, pool:fn(&mut Self)->&mut Pool<T>, handle:Handle<T>, value:u64) -> bool {
+        let Some((name, object)) = pool(self).release(handle) else {
+            return false;
+        };
+        logger::various_log!("registry",
+            (logger::Debug, "retiring {:?} until timeline {}", name.as_str(), value)
+        );
+        let destructor = Self::destructor(name, object);
+        self.retired.push_back((value, destructor));
+        true
+    }
+    
+//----
+    // A retired mesh gives back the materials its surfaces held.
+    pub fn release_mesh(&mut self, handle:Handle<VkMeshAsset>, value:u64) {
+        let materials:Vec<Handle<MaterialInstance>> = self.meshes.get(handle)
+            .map(|mesh|mesh.surfaces.iter().filter_map(|surface|surface.material).collect())
+            .unwrap_or_default();
+        if self.release(|registry|&mut registry.meshes, handle, value) {
+            for material in materials {
+                self.release_material(material, value);
+            }
+        }
+    }
+    
+//----
+    // A retired material gives back its constants buffer.
+    pub fn release_material(&mut self, handle:Handle<MaterialInstance>, value:u64) {
+        let buffer = self.materials.get(handle).map(|material|material.buffer);
+        if self.release(|registry|&mut registry.materials, handle, value) {
+            if let Some(buffer) = buffer {
+                self.release(|registry|&mut registry.buffers, buffer, value);
+            }
+        }
+    }
+    
+//----
+    // Destroys the retired objects whose last use the timeline already went past.
+    pub fn collect(&mut self, device:&mut Device, allocator:&mut Allocator, completed:u64) {
+        let ready = self.retired.iter()
+            .take_while(|(value, _)|*value <= completed)
+            .count();
+        if ready == 0 {
+            return;
+        }
+        
+        let mut stack = DestructionStack::new();
+        for (_, destructor) in self.retired.drain(..ready) {
+            stack.push(destructor);
+        }
+        stack.dispatch(device, allocator);
+    }
+    
+//----
+    fn destructor<T:Registered>(name:ObjectName, value:T) -> VkDynamicDestructor {
+        let location = value.created_at();
+        let callback = Box::new(move |args:VkDestructorArguments|{
+            value.destruct(args);
+        });
+        VkDynamicDestructor::new::<T>(name, T::DESTRUCTOR_TYPE, callback).created_at(location)
+    }
+    
+//----
+    fn drain_pool<T:Registered>(pool:&mut Pool<T>, stack:&mut DestructionStack) {
+        for (name, value) in pool.drain() {
+            stack.push(Self::destructor(name, value));
+        }
+    }
+}
+
+
+impl VkDestructor for ResourceRegistry {
+    fn destruct(mut self, mut args:VkDestructorArguments) {
+        logger::destruct!("registry");
+        let (device, allocator) = args.unwrap_dev_all();
+        
+        let mut stack = DestructionStack::new();
+        for (_, destructor) in self.retired.drain(..) {
+            stack.push(destructor);
+        }
+        Self::drain_pool(&mut self.materials, &mut stack);
+        Self::drain_pool(&mut self.meshes, &mut stack);
+        Self::drain_pool(&mut self.buffers, &mut stack);
+        Self::drain_pool(&mut self.images, &mut stack);
+        Self::drain_pool(&mut self.samplers, &mut stack);
+        stack.dispatch(device, allocator);
+    }
+}
+
+
+impl<T:Registered> Default for Pool<T> {
+    fn default() -> Self {
+        Self{
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other:&Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H:Hasher>(&self, state:&mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle<{}>({}v{})", std::any::type_name::<T>().rsplit("::").next().unwrap_or(""), self.index, self.generation)
+    }
+}
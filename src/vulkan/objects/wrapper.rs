@@ -6,18 +6,21 @@ use crate::errors::messages::NON_EMPTY_WRAPPER;
 
 use super::VkDestructor;
 use super::VkDestructorArguments;
+use super::DestructorInfo;
 
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::panic::Location;
 
 
-pub struct VkWrapper<T:VkDestructor>(Option<T>);
+pub struct VkWrapper<T:VkDestructor>(Option<T>, &'static Location<'static>);
 
 impl<T:VkDestructor> VkWrapper<T> {
     
 //----
+    #[track_caller]
     pub fn new(new:T) -> Self {
-        Self(Some(new))
+        Self(Some(new), Location::caller())
     }
     
 //----
@@ -51,10 +54,14 @@ impl<T:VkDestructor> Drop for VkWrapper<T> {
     fn drop(&mut self) {
         match self.0.as_mut() {
             Some(_) => {
-                logger::various_log!("wrapper",
-                    (logger::Error, "{}", NON_DESTROYED)
-                );
-                panic!("{}", NON_DESTROYED);
+                let type_name = std::any::type_name::<T>();
+                let leaked = DestructorInfo{
+                    name: super::object_name(type_name.rsplit("::").next().unwrap_or(type_name)),
+                    type_name,
+                    location: Some(self.1),
+                };
+                std::mem::forget(self.0.take());
+                super::leak("wrapper", &[leaked], NON_DESTROYED);
             }
             None => {}
         }
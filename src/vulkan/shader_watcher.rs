@@ -0,0 +1,144 @@
+use crate::logger;
+use crate::constants::hot_reload;
+
+use super::init::shader_compiler;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub file: PathBuf,
+    pub message: String,
+}
+
+// Polls the shader sources and a few data files, an edited include marks every source that pulls it in.
+pub struct ShaderWatcher {
+    source_dir: PathBuf,
+    files: Vec<PathBuf>,
+    stamps: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+    errors: Vec<ShaderError>,
+}
+
+impl ShaderWatcher {
+    
+//----
+    pub fn new<P:AsRef<Path>>(source_dir:P, files:&[P]) -> Self {
+        let mut holder = Self{
+            source_dir: source_dir.as_ref().to_path_buf(),
+            files: files.iter().map(|file|file.as_ref().to_path_buf()).collect(),
+            stamps: HashMap::new(),
+            last_poll: Instant::now(),
+            errors: Vec::new(),
+        };
+        let source_dir = holder.source_dir.clone();
+        holder.changed_in(&source_dir);
+        holder.changed_files();
+        holder
+    }
+    
+//----
+    // Watched files and shader sources whose own text or includes changed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < hot_reload::POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        
+        let mut holder = self.changed_files();
+        let source_dir = self.source_dir.clone();
+        let changed = self.changed_in(&source_dir);
+        if changed.is_empty() {
+            return holder;
+        }
+        
+        for source in self.sources() {
+            match shader_compiler::dependencies(&source) {
+                Ok(files) => {
+                    if files.iter().any(|file|changed.contains(file)) {
+                        holder.push(source);
+                    }
+                }
+                // Rebuilding reports the same error against the source.
+                Err(_) => {
+                    if changed.contains(&source) {
+                        holder.push(source);
+                    }
+                }
+            }
+        }
+        holder
+    }
+    
+//----
+    pub fn errors(&self) -> &[ShaderError] {
+        &self.errors
+    }
+    
+//----
+    pub fn report_error(&mut self, file:&Path, message:String) {
+        logger::various_log!("shader_watcher",
+            (logger::Error, "{:?}: {}", file, message)
+        );
+        self.clear_error(file);
+        self.errors.push(ShaderError{
+            file: file.to_path_buf(),
+            message,
+        });
+    }
+    
+//----
+    pub fn clear_error(&mut self, file:&Path) {
+        self.errors.retain(|error|error.file != file);
+    }
+    
+//----
+    // Every stage in the source directory, the .glsl files are only included.
+    fn sources(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.source_dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry|entry.ok().map(|entry|entry.path()))
+            .filter(|path|path.extension().is_some_and(|extension|extension != "glsl"))
+            .collect()
+    }
+    
+//----
+    fn changed_files(&mut self) -> Vec<PathBuf> {
+        let mut holder = Vec::new();
+        for file in self.files.iter() {
+            let Ok(modified) = fs::metadata(file).and_then(|metadata|metadata.modified()) else {
+                continue;
+            };
+            if self.stamps.insert(file.clone(), modified) != Some(modified) {
+                holder.push(file.clone());
+            }
+        }
+        holder
+    }
+    
+//----
+    fn changed_in(&mut self, dir:&Path) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        
+        let mut holder = Vec::new();
+        for entry in entries.filter_map(|entry|entry.ok()) {
+            let Ok(modified) = entry.metadata().and_then(|metadata|metadata.modified()) else {
+                continue;
+            };
+            let path = entry.path();
+            if self.stamps.insert(path.clone(), modified) != Some(modified) {
+                holder.push(path);
+            }
+        }
+        holder
+    }
+}